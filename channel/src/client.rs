@@ -0,0 +1,158 @@
+use crate::{ENDPOINT_VAR, Message, handshake};
+use std::{io::{self, BufReader, Read, Write}, sync::{Mutex, atomic::{AtomicBool, Ordering}}};
+
+/// The payload's end of the channel. Logging and replying can happen from any thread while another
+/// waits for commands.
+pub struct Client {
+  reader: Mutex<BufReader<Box<dyn Read + Send>>>,
+  writer: Mutex<Box<dyn Write + Send>>,
+  closed: AtomicBool,
+  /// Kenjector's process ID, from its hello.
+  pub server_process_id: u32,
+}
+
+impl Client {
+  /// Connect to `endpoint` and exchange hellos, failing when Kenjector speaks another protocol version.
+  pub fn connect(endpoint: &str) -> io::Result<Self> {
+    let (reader, writer) = open(endpoint)?;
+    let mut reader = BufReader::new(reader);
+    let mut writer = writer;
+    let server_process_id = handshake(&mut reader, &mut writer)?;
+    Ok(Self {
+      reader: Mutex::new(reader),
+      writer: Mutex::new(writer),
+      closed: AtomicBool::new(false),
+      server_process_id,
+    })
+  }
+
+  /// Connect to the endpoint in [`ENDPOINT_VAR`].
+  pub fn from_env() -> io::Result<Self> {
+    let endpoint = std::env::var(ENDPOINT_VAR).map_err(|_| io::Error::new(io::ErrorKind::NotFound, format!("{} is not set", ENDPOINT_VAR)))?;
+    Self::connect(&endpoint)
+  }
+
+  pub fn send(&self, message: &Message) -> io::Result<()> { message.write_to(&mut *self.writer.lock().unwrap_or_else(|e| e.into_inner())) }
+
+  /// Log a line to Kenjector's console.
+  pub fn log(&self, line: impl Into<String>) -> io::Result<()> { self.send(&Message::Log(line.into())) }
+
+  /// Answer the last command.
+  pub fn reply(&self, text: impl Into<String>) -> io::Result<()> { self.send(&Message::Reply(text.into())) }
+
+  /// Say bye to Kenjector, which closes its end in answer, so a thread waiting in
+  /// [`Self::next_command`] gets `None`. Only the first call sends anything.
+  pub fn close(&self) -> io::Result<()> { if self.closed.swap(true, Ordering::Relaxed) { Ok(()) } else { self.send(&Message::Bye) } }
+
+  /// Wait for the next command, `None` once Kenjector closes the channel.
+  pub fn next_command(&self) -> io::Result<Option<String>> {
+    let mut reader = self.reader.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+      match Message::read_from(&mut *reader) {
+        Ok(Message::Command(command)) => return Ok(Some(command)),
+        Ok(Message::Bye) => return Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof || e.kind() == io::ErrorKind::BrokenPipe => return Ok(None),
+        Err(e) => return Err(e),
+        Ok(_) => {}
+      }
+    }
+  }
+}
+
+impl Drop for Client {
+  fn drop(&mut self) { let _ = self.close(); }
+}
+
+type Halves = (Box<dyn Read + Send>, Box<dyn Write + Send>);
+
+#[cfg(target_os = "windows")]
+fn open(endpoint: &str) -> io::Result<Halves> {
+  let (up, down) = crate::pipe_names(endpoint);
+  let writer = std::fs::OpenOptions::new().write(true).open(up)?;
+  let reader = std::fs::OpenOptions::new().read(true).open(down)?;
+  Ok((Box::new(reader), Box::new(writer)))
+}
+
+#[cfg(unix)]
+fn open(endpoint: &str) -> io::Result<Halves> {
+  let stream = std::os::unix::net::UnixStream::connect(endpoint)?;
+  Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use std::{os::unix::net::{UnixListener, UnixStream}, path::PathBuf, thread::JoinHandle};
+
+  /// A listener at a fresh socket path, standing in for Kenjector. `serve` gets the accepted stream
+  /// after the handshake.
+  fn serve(name: &str, serve: impl FnOnce(UnixStream) + Send + 'static) -> (PathBuf, JoinHandle<()>) {
+    let path = std::env::temp_dir().join(format!("kenjector-client-test-{}-{}.sock", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let server = std::thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      handshake(&mut stream.try_clone().unwrap(), &mut stream).unwrap();
+      serve(stream);
+    });
+    (path, server)
+  }
+
+  #[test]
+  fn commands_until_bye() {
+    let (path, server) = serve("bye", |mut stream| {
+      Message::Command(String::from("ping")).write_to(&mut stream).unwrap();
+      assert_eq!(Message::read_from(&mut stream).unwrap(), Message::Reply(String::from("pong")));
+      // Messages other than commands are skipped
+      Message::Log(String::from("not for the payload")).write_to(&mut stream).unwrap();
+      Message::Command(String::from("quit")).write_to(&mut stream).unwrap();
+      Message::Bye.write_to(&mut stream).unwrap();
+      assert_eq!(Message::read_from(&mut stream).unwrap(), Message::Bye);
+      // Only one bye, then the payload's end closes
+      assert_eq!(Message::read_from(&mut stream).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    });
+
+    let client = Client::connect(path.to_str().unwrap()).unwrap();
+    assert_eq!(client.server_process_id, std::process::id());
+    assert_eq!(client.next_command().unwrap().as_deref(), Some("ping"));
+    client.reply("pong").unwrap();
+    assert_eq!(client.next_command().unwrap().as_deref(), Some("quit"));
+    assert_eq!(client.next_command().unwrap(), None);
+    client.close().unwrap();
+    client.close().unwrap();
+    drop(client);
+    server.join().unwrap();
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn eof_ends_the_commands() {
+    let (path, server) = serve("eof", |mut stream| {
+      Message::Command(String::from("only")).write_to(&mut stream).unwrap();
+    });
+
+    let client = Client::connect(path.to_str().unwrap()).unwrap();
+    assert_eq!(client.next_command().unwrap().as_deref(), Some("only"));
+    server.join().unwrap();
+    assert_eq!(client.next_command().unwrap(), None);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn garbage_is_an_error() {
+    let (path, server) = serve("garbage", |mut stream| {
+      stream.write_all(&[2, 0, 0, 0, 0xEE, 0]).unwrap();
+    });
+
+    let client = Client::connect(path.to_str().unwrap()).unwrap();
+    server.join().unwrap();
+    assert_eq!(client.next_command().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn missing_endpoint() {
+    let path = std::env::temp_dir().join(format!("kenjector-client-test-{}-missing.sock", std::process::id()));
+    assert_eq!(Client::connect(path.to_str().unwrap()).err().unwrap().kind(), io::ErrorKind::NotFound);
+  }
+}
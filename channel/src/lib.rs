@@ -0,0 +1,52 @@
+//! The channel between Kenjector and a payload it Kenjected: a named pipe pair on Windows, a Unix domain
+//! socket on Linux, carrying length-prefixed, versioned [`Message`]s. Kenjector listens, the payload
+//! connects with a [`Client`] to the endpoint it is handed.
+//!
+//! The endpoint reaches the payload in a config string passed to its [`INIT_EXPORT`] export, which
+//! Kenjector calls once the payload is loaded, or in the [`ENDPOINT_VAR`] environment variable of a
+//! process started with it set.
+
+mod client;
+mod protocol;
+
+pub use client::Client;
+pub use protocol::{MAX_FRAME_LEN, Message, PROTOCOL_VERSION, handshake};
+
+/// The environment variable holding the endpoint, for processes started with it.
+pub const ENDPOINT_VAR: &str = "KENJECTOR_CHANNEL";
+
+/// The export Kenjector calls after a Kenjection, as `extern "system" fn(config: *const c_char) -> u32`
+/// on a thread of its own. The config is a NUL-terminated [`init_config`] string, 0 means success.
+pub const INIT_EXPORT: &str = "kenjector_init";
+
+/// The export Kenjector calls before ejecting a payload, as `extern "system" fn(*mut c_void) -> u32` on a
+/// thread of its own. The payload stops its threads and closes the channel, 0 means it can be freed.
+pub const UNLOAD_EXPORT: &str = "kenjector_unload";
+
+/// The key of the endpoint in the init config.
+const CHANNEL_KEY: &str = "channel";
+
+/// A fresh endpoint for a Kenjection into `process_id`, `id` tells apart the endpoints one Kenjector
+/// opens. A pipe name on Windows, a socket path in the runtime directory on Linux.
+pub fn endpoint_name(process_id: u32, id: u32) -> String {
+  let name = format!("kenjector-{}-{}-{}", process_id, std::process::id(), id);
+  if cfg!(target_os = "windows") {
+    format!(r"\\.\pipe\{}", name)
+  } else {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR").map(std::path::PathBuf::from).unwrap_or_else(std::env::temp_dir);
+    dir.join(format!("{}.sock", name)).to_string_lossy().into_owned()
+  }
+}
+
+/// The two pipes behind a Windows endpoint, payload to Kenjector and back. One handle per direction, as
+/// a pipe opened for synchronous I/O holds up writes while a read is waiting.
+pub fn pipe_names(endpoint: &str) -> (String, String) { (format!("{}-up", endpoint), format!("{}-down", endpoint)) }
+
+/// The config string passed to [`INIT_EXPORT`], `key=value` lines.
+pub fn init_config(endpoint: &str) -> String { format!("{}={}\n", CHANNEL_KEY, endpoint) }
+
+/// The value of `key` in an init config, `None` when it isn't there.
+pub fn config_value<'a>(config: &'a str, key: &str) -> Option<&'a str> { config.lines().find_map(|line| line.split_once('=').filter(|(k, _)| k.trim() == key).map(|(_, value)| value.trim())) }
+
+/// The endpoint in an init config.
+pub fn config_endpoint(config: &str) -> Option<&str> { config_value(config, CHANNEL_KEY) }
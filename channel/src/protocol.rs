@@ -0,0 +1,196 @@
+use std::io::{self, Read, Write};
+
+/// Bumped whenever the framing or a message changes, both ends have to speak the same one.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Frames longer than this are refused, a length that large means the stream is out of step.
+pub const MAX_FRAME_LEN: usize = 1 << 20;
+
+/// One message on the channel.
+///
+/// A frame is the length of the rest as a little-endian `u32`, a kind byte, then the body. Text is
+/// UTF-8. Each end sends [`Message::Hello`] first and checks the version of the other's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+  /// The protocol version and the sender's process ID.
+  Hello { version: u16, process_id: u32 },
+  /// A line the payload logs, shown in Kenjector's console.
+  Log(String),
+  /// A command typed into Kenjector's console.
+  Command(String),
+  /// The payload's answer to a command.
+  Reply(String),
+  /// The sender is closing the channel.
+  Bye,
+}
+
+const HELLO: u8 = 0;
+const LOG: u8 = 1;
+const COMMAND: u8 = 2;
+const REPLY: u8 = 3;
+const BYE: u8 = 4;
+
+impl Message {
+  pub fn hello() -> Self { Self::Hello { version: PROTOCOL_VERSION, process_id: std::process::id() } }
+
+  /// Write the message as one frame and flush it.
+  pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+    let (kind, body) = match self {
+      Self::Hello { version, process_id } => (HELLO, [version.to_le_bytes().as_slice(), process_id.to_le_bytes().as_slice()].concat()),
+      Self::Log(text) => (LOG, text.as_bytes().to_vec()),
+      Self::Command(text) => (COMMAND, text.as_bytes().to_vec()),
+      Self::Reply(text) => (REPLY, text.as_bytes().to_vec()),
+      Self::Bye => (BYE, Vec::new()),
+    };
+    if body.len() + 1 > MAX_FRAME_LEN {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("A {} byte message is over the {} byte frame limit", body.len(), MAX_FRAME_LEN)));
+    }
+
+    let mut frame = Vec::with_capacity(5 + body.len());
+    frame.extend_from_slice(&(body.len() as u32 + 1).to_le_bytes());
+    frame.push(kind);
+    frame.extend_from_slice(&body);
+    writer.write_all(&frame)?;
+    writer.flush()
+  }
+
+  /// Read one frame. A stream closed between frames is `UnexpectedEof`.
+  pub fn read_from(reader: &mut impl Read) -> io::Result<Self> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len == 0 || len > MAX_FRAME_LEN {
+      return Err(invalid(format!("Frame length {} is out of range", len)));
+    }
+
+    let mut frame = vec![0u8; len];
+    reader.read_exact(&mut frame)?;
+    let (kind, body) = (frame[0], &frame[1..]);
+    let text = || String::from_utf8(body.to_vec()).map_err(|_| invalid(String::from("Message text is not UTF-8")));
+    match kind {
+      HELLO if body.len() == 6 => Ok(Self::Hello {
+        version: u16::from_le_bytes([body[0], body[1]]),
+        process_id: u32::from_le_bytes([body[2], body[3], body[4], body[5]]),
+      }),
+      LOG => Ok(Self::Log(text()?)),
+      COMMAND => Ok(Self::Command(text()?)),
+      REPLY => Ok(Self::Reply(text()?)),
+      BYE if body.is_empty() => Ok(Self::Bye),
+      kind => Err(invalid(format!("Unknown message kind {} with {} bytes", kind, body.len()))),
+    }
+  }
+}
+
+fn invalid(message: String) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, message) }
+
+/// Send our hello and check the one that comes back. Returns the other end's process ID.
+pub fn handshake(reader: &mut impl Read, writer: &mut impl Write) -> io::Result<u32> {
+  Message::hello().write_to(writer)?;
+  match Message::read_from(reader)? {
+    Message::Hello { version: PROTOCOL_VERSION, process_id } => Ok(process_id),
+    Message::Hello { version, .. } => {
+      let _ = Message::Bye.write_to(writer);
+      Err(invalid(format!("The other end speaks protocol version {}, this one {}", version, PROTOCOL_VERSION)))
+    }
+    message => Err(invalid(format!("Expected a hello, got {:?}", message))),
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use std::os::unix::net::UnixStream;
+
+  fn round_trip(message: &Message) -> Message {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    message.write_to(&mut a).unwrap();
+    Message::read_from(&mut b).unwrap()
+  }
+
+  #[test]
+  fn every_kind_round_trips() {
+    for message in [Message::hello(), Message::Hello { version: u16::MAX, process_id: u32::MAX }, Message::Log(String::from("loaded at 0x7FF612340000")), Message::Log(String::new()), Message::Command(String::from("dump players")), Message::Reply(String::from("Ken — 100 HP\nRyu — 0 HP")), Message::Bye] {
+      assert_eq!(round_trip(&message), message);
+    }
+  }
+
+  #[test]
+  fn frames_follow_each_other() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    let mut sent = Vec::new();
+    Message::Log(String::from("hi")).write_to(&mut sent).unwrap();
+    assert_eq!(sent, [3, 0, 0, 0, LOG, b'h', b'i']);
+
+    Message::Command(String::from("one")).write_to(&mut a).unwrap();
+    Message::Bye.write_to(&mut a).unwrap();
+    assert_eq!(Message::read_from(&mut b).unwrap(), Message::Command(String::from("one")));
+    assert_eq!(Message::read_from(&mut b).unwrap(), Message::Bye);
+  }
+
+  #[test]
+  fn oversized_frames_are_refused() {
+    let mut sent = Vec::new();
+    let error = Message::Log("x".repeat(MAX_FRAME_LEN)).write_to(&mut sent).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(sent.is_empty());
+    // One byte under, the kind byte makes it exactly the limit
+    Message::Log("x".repeat(MAX_FRAME_LEN - 1)).write_to(&mut sent).unwrap();
+    assert_eq!(Message::read_from(&mut sent.as_slice()).unwrap(), Message::Log("x".repeat(MAX_FRAME_LEN - 1)));
+
+    for len in [0, MAX_FRAME_LEN as u32 + 1, u32::MAX] {
+      let error = Message::read_from(&mut len.to_le_bytes().as_slice()).unwrap_err();
+      assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+      assert!(error.to_string().contains("out of range"), "{}", error);
+    }
+  }
+
+  #[test]
+  fn malformed_frames_are_invalid_data() {
+    for frame in [
+      &[3, 0, 0, 0, 9, 1, 2][..],
+      // A hello with a short body and a bye with one
+      &[3, 0, 0, 0, HELLO, 1, 0],
+      &[2, 0, 0, 0, BYE, 0],
+      &[3, 0, 0, 0, REPLY, 0xC3, 0x28],
+    ] {
+      assert_eq!(Message::read_from(&mut &frame[..]).unwrap_err().kind(), io::ErrorKind::InvalidData, "{:?}", frame);
+    }
+  }
+
+  #[test]
+  fn closed_streams_are_eof() {
+    let (a, mut b) = UnixStream::pair().unwrap();
+    drop(a);
+    assert_eq!(Message::read_from(&mut b).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    // Closed in the middle of a frame
+    assert_eq!(Message::read_from(&mut [5, 0, 0, 0, LOG, b'a'].as_slice()).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+  }
+
+  #[test]
+  fn handshake_returns_the_other_process_id() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    let other = std::thread::spawn(move || handshake(&mut b.try_clone().unwrap(), &mut b));
+    assert_eq!(handshake(&mut a.try_clone().unwrap(), &mut a).unwrap(), std::process::id());
+    assert_eq!(other.join().unwrap().unwrap(), std::process::id());
+  }
+
+  #[test]
+  fn handshake_refuses_another_version() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    Message::Hello { version: PROTOCOL_VERSION + 1, process_id: 42 }.write_to(&mut b).unwrap();
+    let error = handshake(&mut a.try_clone().unwrap(), &mut a).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), format!("The other end speaks protocol version {}, this one {}", PROTOCOL_VERSION + 1, PROTOCOL_VERSION));
+    // Our hello, then a bye so the other end knows why
+    assert_eq!(Message::read_from(&mut b).unwrap(), Message::hello());
+    assert_eq!(Message::read_from(&mut b).unwrap(), Message::Bye);
+  }
+
+  #[test]
+  fn handshake_wants_a_hello_first() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    Message::Log(String::from("early")).write_to(&mut b).unwrap();
+    let error = handshake(&mut a.try_clone().unwrap(), &mut a).unwrap_err();
+    assert!(error.to_string().starts_with("Expected a hello"), "{}", error);
+  }
+}
@@ -0,0 +1,24 @@
+//! A payload answering commands from Kenjector's console: `echo <text>` and `pid`.
+//!
+//! Build it with `cargo build -p kenjector-sdk --example echo`, the DLL, or the `.so` on Linux, lands in
+//! `target/debug/examples`.
+
+use kenjector_sdk::Payload;
+
+kenjector_sdk::payload!(run, unload);
+
+fn run(payload: &Payload) {
+  payload.log(format!("Echo payload loaded into process {}", std::process::id()));
+  while let Some(command) = payload.next_command() {
+    let reply = match command.split_once(' ').unwrap_or((&command, "")) {
+      ("echo", text) => text.to_string(),
+      ("pid", _) => std::process::id().to_string(),
+      (name, _) => format!("Unknown command {}", name),
+    };
+    payload.reply(reply);
+  }
+}
+
+fn unload() {
+  // Undo whatever the payload changed in its host here, while the channel is still open
+}
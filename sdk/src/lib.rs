@@ -0,0 +1,134 @@
+//! What a payload DLL built to be Kenjected needs: an entry point run on a worker thread of its own,
+//! off the loader lock, the init and unload exports Kenjector calls, and the client of Kenjector's
+//! channel.
+//!
+//! A payload is a `cdylib` that names its entry point, and optionally an unload hook, with [`payload!`]:
+//!
+//! ```ignore
+//! kenjector_sdk::payload!(run, unload);
+//!
+//! fn run(payload: &kenjector_sdk::Payload) {
+//!   payload.log("Loaded");
+//!   while let Some(command) = payload.next_command() {
+//!     payload.reply(format!("You said {}", command));
+//!   }
+//! }
+//!
+//! fn unload() {}
+//! ```
+//!
+//! The entry point gets the config Kenjector passed to [`INIT_EXPORT`](kenjector_channel::INIT_EXPORT)
+//! and a connected channel when there was one. It also builds for Linux, where loading the library runs
+//! the entry point and a host calls the exports with `dlsym`.
+
+mod payload;
+#[doc(hidden)]
+pub mod rt;
+
+pub use kenjector_channel::{self as channel, Client};
+pub use payload::Payload;
+
+/// Define the payload's entry points: `DllMain` on Windows, or a constructor and destructor on Linux,
+/// which start `$main` on a worker thread, and the `kenjector_init` and `kenjector_unload` exports.
+///
+/// `$main` is a `fn(&Payload)`, run once Kenjector called the init export, or after a few seconds
+/// without it. `$unload` is a `fn()`, run by the unload export before the channel is closed and `$main`
+/// is waited for. `$main` should return soon after [`Payload::is_unloading`], or the payload stays
+/// loaded.
+#[macro_export]
+macro_rules! payload {
+  ($main:path) => {
+    $crate::payload!($main, || {});
+  };
+  ($main:path, $unload:expr) => {
+    #[cfg(target_os = "windows")]
+    #[unsafe(no_mangle)]
+    pub unsafe extern "system" fn DllMain(module: *mut ::core::ffi::c_void, reason: u32, _reserved: *mut ::core::ffi::c_void) -> i32 { unsafe { $crate::rt::dll_main(module, reason, $main, $unload) } }
+
+    #[cfg(target_os = "linux")]
+    #[used]
+    #[unsafe(link_section = ".init_array")]
+    static __KENJECTOR_ATTACH: extern "C" fn() = {
+      extern "C" fn attach() { $crate::rt::attach($main, $unload) }
+      attach
+    };
+
+    #[cfg(target_os = "linux")]
+    #[used]
+    #[unsafe(link_section = ".fini_array")]
+    static __KENJECTOR_DETACH: extern "C" fn() = {
+      extern "C" fn detach() { $crate::rt::detach() }
+      detach
+    };
+
+    /// Named after `kenjector_channel::INIT_EXPORT`.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "system" fn kenjector_init(config: *const ::core::ffi::c_char) -> u32 { unsafe { $crate::rt::init(config) } }
+
+    /// Named after `kenjector_channel::UNLOAD_EXPORT`.
+    #[unsafe(no_mangle)]
+    pub extern "system" fn kenjector_unload(_parameter: *mut ::core::ffi::c_void) -> u32 { $crate::rt::unload() }
+  };
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+  use kenjector_channel::{Message, handshake, init_config};
+  use std::{ffi::CString, os::unix::net::{UnixListener, UnixStream}, sync::atomic::{AtomicBool, Ordering}, time::{Duration, Instant}};
+
+  static UNLOAD_HOOK_RAN: AtomicBool = AtomicBool::new(false);
+
+  // The test binary stands in for the host, its constructor starts the worker like loading the library would
+  payload!(run, || UNLOAD_HOOK_RAN.store(true, Ordering::Relaxed));
+
+  fn run(payload: &Payload) {
+    payload.log(format!("Channel {}", payload.config_value("channel").unwrap_or_default()));
+    while let Some(command) = payload.next_command() {
+      payload.reply(command.to_uppercase());
+    }
+  }
+
+  /// The payload's connection, failing instead of blocking when it never comes.
+  fn accept(listener: &UnixListener) -> UnixStream {
+    listener.set_nonblocking(true).unwrap();
+    let started = Instant::now();
+    loop {
+      match listener.accept() {
+        Ok((stream, _)) => {
+          stream.set_nonblocking(false).unwrap();
+          stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+          return stream;
+        }
+        Err(_) if started.elapsed() < Duration::from_secs(10) => std::thread::sleep(Duration::from_millis(10)),
+        Err(e) => panic!("the payload did not connect: {}", e),
+      }
+    }
+  }
+
+  #[test]
+  fn init_and_unload_exports() {
+    let endpoint = std::env::temp_dir().join(format!("kenjector-sdk-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&endpoint);
+    let listener = UnixListener::bind(&endpoint).unwrap();
+    let config = CString::new(init_config(endpoint.to_str().unwrap())).unwrap();
+
+    assert_eq!(unsafe { kenjector_init(config.as_ptr()) }, 0);
+    let mut stream = accept(&listener);
+    assert_eq!(handshake(&mut stream.try_clone().unwrap(), &mut stream).unwrap(), std::process::id());
+    assert_eq!(Message::read_from(&mut stream).unwrap(), Message::Log(format!("Channel {}", endpoint.display())));
+    Message::Command(String::from("ping")).write_to(&mut stream).unwrap();
+    assert_eq!(Message::read_from(&mut stream).unwrap(), Message::Reply(String::from("PING")));
+    // The entry point is already running with the first config
+    assert_eq!(unsafe { kenjector_init(config.as_ptr()) }, 1);
+
+    let unload = std::thread::spawn(|| kenjector_unload(std::ptr::null_mut()));
+    // The export closes the payload's end, Kenjector closes its own in answer
+    assert_eq!(Message::read_from(&mut stream).unwrap(), Message::Bye);
+    drop(stream);
+    assert_eq!(unload.join().unwrap(), 0);
+    assert!(UNLOAD_HOOK_RAN.load(Ordering::Relaxed));
+    assert!(rt::is_unloading());
+    std::fs::remove_file(endpoint).unwrap();
+  }
+}
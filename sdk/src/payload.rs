@@ -0,0 +1,72 @@
+use crate::rt;
+use kenjector_channel::{Client, config_endpoint, config_value};
+
+/// What the payload's entry point gets: the init config and the channel to Kenjector.
+pub struct Payload {
+  config: String,
+  client: Option<Client>,
+  channel_error: Option<String>,
+}
+
+impl Payload {
+  /// Connect to the endpoint in `config`, when it names one.
+  pub(crate) fn new(config: String) -> Self {
+    let (client, channel_error) = match config_endpoint(&config) {
+      Some(endpoint) => match Client::connect(endpoint) {
+        Ok(client) => (Some(client), None),
+        Err(e) => (None, Some(format!("Connecting to {} failed, error: {}", endpoint, e))),
+      },
+      None => (None, None),
+    };
+    Self { config, client, channel_error }
+  }
+
+  /// The config passed to the init export, empty when it wasn't called.
+  pub fn config(&self) -> &str { &self.config }
+
+  /// The value of `key` in the config.
+  pub fn config_value(&self, key: &str) -> Option<&str> { config_value(&self.config, key) }
+
+  /// The channel to Kenjector, `None` when the config named none or connecting failed.
+  pub fn client(&self) -> Option<&Client> { self.client.as_ref() }
+
+  /// Why there is no channel although the config named one.
+  pub fn channel_error(&self) -> Option<&str> { self.channel_error.as_deref() }
+
+  /// Log a line to Kenjector's console, or as debug output when there is no channel.
+  pub fn log(&self, line: impl Into<String>) {
+    let line = line.into();
+    match &self.client {
+      Some(client) if client.log(line.as_str()).is_ok() => {}
+      _ => rt::debug_output(&line),
+    }
+  }
+
+  /// Answer the last command, dropped when there is no channel.
+  pub fn reply(&self, text: impl Into<String>) {
+    if let Some(client) = &self.client {
+      let _ = client.reply(text);
+    }
+  }
+
+  /// Wait for the next command from Kenjector's console. `None` once the channel is closed, by
+  /// Kenjector or the unload export, or when there is none.
+  pub fn next_command(&self) -> Option<String> {
+    match self.client.as_ref()?.next_command() {
+      Ok(command) => command,
+      Err(e) => {
+        rt::debug_output(&format!("Reading from the channel failed, error: {}", e));
+        None
+      }
+    }
+  }
+
+  /// Whether the unload export was called, or the module is being unloaded without it.
+  pub fn is_unloading(&self) -> bool { rt::is_unloading() }
+
+  pub(crate) fn close(&self) {
+    if let Some(client) = &self.client {
+      let _ = client.close();
+    }
+  }
+}
@@ -0,0 +1,174 @@
+//! What [`payload!`](crate::payload) expands to calls into, not meant to be used directly.
+
+use crate::Payload;
+use kenjector_channel::{ENDPOINT_VAR, init_config};
+use std::{ffi::{CStr, c_char}, panic::{AssertUnwindSafe, catch_unwind}, sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, atomic::{AtomicBool, Ordering}}, thread::JoinHandle, time::{Duration, Instant}};
+
+/// How long the worker waits for the init export before running the entry point without a config.
+const INIT_WAIT: Duration = Duration::from_secs(5);
+
+/// How long the unload export waits for the entry point to return.
+const UNLOAD_WAIT: Duration = Duration::from_secs(5);
+
+#[cfg(target_os = "windows")]
+const DLL_PROCESS_DETACH: u32 = 0;
+#[cfg(target_os = "windows")]
+const DLL_PROCESS_ATTACH: u32 = 1;
+
+#[cfg(target_os = "windows")]
+#[link(name = "kernel32")]
+unsafe extern "system" {
+  fn DisableThreadLibraryCalls(module: *mut std::ffi::c_void) -> i32;
+  fn OutputDebugStringW(text: *const u16);
+}
+
+struct State {
+  /// Set by the init export, taken by the worker.
+  config: Option<String>,
+  /// The worker started the entry point, a later init comes too late.
+  started: bool,
+  finished: bool,
+}
+
+struct Runtime {
+  state: Mutex<State>,
+  /// Signalled when the config arrives, unloading starts or the worker finishes.
+  changed: Condvar,
+  unloading: AtomicBool,
+  unload_hook: OnceLock<fn()>,
+  payload: Mutex<Option<Arc<Payload>>>,
+  worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+static RUNTIME: Runtime = Runtime {
+  state: Mutex::new(State { config: None, started: false, finished: false }),
+  changed: Condvar::new(),
+  unloading: AtomicBool::new(false),
+  unload_hook: OnceLock::new(),
+  payload: Mutex::new(None),
+  worker: Mutex::new(None),
+};
+
+/// A panic in the entry point or a hook must not leave the locks poisoned for the unload export.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> { mutex.lock().unwrap_or_else(|e| e.into_inner()) }
+
+/// # Safety
+/// Only as the module's `DllMain`, `module` being its handle.
+#[cfg(target_os = "windows")]
+pub unsafe fn dll_main(module: *mut std::ffi::c_void, reason: u32, main: fn(&Payload), unload: fn()) -> i32 {
+  match reason {
+    DLL_PROCESS_ATTACH => {
+      unsafe { DisableThreadLibraryCalls(module) };
+      attach(main, unload);
+    }
+    DLL_PROCESS_DETACH => detach(),
+    _ => {}
+  }
+  1
+}
+
+/// Start the worker. It only runs once the loader lock is released, so the entry point may load
+/// libraries and wait on other threads.
+pub fn attach(main: fn(&Payload), unload: fn()) {
+  let _ = RUNTIME.unload_hook.set(unload);
+  let worker = std::thread::spawn(move || {
+    if let Some(config) = wait_for_config() {
+      let payload = Arc::new(Payload::new(config));
+      *lock(&RUNTIME.payload) = Some(payload.clone());
+      if let Some(e) = payload.channel_error() {
+        debug_output(e);
+      }
+      let _ = catch_unwind(AssertUnwindSafe(|| main(&payload)));
+      payload.close();
+    }
+    lock(&RUNTIME.state).finished = true;
+    RUNTIME.changed.notify_all();
+  });
+  *lock(&RUNTIME.worker) = Some(worker);
+}
+
+/// The config from the init export, or the endpoint in [`ENDPOINT_VAR`] of a process started with it,
+/// or an empty one once [`INIT_WAIT`] passed. `None` when unloading started first.
+fn wait_for_config() -> Option<String> {
+  let from_env = std::env::var(ENDPOINT_VAR).ok().map(|endpoint| init_config(&endpoint));
+  let deadline = Instant::now() + if from_env.is_some() { Duration::ZERO } else { INIT_WAIT };
+  let mut state = lock(&RUNTIME.state);
+  loop {
+    if RUNTIME.unloading.load(Ordering::Relaxed) {
+      return None;
+    }
+    let left = deadline.saturating_duration_since(Instant::now());
+    if let Some(config) = state.config.take().or_else(|| left.is_zero().then(|| from_env.clone().unwrap_or_default())) {
+      state.started = true;
+      return Some(config);
+    }
+    state = RUNTIME.changed.wait_timeout(state, left).unwrap_or_else(|e| e.into_inner()).0;
+  }
+}
+
+/// The init export. Returns 0 when the config reached the worker, 1 when it had started without it.
+///
+/// # Safety
+/// `config` is null or a NUL-terminated string.
+pub unsafe fn init(config: *const c_char) -> u32 {
+  let config = if config.is_null() { String::new() } else { unsafe { CStr::from_ptr(config) }.to_string_lossy().into_owned() };
+  let mut state = lock(&RUNTIME.state);
+  if state.started {
+    return 1;
+  }
+  state.config = Some(config);
+  RUNTIME.changed.notify_all();
+  0
+}
+
+/// The unload export: run the hook, close the channel and wait for the entry point. Returns 0 when
+/// it returned and the module can be freed, 1 when it is still running.
+pub fn unload() -> u32 {
+  {
+    let _state = lock(&RUNTIME.state);
+    RUNTIME.unloading.store(true, Ordering::Relaxed);
+    RUNTIME.changed.notify_all();
+  }
+  if let Some(hook) = RUNTIME.unload_hook.get() {
+    let _ = catch_unwind(*hook);
+  }
+  let payload = lock(&RUNTIME.payload).clone();
+  if let Some(payload) = payload {
+    payload.close();
+  }
+
+  let deadline = Instant::now() + UNLOAD_WAIT;
+  let mut state = lock(&RUNTIME.state);
+  while !state.finished {
+    let left = deadline.saturating_duration_since(Instant::now());
+    if left.is_zero() {
+      return 1;
+    }
+    state = RUNTIME.changed.wait_timeout(state, left).unwrap_or_else(|e| e.into_inner()).0;
+  }
+  drop(state);
+
+  // The worker may still be on its way out of the module's code
+  if let Some(worker) = lock(&RUNTIME.worker).take() {
+    let _ = worker.join();
+  }
+  lock(&RUNTIME.payload).take();
+  0
+}
+
+/// The module is going away without the unload export, at process exit or by a `FreeLibrary` from
+/// elsewhere. Nothing can be waited for under the loader lock, the entry point is only told to stop.
+pub fn detach() { RUNTIME.unloading.store(true, Ordering::Relaxed); }
+
+pub fn is_unloading() -> bool { RUNTIME.unloading.load(Ordering::Relaxed) }
+
+/// Where lines go without a channel: `OutputDebugString` on Windows, stderr on Linux.
+pub fn debug_output(line: &str) {
+  #[cfg(target_os = "windows")]
+  {
+    let wide = format!("{}\n", line).encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+    unsafe { OutputDebugStringW(wide.as_ptr()) };
+  }
+  #[cfg(not(target_os = "windows"))]
+  eprintln!("{}", line);
+}
@@ -0,0 +1,200 @@
+use crate::{logic::{config::Config, filter::{self, Filter}, history::{HistoryLog, InjectionRecord}, kenjector::{Deadline, KenjectionInfo, Kenjector, ProcessInfo}, method::{self, InjectionMethod}}, ui::{export, listview::ListRow}};
+use std::{path::{Path, PathBuf}, time::{Duration, Instant}};
+
+const USAGE: &str = "Usage:
+  Kenjector [--pid PID] [--dll FILE]
+                             Start the GUI, optionally with a process selected
+                             and the DLL path filled in
+  Kenjector list [QUERY...] [--export FILE]
+                             Print the running processes, optionally filtered,
+                             or write them to a .csv or .json file
+  Kenjector history [--export FILE]
+                             Print the injection history log, or export it
+  Kenjector inject (--pid PID | --name NAME) --dll FILE [--method METHOD] [--timeout MS]
+                             Kenject a DLL, with CreateRemoteThread unless
+                             another method is given, waiting at most the
+                             configured timeout for it to load
+  Kenjector methods          List the injection methods and what they need
+
+Query syntax (same as the search bar):
+  word            substring over every field
+  field:value     substring, e.g. name:game
+  field=value     exact match, e.g. admin=no
+  field:/regex/   case-insensitive regex, e.g. name:/^game.*\\.exe$/
+  pid>1000        numeric comparison, also <, <=, >=, !=
+  !term           negate a term
+Fields: admin, name, arch, pid, access";
+
+/// Options the GUI starts with, e.g. to carry the selection over a restart as administrator.
+#[derive(Debug, Clone, Default)]
+pub struct GuiArgs {
+  pub process_id: Option<u32>,
+  pub dll: Option<PathBuf>,
+}
+
+impl GuiArgs {
+  /// Whether `args` start the GUI rather than a command.
+  pub fn is_gui(args: &[String]) -> bool { args.first().is_none_or(|a| a == "--pid" || a == "--dll") }
+
+  pub fn parse(args: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+    let mut gui_args = Self::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+      let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+      match arg.as_str() {
+        "--pid" => gui_args.process_id = Some(filter::parse_number(value()?).ok_or("--pid needs a number")? as u32),
+        "--dll" => gui_args.dll = Some(PathBuf::from(value()?)),
+        other => return Err(format!("Unknown option `{}`\n\n{}", other, USAGE).into()),
+      }
+    }
+    Ok(gui_args)
+  }
+
+  /// The arguments that restore these options, the inverse of [`Self::parse`].
+  pub fn to_args(&self) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(process_id) = self.process_id {
+      args.extend([String::from("--pid"), process_id.to_string()]);
+    }
+    if let Some(dll) = &self.dll {
+      args.extend([String::from("--dll"), dll.display().to_string()]);
+    }
+    args
+  }
+}
+
+/// Run the command given on the command line. `args` excludes the program name.
+pub fn run(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+  attach_console();
+
+  match args[0].as_str() {
+    "list" => list(&args[1..]),
+    "history" => history(&args[1..]),
+    "inject" => inject(&args[1..]),
+    "methods" => {
+      methods();
+      Ok(())
+    }
+    "help" | "-h" | "--help" => {
+      println!("{}", USAGE);
+      Ok(())
+    }
+    other => Err(format!("Unknown command `{}`\n\n{}", other, USAGE).into()),
+  }
+}
+
+fn list(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+  let mut query = Vec::new();
+  let mut export_path = None;
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--export" => export_path = Some(PathBuf::from(args.next().ok_or("--export needs a file path")?)),
+      _ => query.push(arg.as_str()),
+    }
+  }
+
+  let filter = Filter::parse(&query.join(" "), ProcessInfo::FILTER_FIELDS)?;
+  let processes: Vec<ProcessInfo> = Kenjector::get_processes_without_icons().into_iter().filter(|p| filter.matches(p)).collect();
+
+  if let Some(path) = export_path {
+    // The same document the GUI exports, the Kenjection history included
+    let history = HistoryLog::default().load();
+    for written in export::write_snapshot(&path, &processes, &history)? {
+      println!("Wrote {}", written.display());
+    }
+    return Ok(());
+  }
+
+  println!("     PID  Arch     Admin  Access     Name");
+  for p in &processes {
+    println!("{:>8}  {:<7}  {:<5}  {:<9}  {}", p.process_id, p.arch.to_string(), if p.elevated { "Yes" } else { "No" }, p.access.name(), p.name);
+  }
+
+  Ok(())
+}
+
+fn history(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+  let history = HistoryLog::default().load();
+
+  match args {
+    [] => {
+      print!("{}", InjectionRecord::to_csv(&history));
+      Ok(())
+    }
+    [flag, path] if flag == "--export" => {
+      export::write_history(Path::new(path), &history)?;
+      println!("Wrote {}", path);
+      Ok(())
+    }
+    _ => Err(String::from("Usage: Kenjector history [--export FILE]").into()),
+  }
+}
+
+fn inject(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+  let mut process_id = None;
+  let mut name = None;
+  let mut dll = None;
+  let mut method = InjectionMethod::default();
+  let mut timeout = Config::load().injection_timeout();
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+    match arg.as_str() {
+      "--pid" => process_id = Some(filter::parse_number(value()?).ok_or("--pid needs a number")? as u32),
+      "--name" => name = Some(value()?.clone()),
+      "--dll" => dll = Some(PathBuf::from(value()?)),
+      "--method" => {
+        let value = value()?;
+        method = InjectionMethod::from_name(value).ok_or_else(|| format!("Unknown method `{}`, see `Kenjector methods`", value))?;
+      }
+      "--timeout" => timeout = Duration::from_millis(filter::parse_number(value()?).ok_or("--timeout needs a number of milliseconds")?),
+      other => return Err(format!("Unknown option `{}`\n\n{}", other, USAGE).into()),
+    }
+  }
+
+  let dll = dll.ok_or("--dll is required")?;
+  let processes = Kenjector::get_processes_without_icons();
+  let target = match (process_id, &name) {
+    (Some(pid), _) => processes.into_iter().find(|p| p.process_id == pid).ok_or_else(|| format!("No process with PID {}", pid))?,
+    (None, Some(name)) => processes.into_iter().find(|p| p.name.eq_ignore_ascii_case(name)).ok_or_else(|| format!("No process named {}", name))?,
+    (None, None) => return Err("Give the target with --pid or --name".into()),
+  };
+
+  if !Kenjector::is_library(&dll)? {
+    return Err(format!("{} is not a DLL", dll.display()).into());
+  }
+
+  let kenjection_info = KenjectionInfo { name: target.name.clone(), process_id: target.process_id };
+  let started = Instant::now();
+  let result = Kenjector::kennject(&kenjection_info, dll.clone(), method, &Deadline::new(timeout));
+
+  let record = InjectionRecord::new(&target.name, target.process_id, dll, method.to_string(), started, &result);
+  if let Err(e) = HistoryLog::default().append(&record) {
+    eprintln!("Failed to write the history log, error: {}", e);
+  }
+
+  let kenjected = result?;
+  println!("Kenjected into {} with {}, module base {:#X}", kenjection_info, method, kenjected.base);
+  if let Some(warning) = kenjected.warning {
+    eprintln!("Warning: {}", warning);
+  }
+  Ok(())
+}
+
+fn methods() {
+  for info in method::REGISTRY {
+    println!("{}", info.name);
+    println!("  {}", info.description);
+    println!("  Architectures: {}", info.archs.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "));
+    println!("  Needs: {}", info.access_names());
+  }
+}
+
+/// Release builds use the windows subsystem, so borrow the console of the shell we were started from.
+fn attach_console() {
+  #[cfg(all(target_os = "windows", not(debug_assertions)))]
+  unsafe {
+    winapi::um::wincon::AttachConsole(winapi::um::wincon::ATTACH_PARENT_PROCESS);
+  }
+}
@@ -0,0 +1,297 @@
+use kenjector_channel::{Message, endpoint_name, handshake};
+use parking_lot::Mutex;
+use std::{io::{self, BufReader, Read, Write}, sync::{Arc, atomic::{AtomicBool, AtomicU32, Ordering}, mpsc}};
+#[cfg(target_os = "windows")]
+use winapi::{shared::winerror::ERROR_PIPE_CONNECTED, um::{errhandlingapi::GetLastError, handleapi::{CloseHandle, INVALID_HANDLE_VALUE}, namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW}, winbase::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_INBOUND, PIPE_ACCESS_OUTBOUND, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_WAIT}, winnt::HANDLE}};
+
+/// Tells apart the endpoints opened by this Kenjector.
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// What comes over the channel, and what becomes of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelEvent {
+  /// The payload connected from this process.
+  Connected {
+    process_id: u32,
+  },
+  Log(String),
+  Reply(String),
+  /// The channel is done, with the error that ended it unless the payload closed it.
+  Closed(Option<String>),
+}
+
+/// Kenjector's end of the channel to one Kenjected payload. The payload is waited for and read on a
+/// thread of its own, what it sends comes out of [`Self::try_recv`].
+pub struct ChannelServer {
+  /// What the payload connects to, see [`kenjector_channel::endpoint_name`].
+  pub endpoint: String,
+  events: mpsc::Receiver<ChannelEvent>,
+  /// Set once the payload connected.
+  writer: Arc<Mutex<Option<Box<dyn Write + Send>>>>,
+  /// Tells a thread still waiting for the payload that it was woken to stop.
+  closed: Arc<AtomicBool>,
+}
+
+impl ChannelServer {
+  /// Open a fresh endpoint for a Kenjection into `process_id` and wait for the payload to connect.
+  pub fn open(process_id: u32) -> Result<Self, String> {
+    let endpoint = endpoint_name(process_id, NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let listener = Listener::bind(&endpoint)?;
+    let (sender, events) = mpsc::channel();
+    let writer = Arc::new(Mutex::new(None));
+    let closed = Arc::new(AtomicBool::new(false));
+    {
+      let writer = writer.clone();
+      let closed = closed.clone();
+      std::thread::spawn(move || {
+        let result = serve(listener, &sender, &writer, &closed);
+        let _ = sender.send(ChannelEvent::Closed(result.err().map(|e| e.to_string())));
+      });
+    }
+    Ok(Self { endpoint, events, writer, closed })
+  }
+
+  pub fn try_recv(&self) -> Option<ChannelEvent> { self.events.try_recv().ok() }
+
+  pub fn send_command(&self, command: &str) -> Result<(), String> {
+    match self.writer.lock().as_mut() {
+      Some(writer) => Message::Command(command.to_string()).write_to(writer).map_err(|e| format!("Sending the command failed, error: {}", e)),
+      None => Err(String::from("The payload hasn't connected yet")),
+    }
+  }
+}
+
+impl Drop for ChannelServer {
+  fn drop(&mut self) {
+    self.closed.store(true, Ordering::Relaxed);
+    match self.writer.lock().take() {
+      // The payload answers with a bye of its own, which ends the thread
+      Some(mut writer) => {
+        let _ = Message::Bye.write_to(&mut writer);
+      }
+      // Still waiting for the payload, connect in its place so the thread sees it should stop
+      None => Listener::wake(&self.endpoint),
+    }
+    #[cfg(unix)]
+    let _ = std::fs::remove_file(&self.endpoint);
+  }
+}
+
+/// Wait for the payload, exchange hellos, then pass on what it sends until it closes the channel.
+fn serve(listener: Listener, sender: &mpsc::Sender<ChannelEvent>, writer: &Mutex<Option<Box<dyn Write + Send>>>, closed: &AtomicBool) -> io::Result<()> {
+  let (mut reader, mut payload_writer) = listener.accept()?;
+  if closed.load(Ordering::Relaxed) {
+    return Ok(());
+  }
+  let process_id = handshake(&mut reader, &mut payload_writer)?;
+  *writer.lock() = Some(payload_writer);
+  let _ = sender.send(ChannelEvent::Connected { process_id });
+
+  loop {
+    let event = match Message::read_from(&mut reader) {
+      Ok(Message::Log(line)) => ChannelEvent::Log(line),
+      Ok(Message::Reply(text)) => ChannelEvent::Reply(text),
+      Ok(Message::Bye) => {
+        // Closing our end in answer ends the payload's wait for commands
+        writer.lock().take();
+        return Ok(());
+      }
+      Ok(_) => continue,
+      Err(e) if e.kind() == io::ErrorKind::UnexpectedEof || e.kind() == io::ErrorKind::BrokenPipe => return Err(io::Error::new(e.kind(), "The payload went away without closing the channel")),
+      Err(e) => return Err(e),
+    };
+    let _ = sender.send(event);
+  }
+}
+
+type Halves = (BufReader<Box<dyn Read + Send>>, Box<dyn Write + Send>);
+
+/// The payload-to-Kenjector and Kenjector-to-payload pipes of an endpoint, see
+/// [`kenjector_channel::pipe_names`].
+#[cfg(target_os = "windows")]
+struct Listener {
+  up: HANDLE,
+  down: HANDLE,
+}
+
+// The handles are only ever used by the thread the listener is moved to
+#[cfg(target_os = "windows")]
+unsafe impl Send for Listener {}
+
+#[cfg(target_os = "windows")]
+impl Listener {
+  fn bind(endpoint: &str) -> Result<Self, String> {
+    let (up, down) = kenjector_channel::pipe_names(endpoint);
+    let create = |name: &str, access: u32| -> Result<HANDLE, String> {
+      let wide = name.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+      // A single instance, and the first, so no one else can be listening under the name already
+      let pipe = unsafe { CreateNamedPipeW(wide.as_ptr(), access | FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS, 1, 0x10000, 0x10000, 0, std::ptr::null_mut()) };
+      if pipe == INVALID_HANDLE_VALUE {
+        return Err(format!("CreateNamedPipeW failed for {}, error: {:#X?}", name, std::io::Error::last_os_error()));
+      }
+      Ok(pipe)
+    };
+    let up = create(&up, PIPE_ACCESS_INBOUND)?;
+    match create(&down, PIPE_ACCESS_OUTBOUND) {
+      Ok(down) => Ok(Self { up, down }),
+      Err(e) => {
+        unsafe { CloseHandle(up) };
+        Err(e)
+      }
+    }
+  }
+
+  fn accept(mut self) -> io::Result<Halves> {
+    use std::os::windows::io::FromRawHandle;
+    for pipe in [self.up, self.down] {
+      // The payload may have opened the pipe before we got here, which is just as good
+      if unsafe { ConnectNamedPipe(pipe, std::ptr::null_mut()) } == 0 && unsafe { GetLastError() } != ERROR_PIPE_CONNECTED {
+        return Err(io::Error::last_os_error());
+      }
+    }
+    let (up, down) = (std::mem::replace(&mut self.up, std::ptr::null_mut()), std::mem::replace(&mut self.down, std::ptr::null_mut()));
+    let reader: Box<dyn Read + Send> = Box::new(unsafe { std::fs::File::from_raw_handle(up as _) });
+    let writer: Box<dyn Write + Send> = Box::new(unsafe { std::fs::File::from_raw_handle(down as _) });
+    Ok((BufReader::new(reader), writer))
+  }
+
+  fn wake(endpoint: &str) {
+    let (up, down) = kenjector_channel::pipe_names(endpoint);
+    let _ = std::fs::OpenOptions::new().write(true).open(up);
+    let _ = std::fs::OpenOptions::new().read(true).open(down);
+  }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for Listener {
+  fn drop(&mut self) {
+    for pipe in [self.up, self.down].into_iter().filter(|p| !p.is_null()) {
+      unsafe { CloseHandle(pipe) };
+    }
+  }
+}
+
+#[cfg(unix)]
+struct Listener(std::os::unix::net::UnixListener);
+
+#[cfg(unix)]
+impl Listener {
+  fn bind(endpoint: &str) -> Result<Self, String> { std::os::unix::net::UnixListener::bind(endpoint).map(Self).map_err(|e| format!("Failed to listen on {}, error: {}", endpoint, e)) }
+
+  fn accept(self) -> io::Result<Halves> {
+    let (stream, _) = self.0.accept()?;
+    let reader: Box<dyn Read + Send> = Box::new(stream.try_clone()?);
+    Ok((BufReader::new(reader), Box::new(stream)))
+  }
+
+  fn wake(endpoint: &str) { let _ = std::os::unix::net::UnixStream::connect(endpoint); }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use kenjector_channel::Client;
+  use std::{os::unix::net::UnixStream, path::Path, time::{Duration, Instant}};
+
+  /// The next event, waiting for the thread to get to it.
+  fn next_event(server: &ChannelServer) -> ChannelEvent {
+    let started = Instant::now();
+    loop {
+      if let Some(event) = server.try_recv() {
+        return event;
+      }
+      assert!(started.elapsed() < Duration::from_secs(10), "no event from the channel");
+      std::thread::sleep(Duration::from_millis(5));
+    }
+  }
+
+  #[test]
+  fn passes_messages_both_ways() {
+    let server = ChannelServer::open(4242).unwrap();
+    assert_eq!(server.send_command("ping").unwrap_err(), "The payload hasn't connected yet");
+    assert_eq!(server.try_recv(), None);
+
+    let client = Client::connect(&server.endpoint).unwrap();
+    assert_eq!(client.server_process_id, std::process::id());
+    assert_eq!(next_event(&server), ChannelEvent::Connected { process_id: std::process::id() });
+
+    client.log("started").unwrap();
+    server.send_command("ping").unwrap();
+    assert_eq!(client.next_command().unwrap().as_deref(), Some("ping"));
+    client.reply("PONG").unwrap();
+    assert_eq!(next_event(&server), ChannelEvent::Log(String::from("started")));
+    assert_eq!(next_event(&server), ChannelEvent::Reply(String::from("PONG")));
+  }
+
+  #[test]
+  fn bye_closes_our_end() {
+    let server = ChannelServer::open(4242).unwrap();
+    let client = Client::connect(&server.endpoint).unwrap();
+    assert!(matches!(next_event(&server), ChannelEvent::Connected { .. }));
+
+    client.close().unwrap();
+    assert_eq!(next_event(&server), ChannelEvent::Closed(None));
+    // Our end is gone, which is what ends the payload's wait for commands
+    assert_eq!(client.next_command().unwrap(), None);
+    assert!(server.send_command("ping").is_err());
+  }
+
+  #[test]
+  fn payload_going_away_is_an_error() {
+    let server = ChannelServer::open(4242).unwrap();
+    // A payload that dies has no chance to say bye, unlike a dropped Client
+    let mut stream = UnixStream::connect(&server.endpoint).unwrap();
+    handshake(&mut stream.try_clone().unwrap(), &mut stream).unwrap();
+    assert!(matches!(next_event(&server), ChannelEvent::Connected { .. }));
+
+    drop(stream);
+    assert_eq!(next_event(&server), ChannelEvent::Closed(Some(String::from("The payload went away without closing the channel"))));
+  }
+
+  #[test]
+  fn drop_says_bye_and_removes_the_socket() {
+    let server = ChannelServer::open(4242).unwrap();
+    let endpoint = server.endpoint.clone();
+    let client = Client::connect(&endpoint).unwrap();
+    assert!(matches!(next_event(&server), ChannelEvent::Connected { .. }));
+
+    drop(server);
+    assert_eq!(client.next_command().unwrap(), None);
+    assert!(!Path::new(&endpoint).exists());
+  }
+
+  #[test]
+  fn drop_before_the_payload_connects() {
+    let server = ChannelServer::open(4242).unwrap();
+    let endpoint = server.endpoint.clone();
+    assert!(Path::new(&endpoint).exists());
+    drop(server);
+    assert!(!Path::new(&endpoint).exists());
+    assert!(Client::connect(&endpoint).is_err());
+  }
+
+  #[test]
+  fn wake_stops_a_thread_waiting_for_the_payload() {
+    let endpoint = endpoint_name(4242, NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let listener = Listener::bind(&endpoint).unwrap();
+    let (sender, events) = mpsc::channel();
+    let closed = Arc::new(AtomicBool::new(false));
+    let thread = {
+      let closed = closed.clone();
+      std::thread::spawn(move || serve(listener, &sender, &Mutex::new(None), &closed))
+    };
+
+    // What ChannelServer's drop does while no one is connected
+    closed.store(true, Ordering::Relaxed);
+    Listener::wake(&endpoint);
+    let started = Instant::now();
+    while !thread.is_finished() {
+      assert!(started.elapsed() < Duration::from_secs(10), "the thread kept waiting");
+      std::thread::sleep(Duration::from_millis(5));
+    }
+    assert!(thread.join().unwrap().is_ok());
+    // The wake-up connection is not taken for the payload
+    assert_eq!(events.try_recv().ok(), None);
+    let _ = std::fs::remove_file(&endpoint);
+  }
+}
@@ -0,0 +1,92 @@
+use crate::logic::pointer::PointerChain;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::{Path, PathBuf}, time::Duration};
+
+/// User settings that survive restarts, stored as JSON in the user's config directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+  /// Most recent first, without duplicates.
+  pub recent_dlls: Vec<PathBuf>,
+  /// Process names pinned to the top of the list, compared case-insensitively.
+  pub favourite_processes: Vec<String>,
+  /// How long a Kenjection waits for `DllMain`, or whatever else it waits on in the target.
+  pub injection_timeout_ms: u64,
+  /// Saved pointer chains by profile, a profile being named after the game by default.
+  pub pointer_profiles: BTreeMap<String, Vec<PointerChain>>,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      recent_dlls: Vec::new(),
+      favourite_processes: Vec::new(),
+      injection_timeout_ms: Self::DEFAULT_INJECTION_TIMEOUT_MS,
+      pointer_profiles: BTreeMap::new(),
+    }
+  }
+}
+
+impl Config {
+  pub const MAX_RECENT_DLLS: usize = 10;
+  pub const DEFAULT_INJECTION_TIMEOUT_MS: u64 = 10_000;
+
+  pub fn injection_timeout(&self) -> Duration { Duration::from_millis(self.injection_timeout_ms) }
+
+  /// `%APPDATA%\Kenjector\config.json` on Windows, `~/.config/Kenjector/config.json` on Linux.
+  pub fn path() -> PathBuf { dirs::config_dir().unwrap_or_else(std::env::temp_dir).join(crate::APP_NAME).join("config.json") }
+
+  /// Load the config, falling back to defaults when it is missing or unreadable.
+  pub fn load() -> Self {
+    match std::fs::read_to_string(Self::path()) {
+      Ok(v) => serde_json::from_str(&v).unwrap_or_else(|e| {
+        eprintln!("Failed to parse {}, using defaults, error: {}", Self::path().display(), e);
+        Self::default()
+      }),
+      Err(_) => Self::default(),
+    }
+  }
+
+  pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+    let path = Self::path();
+    if let Some(dir) = path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+    Ok(())
+  }
+
+  /// Move `path` to the front of the recent DLLs, dropping duplicates and the oldest past the limit.
+  pub fn add_recent_dll(&mut self, path: &Path) {
+    self.recent_dlls.retain(|p| !same_path(p, path));
+    self.recent_dlls.insert(0, path.to_path_buf());
+    self.recent_dlls.truncate(Self::MAX_RECENT_DLLS);
+  }
+
+  pub fn is_favourite(&self, process_name: &str) -> bool { self.favourite_processes.iter().any(|p| p.eq_ignore_ascii_case(process_name)) }
+
+  /// Pin or unpin `process_name`, returns whether it is now a favourite.
+  pub fn toggle_favourite(&mut self, process_name: &str) -> bool {
+    if self.is_favourite(process_name) {
+      self.favourite_processes.retain(|p| !p.eq_ignore_ascii_case(process_name));
+      false
+    } else {
+      self.favourite_processes.push(process_name.to_string());
+      true
+    }
+  }
+
+  pub fn pointer_chains(&self, profile: &str) -> &[PointerChain] { self.pointer_profiles.get(profile).map(Vec::as_slice).unwrap_or_default() }
+
+  /// Replace the chains saved under `profile`, saving none drops the profile.
+  pub fn set_pointer_chains(&mut self, profile: &str, chains: Vec<PointerChain>) {
+    if chains.is_empty() {
+      self.pointer_profiles.remove(profile);
+    } else {
+      self.pointer_profiles.insert(profile.to_string(), chains);
+    }
+  }
+}
+
+/// Windows paths are case-insensitive, so `C:\A.dll` and `c:\a.DLL` count as one recent entry.
+fn same_path(a: &Path, b: &Path) -> bool { if cfg!(target_os = "windows") { a.to_string_lossy().eq_ignore_ascii_case(&b.to_string_lossy()) } else { a == b } }
@@ -0,0 +1,301 @@
+use derive_more::Display;
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc};
+#[cfg(target_os = "linux")]
+use std::{io::{BufRead, BufReader, Read}, path::Path, process::{Command, Stdio}};
+#[cfg(target_os = "windows")]
+use winapi::{shared::winerror::{ERROR_ALREADY_EXISTS, WAIT_TIMEOUT}, um::{errhandlingapi::GetLastError, handleapi::{CloseHandle, INVALID_HANDLE_VALUE}, memoryapi::{CreateFileMappingW, FILE_MAP_READ, MapViewOfFile, UnmapViewOfFile}, synchapi::{CreateEventW, SetEvent, WaitForSingleObject}, winbase::WAIT_OBJECT_0, winnt::{HANDLE, PAGE_READWRITE}}};
+
+/// Size of the DBWIN buffer, the writer's process ID followed by the NUL-terminated string.
+#[cfg(target_os = "windows")]
+const DBWIN_BUFFER_SIZE: usize = 4096;
+
+/// Where a captured line came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum OutputSource {
+  #[cfg(target_os = "windows")]
+  #[display("OutputDebugString")]
+  DebugString,
+  #[cfg(target_os = "linux")]
+  #[display("stdout")]
+  Stdout,
+  #[cfg(target_os = "linux")]
+  #[display("stderr")]
+  Stderr,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputLine {
+  pub time: chrono::DateTime<chrono::Local>,
+  pub source: OutputSource,
+  pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum CaptureEvent {
+  Line(OutputLine),
+  /// The capture stopped on its own, saying why.
+  Ended(String),
+}
+
+/// Debug output of one process, read on threads of its own and handed out by [`Self::try_recv`].
+/// Dropping it stops the capture.
+pub struct OutputCapture {
+  pub process_id: u32,
+  events: mpsc::Receiver<CaptureEvent>,
+  stop: Arc<AtomicBool>,
+}
+
+impl OutputCapture {
+  pub fn try_recv(&self) -> Option<CaptureEvent> { self.events.try_recv().ok() }
+
+  /// Listen for what `process_id` passes to `OutputDebugString`, as DebugView does. Only one listener
+  /// can hold the DBWIN buffer of a session, and a process being debugged sends its output to the
+  /// debugger instead.
+  #[cfg(target_os = "windows")]
+  pub fn attach(process_id: u32) -> Result<Self, String> {
+    let dbwin = Dbwin::open()?;
+    let (sender, events) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+      let stop = stop.clone();
+      std::thread::spawn(move || {
+        if let Err(e) = dbwin.listen(process_id, &sender, &stop) {
+          let _ = sender.send(CaptureEvent::Ended(e));
+        }
+      });
+    }
+    Ok(Self { process_id, events, stop })
+  }
+
+  /// Output of a running process can't be taken over on Linux, only a process started with
+  /// [`Self::launch`] is captured.
+  #[cfg(target_os = "linux")]
+  pub fn attach(process_id: u32) -> Result<Self, String> { Err(format!("The output of process {} can't be captured as it is already running, launch it from here instead", process_id)) }
+
+  /// Start `program` with `args`, capturing what it writes to stdout and stderr. The process keeps
+  /// running once the capture is dropped, its output is then read and thrown away.
+  #[cfg(target_os = "linux")]
+  pub fn launch(program: &Path, args: &[String]) -> Result<Self, String> {
+    let mut child = Command::new(program).args(args).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map_err(|e| format!("Failed to launch {}, error: {}", program.display(), e))?;
+    let process_id = child.id();
+    let (sender, events) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let readers = [child.stdout.take().map(|s| (Box::new(s) as Box<dyn Read + Send>, OutputSource::Stdout)), child.stderr.take().map(|s| (Box::new(s) as Box<dyn Read + Send>, OutputSource::Stderr))]
+      .into_iter()
+      .flatten()
+      .map(|(stream, source)| {
+        let sender = sender.clone();
+        let stop = stop.clone();
+        std::thread::spawn(move || read_lines(stream, source, &sender, &stop))
+      })
+      .collect::<Vec<_>>();
+
+    std::thread::spawn(move || {
+      let status = child.wait();
+      // What the process wrote last comes before the news that it exited
+      for reader in readers {
+        let _ = reader.join();
+      }
+      let _ = sender.send(CaptureEvent::Ended(match status {
+        Ok(status) => format!("The process exited, {}", status),
+        Err(e) => format!("Waiting for the process failed, error: {}", e),
+      }));
+    });
+
+    Ok(Self { process_id, events, stop })
+  }
+
+  /// The program and arguments `process_id` was started with, to launch another one like it.
+  #[cfg(target_os = "linux")]
+  pub fn command_of(process_id: u32) -> Result<(std::path::PathBuf, Vec<String>), String> {
+    let program = std::fs::read_link(format!("/proc/{}/exe", process_id)).map_err(|e| format!("Failed to read the executable of process {}, error: {}", process_id, e))?;
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", process_id)).map_err(|e| format!("Failed to read the command line of process {}, error: {}", process_id, e))?;
+    let args = cmdline.split(|b| *b == 0).skip(1).filter(|a| !a.is_empty()).map(|a| String::from_utf8_lossy(a).into_owned()).collect();
+    Ok((program, args))
+  }
+}
+
+impl Drop for OutputCapture {
+  fn drop(&mut self) { self.stop.store(true, Ordering::Relaxed); }
+}
+
+/// Split a command line into arguments on whitespace, double quotes keep an argument together and a
+/// backslash takes the `"` or `\` after it literally. Any other backslash is kept as it is.
+#[cfg(target_os = "linux")]
+pub fn split_arguments(text: &str) -> Vec<String> {
+  let mut args = Vec::new();
+  let mut current: Option<String> = None;
+  let mut quoted = false;
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' if matches!(chars.peek(), Some('"' | '\\')) => current.get_or_insert_with(String::new).extend(chars.next()),
+      '"' => {
+        quoted = !quoted;
+        current.get_or_insert_with(String::new);
+      }
+      c if c.is_whitespace() && !quoted => args.extend(current.take()),
+      c => current.get_or_insert_with(String::new).push(c),
+    }
+  }
+  args.extend(current);
+  args
+}
+
+/// Join arguments back into a command line [`split_arguments`] takes apart again.
+#[cfg(target_os = "linux")]
+pub fn join_arguments(args: &[String]) -> String {
+  args
+    .iter()
+    .map(|a| {
+      let escaped = a.replace('\\', "\\\\").replace('"', "\\\"");
+      if a.is_empty() || a.contains(char::is_whitespace) { format!("\"{}\"", escaped) } else { escaped }
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Send every line of `stream` until it closes. After a stop the lines are still read, so the process
+/// doesn't block on a full pipe.
+#[cfg(target_os = "linux")]
+fn read_lines(stream: Box<dyn Read + Send>, source: OutputSource, sender: &mpsc::Sender<CaptureEvent>, stop: &AtomicBool) {
+  for line in BufReader::new(stream).split(b'\n') {
+    let Ok(line) = line else { break };
+    if !stop.load(Ordering::Relaxed) {
+      let text = String::from_utf8_lossy(&line).trim_end_matches('\r').to_string();
+      let _ = sender.send(CaptureEvent::Line(OutputLine { time: chrono::Local::now(), source, text }));
+    }
+  }
+}
+
+/// The shared buffer and the two events `OutputDebugString` talks to a listener through.
+#[cfg(target_os = "windows")]
+struct Dbwin {
+  buffer: HANDLE,
+  view: *const u8,
+  buffer_ready: HANDLE,
+  data_ready: HANDLE,
+}
+
+// The handles and the view are only ever used by the thread the listener is moved to
+#[cfg(target_os = "windows")]
+unsafe impl Send for Dbwin {}
+
+#[cfg(target_os = "windows")]
+impl Dbwin {
+  fn open() -> Result<Self, String> {
+    let wide = |name: &str| name.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+    let mut dbwin = Self {
+      buffer: std::ptr::null_mut(),
+      view: std::ptr::null(),
+      buffer_ready: std::ptr::null_mut(),
+      data_ready: std::ptr::null_mut(),
+    };
+    unsafe {
+      // Writers only open the events, so finding them already there means another listener has them
+      dbwin.buffer_ready = CreateEventW(std::ptr::null_mut(), 0, 0, wide("DBWIN_BUFFER_READY").as_ptr());
+      if dbwin.buffer_ready.is_null() {
+        return Err(format!("CreateEventW failed, error: {:#X?}", std::io::Error::last_os_error()));
+      }
+      if GetLastError() == ERROR_ALREADY_EXISTS {
+        return Err(String::from("Another debug output listener, such as DebugView or a debugger, is already running"));
+      }
+      dbwin.data_ready = CreateEventW(std::ptr::null_mut(), 0, 0, wide("DBWIN_DATA_READY").as_ptr());
+      if dbwin.data_ready.is_null() {
+        return Err(format!("CreateEventW failed, error: {:#X?}", std::io::Error::last_os_error()));
+      }
+      dbwin.buffer = CreateFileMappingW(INVALID_HANDLE_VALUE, std::ptr::null_mut(), PAGE_READWRITE, 0, DBWIN_BUFFER_SIZE as u32, wide("DBWIN_BUFFER").as_ptr());
+      if dbwin.buffer.is_null() {
+        return Err(format!("CreateFileMappingW failed, error: {:#X?}", std::io::Error::last_os_error()));
+      }
+      dbwin.view = MapViewOfFile(dbwin.buffer, FILE_MAP_READ, 0, 0, DBWIN_BUFFER_SIZE) as *const u8;
+      if dbwin.view.is_null() {
+        return Err(format!("MapViewOfFile failed, error: {:#X?}", std::io::Error::last_os_error()));
+      }
+    }
+    Ok(dbwin)
+  }
+
+  /// Take strings from the buffer until stopped, passing on those `process_id` wrote. Every writer in
+  /// the session waits for the buffer, so it is handed back as soon as it's read.
+  fn listen(&self, process_id: u32, sender: &mpsc::Sender<CaptureEvent>, stop: &AtomicBool) -> Result<(), String> {
+    while !stop.load(Ordering::Relaxed) {
+      unsafe { SetEvent(self.buffer_ready) };
+      loop {
+        match unsafe { WaitForSingleObject(self.data_ready, 200) } {
+          WAIT_OBJECT_0 => break,
+          WAIT_TIMEOUT if stop.load(Ordering::Relaxed) => return Ok(()),
+          WAIT_TIMEOUT => continue,
+          _ => return Err(format!("WaitForSingleObject failed, error: {:#X?}", std::io::Error::last_os_error())),
+        }
+      }
+
+      let (writer, text) = unsafe {
+        let writer = std::ptr::read_unaligned(self.view as *const u32);
+        let text = std::slice::from_raw_parts(self.view.add(4), DBWIN_BUFFER_SIZE - 4);
+        (writer, String::from_utf8_lossy(&text[..text.iter().position(|b| *b == 0).unwrap_or(text.len())]).into_owned())
+      };
+      if writer != process_id {
+        continue;
+      }
+      let time = chrono::Local::now();
+      for line in text.trim_end_matches(['\r', '\n']).lines() {
+        let _ = sender.send(CaptureEvent::Line(OutputLine { time, source: OutputSource::DebugString, text: line.to_string() }));
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for Dbwin {
+  fn drop(&mut self) {
+    unsafe {
+      if !self.view.is_null() {
+        UnmapViewOfFile(self.view as _);
+      }
+      for handle in [self.buffer, self.buffer_ready, self.data_ready].into_iter().filter(|h| !h.is_null()) {
+        CloseHandle(handle);
+      }
+    }
+  }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+
+  fn args(list: &[&str]) -> Vec<String> { list.iter().map(|a| a.to_string()).collect() }
+
+  #[test]
+  fn splits_on_whitespace_outside_quotes() {
+    assert_eq!(split_arguments("  --port 8080\t-v  "), args(&["--port", "8080", "-v"]));
+    assert_eq!(split_arguments(r#"--name "two words" "" x"#), args(&["--name", "two words", "", "x"]));
+    // Quotes only group, they can start and end mid-argument
+    assert_eq!(split_arguments(r#"--path="/tmp/a b"/c"#), args(&["--path=/tmp/a b/c"]));
+    assert_eq!(split_arguments(""), args(&[]));
+  }
+
+  #[test]
+  fn backslashes_escape_quotes_and_themselves_only() {
+    assert_eq!(split_arguments(r#"a\"b "c \" d" e\\f"#), args(&[r#"a"b"#, r#"c " d"#, r"e\f"]));
+    assert_eq!(split_arguments(r"C:\Games\x.exe \n"), args(&[r"C:\Games\x.exe", r"\n"]));
+  }
+
+  #[test]
+  fn join_quotes_what_needs_it() {
+    assert_eq!(join_arguments(&args(&["-v", "two words", ""])), r#"-v "two words" """#);
+    assert_eq!(join_arguments(&args(&[r#"a"b c"#])), r#""a\"b c""#);
+    assert_eq!(join_arguments(&args(&[r"C:\dir\"])), r"C:\\dir\\");
+  }
+
+  #[test]
+  fn join_and_split_round_trip() {
+    let cases: [&[&str]; 6] = [&["--config", "/home/me/my game/config.ini"], &[r#"a"b c"#, r#"""#, r#""""#], &[r"trailing\", r"\", r#"\""#, r#"say "hi"\"#], &["", "", "tab\there", "new\nline"], &["ünïcödé", "名前 🎮"], &[]];
+    for case in cases {
+      let case = args(case);
+      assert_eq!(split_arguments(&join_arguments(&case)), case, "{}", join_arguments(&case));
+    }
+  }
+}
@@ -0,0 +1,447 @@
+use derive_more::Display;
+use regex::{Regex, RegexBuilder};
+
+/// A single value a row exposes to the filter under a field name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+  Text(String),
+  Number(u64),
+}
+
+/// Anything that can be matched against a [`Filter`].
+pub trait Filterable {
+  /// Every value of the row, used by bare terms that name no field.
+  fn field_values(&self) -> Vec<FieldValue>;
+  /// The value of a named field, `None` if the row has no such field.
+  fn field_value(&self, field: &str) -> Option<FieldValue>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[display("{} (at character {})", message, position + 1)]
+pub struct FilterError {
+  pub message: String,
+  /// Index of the char in the query the error points at, not a byte offset.
+  pub position: usize,
+}
+
+impl std::error::Error for FilterError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum CompareOp {
+  #[display(":")]
+  Match,
+  #[display("=")]
+  Eq,
+  #[display("!=")]
+  Ne,
+  #[display("<")]
+  Lt,
+  #[display("<=")]
+  Le,
+  #[display(">")]
+  Gt,
+  #[display(">=")]
+  Ge,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+  Text(String),
+  Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+pub enum Predicate {
+  /// A bare term, matched against every field of the row.
+  Any(Pattern),
+  /// `field:text`, `field=text`, `field:/regex/`.
+  Field { field: String, op: CompareOp, pattern: Pattern },
+  /// `field<N`, `field>=N`, ... on a numeric field.
+  Number { field: String, op: CompareOp, value: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Term {
+  pub negated: bool,
+  pub predicate: Predicate,
+}
+
+/// A parsed search query, e.g. `arch:x86 admin:no name:/^game.*\.exe$/ pid>1000`.
+///
+/// Terms are separated by whitespace and must all match. A term is either a bare
+/// word (substring over every field), or `field` followed by one of `:`, `=`, `!=`,
+/// `<`, `<=`, `>`, `>=` and a value. Values can be quoted (`"a b"`) or a regex
+/// (`/.../`). A leading `!` negates a term. Text matching is case-insensitive and
+/// numbers accept a `0x` prefix.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+  pub terms: Vec<Term>,
+}
+
+impl Filter {
+  /// Parse `query`. When `fields` is not empty, field names are checked against it.
+  pub fn parse(query: &str, fields: &[&str]) -> Result<Self, FilterError> {
+    let mut parser = Parser { chars: query.chars().collect(), pos: 0 };
+    let mut terms = Vec::new();
+
+    loop {
+      parser.skip_whitespace();
+      if parser.at_end() {
+        break;
+      }
+      terms.push(parser.term(fields)?);
+    }
+
+    Ok(Self { terms })
+  }
+
+  pub fn is_empty(&self) -> bool { self.terms.is_empty() }
+
+  pub fn matches(&self, row: &impl Filterable) -> bool { self.terms.iter().all(|term| term.matches(row)) }
+}
+
+impl Term {
+  fn matches(&self, row: &impl Filterable) -> bool {
+    let hit = match &self.predicate {
+      Predicate::Any(pattern) => row.field_values().iter().any(|v| pattern.matches(v)),
+      Predicate::Field { field, op, pattern } => match row.field_value(field) {
+        Some(v) => match op {
+          CompareOp::Ne => !pattern.matches_exact(&v),
+          CompareOp::Eq => pattern.matches_exact(&v),
+          _ => pattern.matches(&v),
+        },
+        None => false,
+      },
+      Predicate::Number { field, op, value } => match row.field_value(field) {
+        Some(FieldValue::Number(n)) => compare(n, *op, *value),
+        Some(FieldValue::Text(t)) => parse_number(&t).is_some_and(|n| compare(n, *op, *value)),
+        None => false,
+      },
+    };
+    hit != self.negated
+  }
+}
+
+impl Pattern {
+  fn matches(&self, value: &FieldValue) -> bool {
+    match (self, value) {
+      (Pattern::Text(t), FieldValue::Text(v)) => v.to_lowercase().contains(&t.to_lowercase()),
+      // Numbers match on either their decimal or hex spelling, or exactly if the term is a number
+      (Pattern::Text(t), FieldValue::Number(n)) => match parse_number(t) {
+        Some(wanted) => *n == wanted,
+        None => n.to_string().contains(t.as_str()) || format!("{:#x}", n).contains(&t.to_lowercase()),
+      },
+      (Pattern::Regex(r), FieldValue::Text(v)) => r.is_match(v),
+      (Pattern::Regex(r), FieldValue::Number(n)) => r.is_match(&n.to_string()) || r.is_match(&format!("{:#X}", n)),
+    }
+  }
+
+  fn matches_exact(&self, value: &FieldValue) -> bool {
+    match (self, value) {
+      (Pattern::Text(t), FieldValue::Text(v)) => v.trim().eq_ignore_ascii_case(t),
+      (Pattern::Text(t), FieldValue::Number(n)) => parse_number(t) == Some(*n),
+      (Pattern::Regex(_), _) => self.matches(value),
+    }
+  }
+}
+
+fn compare(lhs: u64, op: CompareOp, rhs: u64) -> bool {
+  match op {
+    CompareOp::Match | CompareOp::Eq => lhs == rhs,
+    CompareOp::Ne => lhs != rhs,
+    CompareOp::Lt => lhs < rhs,
+    CompareOp::Le => lhs <= rhs,
+    CompareOp::Gt => lhs > rhs,
+    CompareOp::Ge => lhs >= rhs,
+  }
+}
+
+/// Parse a decimal or `0x`-prefixed hexadecimal number.
+pub fn parse_number(text: &str) -> Option<u64> {
+  let text = text.trim();
+  match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+    Some(hex) => u64::from_str_radix(hex, 16).ok(),
+    None => text.parse().ok(),
+  }
+}
+
+struct Parser {
+  chars: Vec<char>,
+  pos: usize,
+}
+
+impl Parser {
+  fn at_end(&self) -> bool { self.pos >= self.chars.len() }
+
+  fn peek(&self) -> Option<char> { self.chars.get(self.pos).copied() }
+
+  fn peek_at(&self, offset: usize) -> Option<char> { self.chars.get(self.pos + offset).copied() }
+
+  fn error(&self, position: usize, message: impl Into<String>) -> FilterError { FilterError { message: message.into(), position } }
+
+  fn skip_whitespace(&mut self) {
+    while self.peek().is_some_and(char::is_whitespace) {
+      self.pos += 1;
+    }
+  }
+
+  fn term(&mut self, fields: &[&str]) -> Result<Term, FilterError> {
+    let negated = self.peek() == Some('!');
+    if negated {
+      self.pos += 1;
+    }
+
+    let start = self.pos;
+
+    // Look ahead for `ident op`, otherwise this is a bare term
+    let mut ident_end = self.pos;
+    while self.chars.get(ident_end).is_some_and(|c| c.is_alphanumeric() || *c == '_') {
+      ident_end += 1;
+    }
+
+    let op_follows = ident_end > self.pos && self.chars.get(ident_end).is_some_and(|c| matches!(c, ':' | '=' | '<' | '>') || (*c == '!' && self.chars.get(ident_end + 1) == Some(&'=')));
+
+    if !op_follows {
+      let pattern = self.pattern()?;
+      return Ok(Term { negated, predicate: Predicate::Any(pattern) });
+    }
+
+    let field: String = self.chars[self.pos..ident_end].iter().map(|c| c.to_ascii_lowercase()).collect();
+    self.pos = ident_end;
+
+    if !fields.is_empty() && !fields.contains(&field.as_str()) {
+      return Err(self.error(start, format!("unknown field `{}`, expected one of: {}", field, fields.join(", "))));
+    }
+
+    let op = self.op();
+    let value_start = self.pos;
+
+    match op {
+      CompareOp::Lt | CompareOp::Le | CompareOp::Gt | CompareOp::Ge => {
+        let word = self.word();
+        match parse_number(&word) {
+          Some(value) => Ok(Term { negated, predicate: Predicate::Number { field, op, value } }),
+          None if word.is_empty() => Err(self.error(value_start, format!("expected a number after `{}{}`", field, op))),
+          None => Err(self.error(value_start, format!("`{}` is not a number", word))),
+        }
+      }
+      _ => {
+        let pattern = self.pattern()?;
+        Ok(Term { negated, predicate: Predicate::Field { field, op, pattern } })
+      }
+    }
+  }
+
+  fn op(&mut self) -> CompareOp {
+    let (op, width) = match (self.peek(), self.peek_at(1)) {
+      (Some('!'), Some('=')) => (CompareOp::Ne, 2),
+      (Some('<'), Some('=')) => (CompareOp::Le, 2),
+      (Some('>'), Some('=')) => (CompareOp::Ge, 2),
+      (Some('<'), _) => (CompareOp::Lt, 1),
+      (Some('>'), _) => (CompareOp::Gt, 1),
+      (Some('='), _) => (CompareOp::Eq, 1),
+      _ => (CompareOp::Match, 1),
+    };
+    self.pos += width;
+    op
+  }
+
+  fn word(&mut self) -> String {
+    let mut word = String::new();
+    while let Some(c) = self.peek().filter(|c| !c.is_whitespace()) {
+      word.push(c);
+      self.pos += 1;
+    }
+    word
+  }
+
+  fn pattern(&mut self) -> Result<Pattern, FilterError> {
+    let start = self.pos;
+    match self.peek() {
+      Some('"') => {
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+          match self.peek() {
+            Some('"') => {
+              self.pos += 1;
+              break;
+            }
+            Some('\\') if self.peek_at(1) == Some('"') => {
+              text.push('"');
+              self.pos += 2;
+            }
+            Some(c) => {
+              text.push(c);
+              self.pos += 1;
+            }
+            None => return Err(self.error(start, "unterminated quote")),
+          }
+        }
+        Ok(Pattern::Text(text))
+      }
+      Some('/') => {
+        self.pos += 1;
+        let mut source = String::new();
+        loop {
+          match self.peek() {
+            Some('/') => {
+              self.pos += 1;
+              break;
+            }
+            // `\/` is a literal slash, every other escape is handed to the regex as-is
+            Some('\\') if self.peek_at(1) == Some('/') => {
+              source.push('/');
+              self.pos += 2;
+            }
+            Some('\\') => {
+              source.push('\\');
+              self.pos += 1;
+              if let Some(c) = self.peek() {
+                source.push(c);
+                self.pos += 1;
+              }
+            }
+            Some(c) => {
+              source.push(c);
+              self.pos += 1;
+            }
+            None => return Err(self.error(start, "unterminated regex, expected a closing `/`")),
+          }
+        }
+        let regex = RegexBuilder::new(&source).case_insensitive(true).build().map_err(|e| {
+          // The regex error is a multi-line diagram, keep only its final message
+          let message = e.to_string();
+          let message = message.lines().last().unwrap_or_default().trim();
+          self.error(start, format!("invalid regex: {}", message.strip_prefix("error: ").unwrap_or(message)))
+        })?;
+        Ok(Pattern::Regex(regex))
+      }
+      _ => {
+        let word = self.word();
+        if word.is_empty() { Err(self.error(start, "expected a value")) } else { Ok(Pattern::Text(word)) }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FIELDS: &[&str] = &["name", "arch", "pid", "admin"];
+
+  struct Row {
+    name: &'static str,
+    arch: &'static str,
+    pid: u64,
+    admin: &'static str,
+  }
+
+  impl Filterable for Row {
+    fn field_values(&self) -> Vec<FieldValue> { FIELDS.iter().filter_map(|f| self.field_value(f)).collect() }
+
+    fn field_value(&self, field: &str) -> Option<FieldValue> {
+      match field {
+        "name" => Some(FieldValue::Text(self.name.to_string())),
+        "arch" => Some(FieldValue::Text(self.arch.to_string())),
+        "pid" => Some(FieldValue::Number(self.pid)),
+        "admin" => Some(FieldValue::Text(self.admin.to_string())),
+        _ => None,
+      }
+    }
+  }
+
+  const GAME: Row = Row { name: "Game Client.exe", arch: "x64", pid: 4242, admin: "no" };
+  const SERVICE: Row = Row { name: "svchost.exe", arch: "x64", pid: 0x3E8, admin: "yes" };
+
+  fn matches(query: &str, row: &Row) -> bool { Filter::parse(query, FIELDS).unwrap().matches(row) }
+
+  fn error(query: &str) -> FilterError { Filter::parse(query, FIELDS).unwrap_err() }
+
+  #[test]
+  fn empty_query_matches_everything() {
+    let filter = Filter::parse("  \t ", FIELDS).unwrap();
+    assert!(filter.is_empty());
+    assert!(filter.matches(&GAME));
+  }
+
+  #[test]
+  fn bare_terms_search_every_field() {
+    assert!(matches("client", &GAME));
+    assert!(matches("4242", &GAME));
+    assert!(!matches("client", &SERVICE));
+    // Every term has to match
+    assert!(!matches("client svchost", &GAME));
+  }
+
+  #[test]
+  fn field_operators() {
+    assert!(matches("name:game", &GAME));
+    assert!(matches("NAME:GAME", &GAME));
+    assert!(!matches("name=game", &GAME));
+    assert!(matches("admin=no", &GAME));
+    assert!(matches("admin!=yes", &GAME));
+    assert!(!matches("admin!=no", &GAME));
+  }
+
+  #[test]
+  fn numeric_comparisons() {
+    assert!(matches("pid>1000", &GAME));
+    assert!(matches("pid>=4242 pid<=4242", &GAME));
+    assert!(!matches("pid<4242", &GAME));
+    assert!(matches("pid=0x3e8", &SERVICE));
+    assert!(matches("pid<0x400", &SERVICE));
+  }
+
+  #[test]
+  fn quoted_values() {
+    assert!(matches("name:\"game client\"", &GAME));
+    assert!(!matches("name:\"client game\"", &GAME));
+    let filter = Filter::parse(r#"name:"say \"hi\"""#, FIELDS).unwrap();
+    assert!(matches!(&filter.terms[0].predicate, Predicate::Field { pattern: Pattern::Text(text), .. } if text == "say \"hi\""));
+  }
+
+  #[test]
+  fn regex_values() {
+    assert!(matches(r"name:/^game.*\.exe$/", &GAME));
+    assert!(!matches(r"name:/^game.*\.dll$/", &GAME));
+    let filter = Filter::parse(r"name:/a\/b/", FIELDS).unwrap();
+    assert!(matches!(&filter.terms[0].predicate, Predicate::Field { pattern: Pattern::Regex(regex), .. } if regex.as_str() == "a/b"));
+  }
+
+  #[test]
+  fn negation() {
+    assert!(matches("!svchost", &GAME));
+    assert!(!matches("!name:game", &GAME));
+    assert!(matches("!pid<1000", &GAME));
+  }
+
+  #[test]
+  fn errors_point_at_the_offending_char() {
+    let e = error("arch:x64 size>10");
+    assert_eq!(e.position, 9);
+    assert!(e.message.starts_with("unknown field `size`"));
+
+    assert_eq!(error("pid>").position, 4);
+    assert_eq!(error("pid>abc").message, "`abc` is not a number");
+    assert_eq!(error("name:\"game").position, 5);
+    assert_eq!(error("name:/game").position, 5);
+    assert!(error("name:/(/").message.starts_with("invalid regex"));
+    assert_eq!(error("name:").message, "expected a value");
+  }
+
+  #[test]
+  fn positions_count_chars_not_bytes() {
+    // `é` and `名` take 2 and 3 bytes, the error still points at the 6th char
+    let e = error("é名 x size:1");
+    assert_eq!(e.position, 5);
+    assert_eq!(e.to_string(), "unknown field `size`, expected one of: name, arch, pid, admin (at character 6)");
+  }
+
+  #[test]
+  fn fields_are_not_checked_without_a_list() {
+    let filter = Filter::parse("size>10", &[]).unwrap();
+    assert!(!filter.matches(&GAME));
+  }
+}
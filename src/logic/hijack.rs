@@ -0,0 +1,215 @@
+use crate::logic::{kenjector::{Arch, Deadline, Kenjector}, remote::RemoteResources, stub::{CallConv, CallStub, StubExit}};
+use std::time::Duration;
+use winapi::um::{handleapi::CloseHandle, memoryapi::{ReadProcessMemory, WriteProcessMemory}, processthreadsapi::{FlushInstructionCache, GetThreadContext, OpenThread, ResumeThread, SetThreadContext, SuspendThread}, winbase::{Wow64GetThreadContext, Wow64SetThreadContext}, winnt::{CONTEXT, CONTEXT_FULL, HANDLE, PAGE_EXECUTE_READWRITE, THREAD_GET_CONTEXT, THREAD_SET_CONTEXT, THREAD_SUSPEND_RESUME, WOW64_CONTEXT, WOW64_CONTEXT_FULL}};
+
+/// Layout of the block allocated in the target: the stub, then the result and the done flag.
+const BLOCK_SIZE: usize = 0x1000;
+const RESULT_OFFSET: u64 = 0xF00;
+const DONE_OFFSET: u64 = 0xF08;
+
+/// Run `load_library(remote_path)` on an existing thread of the target and return its result.
+///
+/// A thread is suspended, its instruction pointer is pointed at a [`CallStub`] and it is resumed.
+/// Once the stub signals completion the thread is suspended again, sent back to where it was and
+/// carries on as if nothing happened. A thread blocked in a wait only reaches the stub when
+/// the wait ends, so this gives up at `deadline`. If the thread is inside the stub by then, the stub
+/// and everything else in `resources` stays allocated for it to finish with.
+pub fn load_library(resources: &mut RemoteResources, process_id: u32, arch: Arch, load_library: u64, remote_path: u64, deadline: &Deadline) -> Result<u64, String> {
+  if !arch.can_target() {
+    return Err(format!("Thread hijacking a {} process is not supported from a {} build", arch, Arch::native()));
+  }
+  let wow64 = arch != Arch::native();
+
+  let thread_ids = Kenjector::get_thread_ids(process_id)?;
+  if thread_ids.is_empty() {
+    return Err(format!("Process {} has no threads", process_id));
+  }
+
+  let h_process = resources.process();
+  unsafe {
+    let block = resources.alloc(BLOCK_SIZE, PAGE_EXECUTE_READWRITE)?;
+
+    let call = CallStub {
+      conv: CallConv::windows(arch)?,
+      target: load_library,
+      args: vec![remote_path],
+      result: block + RESULT_OFFSET,
+      done: block + DONE_OFFSET,
+      exit: StubExit::Spin,
+    };
+    let stub = call.assemble()?;
+    let spin = block + call.spin_offset(&stub) as u64;
+    write(h_process, block, &stub)?;
+    FlushInstructionCache(h_process, block as _, stub.len());
+
+    // Take the first thread we are allowed to suspend and read
+    let mut hijacked = None;
+    for thread_id in thread_ids {
+      let thread = OpenThread(THREAD_SUSPEND_RESUME | THREAD_GET_CONTEXT | THREAD_SET_CONTEXT, 0, thread_id);
+      if thread.is_null() {
+        continue;
+      }
+
+      if SuspendThread(thread) == u32::MAX {
+        CloseHandle(thread);
+        continue;
+      }
+
+      match ThreadContext::get(thread, wow64) {
+        Ok(v) => {
+          hijacked = Some((thread, v));
+          break;
+        }
+        Err(_) => {
+          ResumeThread(thread);
+          CloseHandle(thread);
+        }
+      }
+    }
+
+    let Some((thread, saved)) = hijacked else { return Err(format!("Failed to suspend any thread of process {}, error: {:#X?}", process_id, std::io::Error::last_os_error())) };
+
+    let mut redirected = saved.clone();
+    redirected.set_ip(block);
+    if let Err(e) = redirected.set(thread) {
+      ResumeThread(thread);
+      CloseHandle(thread);
+      return Err(e);
+    }
+    ResumeThread(thread);
+
+    // Poll the flag while the thread runs. It is set before the stub restores its registers, so once it
+    // is the thread is suspended and resumed again until it sits on the final spin
+    let (done, current) = loop {
+      let flagged = read_u64(h_process, block + DONE_OFFSET).unwrap_or_default() as u32 == 1;
+      if flagged || deadline.is_over() {
+        SuspendThread(thread);
+        let current = ThreadContext::get(thread, wow64);
+        let spinning = flagged && current.as_ref().is_ok_and(|c| c.ip() == spin);
+        if spinning || deadline.is_over() || current.is_err() {
+          break (spinning, current);
+        }
+        ResumeThread(thread);
+      }
+      std::thread::sleep(Duration::from_millis(10));
+    };
+
+    // On the spin the stub has restored every register, so only the instruction pointer goes back.
+    // This keeps the result of a wait that ended while the thread was redirected.
+    // A thread still inside the stub is left alone, changing it there would corrupt it.
+    let mut kept = None;
+    let restored = match current {
+      Ok(mut current) if done => {
+        current.set_ip(saved.ip());
+        current.set(thread)
+      }
+      Ok(current) if current.ip() == block => saved.set(thread),
+      Ok(_) => {
+        kept = Some(resources.keep_allocations());
+        Ok(())
+      }
+      Err(e) => Err(format!("{}. {}", e, resources.keep_allocations())),
+    };
+
+    ResumeThread(thread);
+    CloseHandle(thread);
+    restored?;
+
+    if !done {
+      let error = format!("{}. The hijacked thread may be blocked in a wait", deadline.error("LoadLibraryW"));
+      return Err(match kept {
+        Some(kept) => format!("{}. {}", error, kept),
+        None => error,
+      });
+    }
+
+    let result = read_u64(h_process, block + RESULT_OFFSET)?;
+    Ok(if arch == Arch::AMDx86 { result & 0xFFFF_FFFF } else { result })
+  }
+}
+
+unsafe fn write(h_process: HANDLE, address: u64, bytes: &[u8]) -> Result<(), String> {
+  if unsafe { WriteProcessMemory(h_process, address as _, bytes.as_ptr() as _, bytes.len(), std::ptr::null_mut()) } == 0 {
+    return Err(format!("WriteProcessMemory failed, error: {:#X?}", std::io::Error::last_os_error()));
+  }
+  Ok(())
+}
+
+unsafe fn read_u64(h_process: HANDLE, address: u64) -> Result<u64, String> {
+  let mut value: u64 = 0;
+  if unsafe { ReadProcessMemory(h_process, address as _, &mut value as *mut _ as _, std::mem::size_of::<u64>(), std::ptr::null_mut()) } == 0 {
+    return Err(format!("ReadProcessMemory failed, error: {:#X?}", std::io::Error::last_os_error()));
+  }
+  Ok(value)
+}
+
+/// `CONTEXT` has to be 16-byte aligned on x64, which winapi's definition does not guarantee.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct AlignedContext(CONTEXT);
+
+/// Register state of a suspended thread, either native or a 32-bit thread under WOW64.
+#[derive(Clone)]
+enum ThreadContext {
+  Native(Box<AlignedContext>),
+  Wow64(Box<WOW64_CONTEXT>),
+}
+
+impl ThreadContext {
+  unsafe fn get(thread: HANDLE, wow64: bool) -> Result<Self, String> {
+    unsafe {
+      if wow64 {
+        let mut context: Box<WOW64_CONTEXT> = Box::new(std::mem::zeroed());
+        context.ContextFlags = WOW64_CONTEXT_FULL;
+        if Wow64GetThreadContext(thread, &mut *context) == 0 {
+          return Err(format!("Wow64GetThreadContext failed, error: {:#X?}", std::io::Error::last_os_error()));
+        }
+        Ok(Self::Wow64(context))
+      } else {
+        let mut context: Box<AlignedContext> = Box::new(std::mem::zeroed());
+        context.0.ContextFlags = CONTEXT_FULL;
+        if GetThreadContext(thread, &mut context.0) == 0 {
+          return Err(format!("GetThreadContext failed, error: {:#X?}", std::io::Error::last_os_error()));
+        }
+        Ok(Self::Native(context))
+      }
+    }
+  }
+
+  unsafe fn set(&self, thread: HANDLE) -> Result<(), String> {
+    let ok = unsafe {
+      match self {
+        Self::Native(context) => SetThreadContext(thread, &context.0),
+        Self::Wow64(context) => Wow64SetThreadContext(thread, &**context),
+      }
+    };
+    if ok == 0 {
+      return Err(format!("SetThreadContext failed, error: {:#X?}", std::io::Error::last_os_error()));
+    }
+    Ok(())
+  }
+
+  fn ip(&self) -> u64 {
+    match self {
+      #[cfg(target_arch = "x86_64")]
+      Self::Native(context) => context.0.Rip,
+      #[cfg(target_arch = "x86")]
+      Self::Native(context) => context.0.Eip as u64,
+      #[cfg(target_arch = "aarch64")]
+      Self::Native(context) => context.0.Pc,
+      Self::Wow64(context) => context.Eip as u64,
+    }
+  }
+
+  fn set_ip(&mut self, ip: u64) {
+    match self {
+      #[cfg(target_arch = "x86_64")]
+      Self::Native(context) => context.0.Rip = ip,
+      #[cfg(target_arch = "x86")]
+      Self::Native(context) => context.0.Eip = ip as u32,
+      #[cfg(target_arch = "aarch64")]
+      Self::Native(context) => context.0.Pc = ip,
+      Self::Wow64(context) => context.Eip = ip as u32,
+    }
+  }
+}
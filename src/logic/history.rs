@@ -0,0 +1,111 @@
+use crate::logic::kenjector::Kenjected;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fs::OpenOptions, io::{BufRead, BufReader, Write}, path::{Path, PathBuf}, time::Instant};
+
+/// One Kenjection attempt, as shown in the history panel and written to the history log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InjectionRecord {
+  pub timestamp: DateTime<Local>,
+  pub target_name: String,
+  pub process_id: u32,
+  pub dll_path: PathBuf,
+  pub dll_sha256: String,
+  pub method: String,
+  pub module_base: Option<u64>,
+  pub duration_ms: u64,
+  pub error: Option<String>,
+  /// Set when the DLL loaded but something after it failed, e.g. cleaning up in the target.
+  pub warning: Option<String>,
+}
+
+impl InjectionRecord {
+  /// Build a record for an injection that started at `started` and returned `result`.
+  pub fn new(target_name: impl Into<String>, process_id: u32, dll_path: PathBuf, method: impl Into<String>, started: Instant, result: &Result<Kenjected, String>) -> Self {
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let dll_sha256 = Self::hash_file(&dll_path).unwrap_or_default();
+    let (module_base, error, warning) = match result {
+      Ok(v) => (Some(v.base), None, v.warning.clone()),
+      Err(e) => (None, Some(e.clone()), None),
+    };
+
+    Self {
+      timestamp: Local::now(),
+      target_name: target_name.into(),
+      process_id,
+      dll_path,
+      dll_sha256,
+      method: method.into(),
+      module_base,
+      duration_ms,
+      error,
+      warning,
+    }
+  }
+
+  /// Lowercase hex SHA-256 of the file at `path`.
+  pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let bytes = std::fs::read(path)?;
+    Ok(Sha256::digest(&bytes).iter().map(|b| format!("{:02x}", b)).collect())
+  }
+}
+
+/// Append-only JSON-lines log of every injection, rotated once it grows past `max_bytes`.
+#[derive(Debug, Clone)]
+pub struct HistoryLog {
+  pub path: PathBuf,
+  pub max_bytes: u64,
+  pub max_files: u32,
+}
+
+impl Default for HistoryLog {
+  fn default() -> Self { Self { path: Self::default_path(), max_bytes: 1024 * 1024, max_files: 3 } }
+}
+
+impl HistoryLog {
+  /// `%LOCALAPPDATA%\Kenjector\history.jsonl` on Windows, `~/.local/share/Kenjector/history.jsonl` on Linux.
+  pub fn default_path() -> PathBuf { dirs::data_local_dir().unwrap_or_else(std::env::temp_dir).join(crate::APP_NAME).join("history.jsonl") }
+
+  pub fn append(&self, record: &InjectionRecord) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = self.path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+
+    self.rotate()?;
+
+    let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+  }
+
+  /// Read the current log file, oldest first. Lines that fail to parse are skipped.
+  pub fn load(&self) -> Vec<InjectionRecord> {
+    let Ok(file) = std::fs::File::open(&self.path) else { return Vec::new() };
+    BufReader::new(file).lines().map_while(Result::ok).filter_map(|line| serde_json::from_str(&line).ok()).collect()
+  }
+
+  /// Shift `history.jsonl` -> `history.jsonl.1` -> ... once it reaches `max_bytes`, dropping the oldest.
+  fn rotate(&self) -> std::io::Result<()> {
+    let size = match std::fs::metadata(&self.path) {
+      Ok(m) => m.len(),
+      Err(_) => return Ok(()),
+    };
+
+    if size < self.max_bytes {
+      return Ok(());
+    }
+
+    let rotated = |n: u32| PathBuf::from(format!("{}.{}", self.path.display(), n));
+
+    let _ = std::fs::remove_file(rotated(self.max_files));
+    for n in (1..self.max_files).rev() {
+      let from = rotated(n);
+      if from.exists() {
+        std::fs::rename(&from, rotated(n + 1))?;
+      }
+    }
+
+    std::fs::rename(&self.path, rotated(1))
+  }
+}
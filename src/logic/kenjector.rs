@@ -1,8 +1,17 @@
+use crate::logic::{filter::{FieldValue, Filterable}, method::InjectionMethod};
+#[cfg(target_os = "windows")]
+use crate::logic::{hijack, reflective, remote::RemoteResources, winpath};
+#[cfg(target_os = "linux")]
+use crate::logic::{privilege::{PtraceAccess, PtraceTarget}, ptrace, regions::{self, RegionType}};
 use derive_more::Display;
 use gtk4::{gdk::prelude::DisplayExt, prelude::NativeExt};
+use kenjector_channel::{INIT_EXPORT, UNLOAD_EXPORT};
 use pelite::{FileMap, pe32::{Pe as Pe32, PeFile as Pe32File}, pe64::{Pe as Pe64, PeFile as Pe64File}};
-use std::{ffi::{CStr, CString}, path::PathBuf};
-use winapi::{shared::windef::{HBITMAP, HICON}, um::{handleapi::CloseHandle, libloaderapi::{GetModuleHandleA, GetProcAddress}, memoryapi::{VirtualAllocEx, WriteProcessMemory}, processthreadsapi::{CreateRemoteThread, GetExitCodeThread, OpenProcess, OpenProcessToken}, psapi::GetModuleFileNameExW, securitybaseapi::GetTokenInformation, shellapi::ExtractIconExW, synchapi::WaitForSingleObject, tlhelp32::{CreateToolhelp32Snapshot, PROCESSENTRY32, Process32First, Process32Next, TH32CS_SNAPPROCESS}, winbase::INFINITE, wingdi::{BITMAPINFO, BITMAPINFOHEADER, DIB_RGB_COLORS, DeleteDC, DeleteObject, GetDIBits}, winnt::{HANDLE, IMAGE_FILE_MACHINE_I386, MEM_COMMIT, PAGE_READWRITE, PROCESS_ALL_ACCESS, PROCESS_QUERY_LIMITED_INFORMATION, TOKEN_ELEVATION, TOKEN_QUERY, TokenElevation}, winuser::{GetIconInfo, ICONINFO}, wow64apiset::IsWow64Process2}};
+#[cfg(target_os = "windows")]
+use std::ffi::CStr;
+use std::{ffi::CString, path::{Path, PathBuf}, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
+#[cfg(target_os = "windows")]
+use winapi::{shared::windef::{HBITMAP, HICON}, shared::winerror::{ERROR_ACCESS_DENIED, ERROR_BAD_LENGTH, ERROR_INVALID_PARAMETER}, um::{errhandlingapi::GetLastError, handleapi::{CloseHandle, INVALID_HANDLE_VALUE}, minwinbase::STILL_ACTIVE, processthreadsapi::{CreateRemoteThread, GetCurrentProcess, GetExitCodeProcess, GetExitCodeThread, GetProcessInformation, OpenProcess, OpenProcessToken, OpenThread, PROCESS_INFORMATION_CLASS, QueueUserAPC}, psapi::GetModuleFileNameExW, securitybaseapi::GetTokenInformation, shellapi::ExtractIconExW, synchapi::WaitForSingleObject, tlhelp32::{CreateToolhelp32Snapshot, MODULEENTRY32W, Module32FirstW, Module32NextW, PROCESSENTRY32, Process32First, Process32Next, TH32CS_SNAPMODULE, TH32CS_SNAPMODULE32, TH32CS_SNAPPROCESS, TH32CS_SNAPTHREAD, THREADENTRY32, Thread32First, Thread32Next}, winbase::{WAIT_FAILED, WAIT_OBJECT_0}, wingdi::{BITMAPINFO, BITMAPINFOHEADER, DIB_RGB_COLORS, DeleteDC, DeleteObject, GetDIBits}, winnt::{HANDLE, IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_ARM64, IMAGE_FILE_MACHINE_I386, IMAGE_FILE_MACHINE_UNKNOWN, PAGE_READWRITE, PAPCFUNC, PROCESS_ALL_ACCESS, PROCESS_QUERY_LIMITED_INFORMATION, THREAD_SET_CONTEXT, TOKEN_ELEVATION, TOKEN_QUERY, TokenElevation}, winuser::{GetIconInfo, ICONINFO}, wow64apiset::IsWow64Process2}};
 
 #[derive(Debug, Clone, Display)]
 #[display("{} - {:#X}", name, process_id)]
@@ -12,6 +21,91 @@ pub struct ProcessInfo {
   pub name: String,
   pub arch: Arch,
   pub process_id: u32,
+  /// Pinned by the user, see [`crate::logic::config::Config::favourite_processes`].
+  pub favourite: bool,
+  /// Whether a Kenjection can open the process, found when the list is taken.
+  pub access: AccessStatus,
+}
+
+impl ProcessInfo {
+  /// Field names accepted by the search filter, see [`crate::logic::filter::Filter`].
+  pub const FILTER_FIELDS: &'static [&'static str] = &["admin", "name", "arch", "pid", "access"];
+}
+
+impl Filterable for ProcessInfo {
+  fn field_values(&self) -> Vec<FieldValue> { Self::FILTER_FIELDS.iter().filter_map(|f| self.field_value(f)).collect() }
+
+  fn field_value(&self, field: &str) -> Option<FieldValue> {
+    match field {
+      "admin" => Some(FieldValue::Text(String::from(if self.elevated { "yes" } else { "no" }))),
+      "name" => Some(FieldValue::Text(self.name.clone())),
+      "arch" => Some(FieldValue::Text(self.arch.to_string())),
+      "pid" => Some(FieldValue::Number(self.process_id as u64)),
+      "access" => Some(FieldValue::Text(self.access.name().to_string())),
+      _ => None,
+    }
+  }
+}
+
+/// `ProcessProtectionLevelInfo`, missing from winapi's `PROCESS_INFORMATION_CLASS`.
+#[cfg(target_os = "windows")]
+const PROCESS_PROTECTION_LEVEL_INFO: PROCESS_INFORMATION_CLASS = 7;
+/// `PROTECTION_LEVEL_NONE`, what a process that isn't protected reports.
+#[cfg(target_os = "windows")]
+const PROTECTION_LEVEL_NONE: u32 = 0xFFFFFFFE;
+
+/// Whether a Kenjection can open a process with the rights it needs, and if not what stands in the way.
+/// The strings are the error `OpenProcess` failed with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessStatus {
+  Accessible,
+  /// Runs as administrator while we don't.
+  Elevated(String),
+  /// A protected process (PPL), even an administrator with `SeDebugPrivilege` can't write to it.
+  Protected(String),
+  /// Another user's or a service process whose security descriptor keeps us out.
+  Denied(String),
+  /// Gone since the process list was taken.
+  Exited,
+  Failed(String),
+}
+
+impl AccessStatus {
+  pub fn is_accessible(&self) -> bool { *self == Self::Accessible }
+
+  /// One word for the list and the search filter.
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::Accessible => "ok",
+      Self::Elevated(_) => "elevated",
+      Self::Protected(_) => "protected",
+      Self::Denied(_) => "denied",
+      Self::Exited => "exited",
+      Self::Failed(_) => "failed",
+    }
+  }
+
+  /// The error opening the process ran into, empty when there was none, e.g. for bug report exports.
+  pub fn error(&self) -> &str {
+    match self {
+      Self::Elevated(e) | Self::Protected(e) | Self::Denied(e) | Self::Failed(e) => e,
+      Self::Accessible | Self::Exited => "",
+    }
+  }
+
+  /// Why the process can't be Kenjected into and what would fix it, for tooltips and errors.
+  pub fn reason(&self) -> String {
+    match self {
+      Self::Accessible => String::from("Can be Kenjected into"),
+      Self::Elevated(e) if cfg!(target_os = "linux") => format!("Runs as root, restart Kenjector with sudo to Kenject into it ({})", e),
+      Self::Elevated(e) => format!("Runs as administrator, restart Kenjector as administrator to Kenject into it ({})", e),
+      Self::Protected(e) if cfg!(target_os = "linux") => format!("Not dumpable, only a process with CAP_SYS_PTRACE can attach to it ({})", e),
+      Self::Protected(e) => format!("Protected process (PPL), Windows keeps everyone from writing to it, administrators included ({})", e),
+      Self::Denied(e) => format!("Access denied, it belongs to another user or the system. Running as administrator may help ({})", e),
+      Self::Exited => String::from("The process has exited, refresh the list"),
+      Self::Failed(e) => format!("Failed to open the process, {}", e),
+    }
+  }
 }
 
 #[derive(Debug, Clone, Display)]
@@ -21,6 +115,67 @@ pub struct KenjectionInfo {
   pub process_id: u32,
 }
 
+/// A DLL loaded into a target by [`Kenjector::kennject`].
+#[derive(Debug, Clone)]
+pub struct Kenjected {
+  pub base: u64,
+  /// What went wrong after the DLL was loaded, e.g. freeing the path copied into the target.
+  pub warning: Option<String>,
+}
+
+impl Kenjected {
+  /// Add `warning` to the ones already there.
+  pub fn warn(&mut self, warning: Option<String>) {
+    self.warning = match (self.warning.take(), warning) {
+      (Some(a), Some(b)) => Some(format!("{}. {}", a, b)),
+      (a, b) => a.or(b),
+    };
+  }
+}
+
+/// A module loaded in another process, from a Toolhelp32 module snapshot.
+#[derive(Debug, Clone)]
+pub struct RemoteModule {
+  pub name: String,
+  pub path: PathBuf,
+  pub base: u64,
+  pub size: u32,
+}
+
+/// How long a Kenjection may wait on the target, and a flag to give up sooner. Clones share the flag,
+/// so the UI can cancel a Kenjection running on another thread.
+#[derive(Debug, Clone)]
+pub struct Deadline {
+  pub timeout: Duration,
+  started: Instant,
+  cancelled: Arc<AtomicBool>,
+}
+
+impl Deadline {
+  /// A deadline `timeout` from now.
+  pub fn new(timeout: Duration) -> Self { Self { timeout, started: Instant::now(), cancelled: Arc::new(AtomicBool::new(false)) } }
+
+  /// A deadline the same timeout from now for the next stage, e.g. the init export after `LoadLibraryW`.
+  /// It shares the flag, so cancelling this one stops both.
+  pub fn restart(&self) -> Self { Self { timeout: self.timeout, started: Instant::now(), cancelled: self.cancelled.clone() } }
+
+  pub fn cancel(&self) { self.cancelled.store(true, Ordering::Relaxed) }
+
+  pub fn is_cancelled(&self) -> bool { self.cancelled.load(Ordering::Relaxed) }
+
+  /// Whether to stop waiting, because the timeout passed or the Kenjection was cancelled.
+  pub fn is_over(&self) -> bool { self.is_cancelled() || self.started.elapsed() >= self.timeout }
+
+  /// Why waiting for `what` to return stopped, once [`Self::is_over`].
+  pub fn error(&self, what: &str) -> String {
+    if self.is_cancelled() {
+      format!("Cancelled while waiting for {} to return. The target may be left in an inconsistent state", what)
+    } else {
+      format!("{} did not return within {} ms. The target may be left in an inconsistent state", what, self.timeout.as_millis())
+    }
+  }
+}
+
 #[derive(Debug, Default)]
 struct VersionInfo {
   product_version: String,
@@ -41,6 +196,26 @@ pub enum Arch {
   Unknown,
 }
 
+impl Arch {
+  /// The architecture this build of Kenjector runs as.
+  pub fn native() -> Self {
+    if cfg!(target_arch = "x86_64") {
+      Self::AMDx64
+    } else if cfg!(target_arch = "x86") {
+      Self::AMDx86
+    } else if cfg!(target_arch = "aarch64") {
+      Self::Arm64
+    } else {
+      Self::Unknown
+    }
+  }
+
+  /// Whether this build can inject into a process of this architecture: the same one, or a
+  /// 32-bit process under WOW64 from a 64-bit x86 build.
+  pub fn can_target(self) -> bool { self != Self::Unknown && (self == Self::native() || (self == Self::AMDx86 && Self::native() == Self::AMDx64)) }
+}
+
+#[cfg(target_os = "windows")]
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
 pub enum Access {
@@ -51,106 +226,482 @@ pub enum Access {
 #[derive(Debug, Copy, Clone)]
 pub struct Kenjector {}
 impl Kenjector {
-  pub fn kennject(kenjection_info: &KenjectionInfo, path: PathBuf) -> Result<String, String> {
-    let process_id = Self::get_pid(&kenjection_info.name).map_err(|e| format!("Failed to get PID: {}", e))?;
-    let dll_str = path.to_str().ok_or("Invalid DLL path")?;
-    let dll_cstring = CString::new(dll_str).map_err(|_| "CString conversion failed")?;
-    println!("DLL path being injected: {:?}", dll_cstring);
+  /// Load the DLL at `path` into the target with `method`, returns the module base reported by `LoadLibraryW`,
+  /// or what the DLL's loader returned for [`InjectionMethod::ReflectiveLoader`]. Cleaning up in the
+  /// target failing after the DLL loaded is a warning, not an error. Waiting on the target stops at `deadline`.
+  #[cfg(target_os = "windows")]
+  pub fn kennject(kenjection_info: &KenjectionInfo, path: PathBuf, method: InjectionMethod, deadline: &Deadline) -> Result<Kenjected, String> {
+    // The process picked, not the first one with its name
+    let process_id = kenjection_info.process_id;
+    method.supports(process_id)?;
+    // Fail with the reason now rather than on whichever call the missing right first breaks
+    let we_elevated = Self::running_elevated();
+    let status = Self::access_status(process_id, method.info().process_access, we_elevated);
+    if !status.is_accessible() {
+      return Err(format!("Can't Kenject into {}, {}", kenjection_info.name, status.reason()));
+    }
+    if method == InjectionMethod::ReflectiveLoader {
+      return Self::kennject_reflective(process_id, &path, deadline);
+    }
+    let path = winpath::normalize_for_injection(&path)?;
+    let dll_wide = winpath::path_to_wide(&path);
+
+    // Freed by finish below, or on drop when an early return skips it
+    let mut resources = RemoteResources::open(process_id, method.info().process_access)?;
+    let remote_path = resources.alloc_copy(&dll_wide, PAGE_READWRITE)?;
+
+    let remote_result = match method {
+      InjectionMethod::CreateRemoteThread => Self::remote_proc_address(process_id, "kernel32.dll", "LoadLibraryW").and_then(|load_library| unsafe { Self::create_remote_thread(&mut resources, "LoadLibraryW", load_library, remote_path, deadline) }),
+      InjectionMethod::ThreadHijack => match (Self::architecture(resources.process()), Self::remote_proc_address(process_id, "kernel32.dll", "LoadLibraryW")) {
+        (Ok(arch), Ok(load_library)) => hijack::load_library(&mut resources, process_id, arch, load_library, remote_path, deadline),
+        (Err(e), _) => Err(e.to_string()),
+        (_, Err(e)) => Err(e),
+      },
+      InjectionMethod::QueueApc => match (Self::architecture(resources.process()), Self::remote_proc_address(process_id, "kernel32.dll", "LoadLibraryW")) {
+        (Ok(arch), Ok(load_library)) => Self::queue_apc(process_id, arch, load_library, remote_path).and_then(|queued| {
+          // Every thread that took the APC reads the path whenever it gets to run it, even after one
+          // of them has loaded the DLL, so the path has to stay
+          let kept = resources.keep_allocations();
+          match Self::wait_for_module(process_id, &path, deadline)? {
+            Some(module) => Ok(module.base),
+            None if deadline.is_cancelled() => Err(format!("Cancelled, LoadLibraryW stays queued to {} threads and loads the DLL once one of them enters an alertable wait. {}", queued, kept)),
+            None => Err(format!("LoadLibraryW was queued to {} threads but the DLL did not load within {} ms. It loads once a thread enters an alertable wait, check the history entry later. {}", queued, deadline.timeout.as_millis(), kept)),
+          }
+        }),
+        (Err(e), _) => Err(e.to_string()),
+        (_, Err(e)) => Err(e),
+      },
+      InjectionMethod::ReflectiveLoader => unreachable!("reflective DLLs are written by kennject_reflective"),
+    };
 
-    unsafe {
-      let h_process = Self::open_process(Access::Full, process_id).unwrap();
-      if h_process.is_null() {
-        return Err(format!("OpenProcess failed, error: {:#X?}", std::io::Error::last_os_error()));
-      }
+    let (remote_result, warning) = resources.finish(remote_result)?;
+
+    // CreateRemoteThread only gets the low 32 bits of the HMODULE, look the module up to get all of it
+    let found = Self::find_module(process_id, &path);
+    let (base, lookup_warning) = Self::loaded_base(remote_result, method == InjectionMethod::CreateRemoteThread, found)?;
+    let mut kenjected = Kenjected { base, warning };
+    kenjected.warn(lookup_warning);
+    Ok(kenjected)
+  }
 
-      // println!("h_process = {:X?}, process_id = {}, kenjection_info.process_id = {}, kenjection_info.name = {}", h_process, process_id, kenjection_info.process_id, kenjection_info.name);
+  /// The base of the DLL `LoadLibraryW` returned `remote_result` for, from `found`, the module lookup
+  /// after it. Without the lookup `remote_result` is only used if it isn't `truncated` to a thread's
+  /// 32-bit exit code, a cut off base would send later export calls to the wrong address.
+  #[cfg(any(target_os = "windows", test))]
+  fn loaded_base(remote_result: u64, truncated: bool, found: Result<Option<RemoteModule>, String>) -> Result<(u64, Option<String>), String> {
+    match found {
+      Ok(Some(module)) => Ok((module.base, None)),
+      Ok(None) if remote_result == 0 => Err(String::from("LoadLibraryW failed — did not load DLL.")),
+      Ok(None) => Err(format!("LoadLibraryW returned {:#X} but the DLL is not in the target's module list", remote_result)),
+      Err(e) if remote_result == 0 => Err(e),
+      Err(e) if truncated => Err(format!("LoadLibraryW returned {:#X}, which may be cut to 32 bits, and the module snapshot to get the full base failed, error: {}", remote_result, e)),
+      Err(e) => Ok((remote_result, Some(format!("The module snapshot failed, reporting the base LoadLibraryW returned, error: {}", e)))),
+    }
+  }
 
-      let alloc = VirtualAllocEx(h_process, std::ptr::null_mut(), dll_cstring.to_bytes_with_nul().len(), MEM_COMMIT, PAGE_READWRITE);
+  /// Load the shared object at `path` into the target with `method`, returns its load base. Waiting on
+  /// the target stops at `deadline`.
+  #[cfg(target_os = "linux")]
+  pub fn kennject(kenjection_info: &KenjectionInfo, path: PathBuf, method: InjectionMethod, deadline: &Deadline) -> Result<Kenjected, String> {
+    // Checks what ptrace_scope and the target's ids allow too, with the reason
+    method.supports(kenjection_info.process_id)?;
+    match method {
+      InjectionMethod::Ptrace => ptrace::load_library(kenjection_info.process_id, &path, deadline).map(|base| Kenjected { base, warning: None }),
+    }
+  }
 
-      if alloc.is_null() {
-        CloseHandle(h_process);
-        return Err(format!("VirtualAllocEx failed, error: {:#X?}", std::io::Error::last_os_error()));
-      }
+  /// Write the DLL at `path` into the process as is and start its `ReflectiveLoader` export, the target
+  /// never sees a `LoadLibraryW` call or a path.
+  #[cfg(target_os = "windows")]
+  fn kennject_reflective(process_id: u32, path: &Path, deadline: &Deadline) -> Result<Kenjected, String> {
+    let image = std::fs::read(path).map_err(|e| format!("Failed to read {}, error: {}", path.display(), e))?;
+    let loader = reflective::find_loader(&image)?.ok_or_else(|| format!("{} has no ReflectiveLoader export", path.display()))?;
+
+    let mut resources = RemoteResources::open(process_id, InjectionMethod::ReflectiveLoader.info().process_access)?;
+    let remote_result = match Self::architecture(resources.process()) {
+      Ok(arch) if arch == loader.arch => reflective::load(&mut resources, &image, &loader, deadline),
+      Ok(arch) => Err(format!("{} is a {} DLL but the process is {}", path.display(), loader.arch, arch)),
+      Err(e) => Err(e.to_string()),
+    };
+
+    match resources.finish(remote_result)? {
+      (0, _) => Err(format!("{} returned 0 — did not load DLL.", loader.name)),
+      (base, warning) => Ok(Kenjected { base, warning }),
+    }
+  }
+
+  /// Whether the DLL at `path` exports [`INIT_EXPORT`], so it can be handed a channel after a Kenjection.
+  pub fn has_init_export(path: &Path) -> bool { Self::export_rva(path, INIT_EXPORT).is_ok() }
+
+  /// Run the [`INIT_EXPORT`] of the DLL at `path`, loaded at `module_base`, with `config` on a new thread
+  /// of the target. Returns what the export returned, 0 for success, and what failed cleaning up after it.
+  #[cfg(target_os = "windows")]
+  pub fn call_init(process_id: u32, path: &Path, module_base: u64, config: &str, deadline: &Deadline) -> Result<(u32, Option<String>), String> {
+    let init = Self::remote_export_address(module_base, path, INIT_EXPORT)?;
+    let config = CString::new(config).map_err(|_| String::from("The init config contains a nul byte"))?;
+    let mut resources = RemoteResources::open(process_id, InjectionMethod::CreateRemoteThread.info().process_access)?;
+    let remote_config = resources.alloc_copy(config.as_bytes_with_nul(), PAGE_READWRITE)?;
+    let code = unsafe { Self::create_remote_thread(&mut resources, INIT_EXPORT, init, remote_config, deadline) };
+    resources.finish(code).map(|(code, warning)| (code as u32, warning))
+  }
 
-      let wrote = WriteProcessMemory(h_process, alloc, dll_cstring.as_ptr() as _, dll_cstring.to_bytes_with_nul().len(), std::ptr::null_mut());
+  /// Run the [`INIT_EXPORT`] of the shared object at `path`, loaded at `module_base`, with `config` on
+  /// the target's main thread. Returns what the export returned, 0 for success, and no warning, as the
+  /// stub page is unmapped in [`ptrace::call`].
+  #[cfg(target_os = "linux")]
+  pub fn call_init(process_id: u32, path: &Path, module_base: u64, config: &str, deadline: &Deadline) -> Result<(u32, Option<String>), String> {
+    let init = Self::remote_export_address(module_base, path, INIT_EXPORT)?;
+    let config = CString::new(config).map_err(|_| String::from("The init config contains a nul byte"))?;
+    ptrace::call(process_id, INIT_EXPORT, init, config.as_bytes_with_nul(), |config| vec![config], deadline).map(|code| (code as u32, None))
+  }
 
-      if wrote == 0 {
-        CloseHandle(h_process);
-        return Err(format!("WriteProcessMemory failed, error: {:#X?}", std::io::Error::last_os_error()));
+  /// Unload the DLL loaded from `path`, returns the base it was loaded at. A payload exporting
+  /// [`UNLOAD_EXPORT`] is asked to stop first, and left loaded when it can't, as freeing it under a
+  /// running thread would crash the target.
+  #[cfg(target_os = "windows")]
+  pub fn eject(process_id: u32, path: &Path, deadline: &Deadline) -> Result<u64, String> {
+    let module = Self::find_module(process_id, path)?.ok_or_else(|| format!("{} is not loaded in process {}", path.display(), process_id))?;
+    let mut resources = RemoteResources::open(process_id, InjectionMethod::CreateRemoteThread.info().process_access)?;
+    if let Ok(unload) = Self::remote_export_address(module.base, path, UNLOAD_EXPORT) {
+      match unsafe { Self::create_remote_thread(&mut resources, UNLOAD_EXPORT, unload, 0, deadline) }? {
+        0 => {}
+        code => return Err(format!("The payload's unload export returned {}, it is still running so it was left loaded", code)),
       }
+    }
+
+    let free_library = Self::remote_proc_address(process_id, "kernel32.dll", "FreeLibrary")?;
+    if unsafe { Self::create_remote_thread(&mut resources, "FreeLibrary", free_library, module.base, deadline) }? == 0 {
+      return Err(format!("FreeLibrary failed for {} in process {}", module.name, process_id));
+    }
+    // A DLL loaded more than once stays until each load is freed
+    match Self::find_module(process_id, path)? {
+      Some(_) => Err(format!("{} is still loaded in process {}, it was loaded more than once", module.name, process_id)),
+      None => Ok(module.base),
+    }
+  }
 
-      let kernel32 = GetModuleHandleA(b"kernel32.dll\0".as_ptr() as _);
-      let load_library = GetProcAddress(kernel32, b"LoadLibraryA\0".as_ptr() as _);
-      if load_library.is_null() {
-        CloseHandle(h_process);
-        return Err(format!("GetProcAddress failed, error: {:#X?}", std::io::Error::last_os_error()));
+  /// Unload the shared object loaded from `path`, returns the base it was loaded at. A payload exporting
+  /// [`UNLOAD_EXPORT`] is asked to stop first, as on Windows.
+  #[cfg(target_os = "linux")]
+  pub fn eject(process_id: u32, path: &Path, deadline: &Deadline) -> Result<u64, String> {
+    let module = Self::find_module(process_id, path)?.ok_or_else(|| format!("{} is not loaded in process {}", path.display(), process_id))?;
+    if let Ok(unload) = Self::remote_export_address(module.base, path, UNLOAD_EXPORT) {
+      match ptrace::call(process_id, UNLOAD_EXPORT, unload, &[], |_| vec![0], deadline)? as u32 {
+        0 => {}
+        code => return Err(format!("The payload's unload export returned {}, it is still running so it was left loaded", code)),
       }
+    }
+
+    ptrace::unload_library(process_id, &module.path, deadline)?;
+    // glibc keeps an object loaded while another one depends on it, or when it has thread-local destructors registered
+    match Self::find_module(process_id, path)? {
+      Some(_) => Err(format!("{} is still loaded in process {}, it was loaded more than once or can't be unloaded", module.name, process_id)),
+      None => Ok(module.base),
+    }
+  }
 
-      let thread = CreateRemoteThread(h_process, std::ptr::null_mut(), 0, Some(std::mem::transmute(load_library)), alloc, 0, std::ptr::null_mut());
+  /// Run `start(parameter)` on a new thread of the target and wait for it, returns the thread's exit code.
+  /// `what` names the call in the error when `deadline` passes first.
+  /// A thread still running at `deadline` keeps everything in `resources` allocated, as it may be using it.
+  #[cfg(target_os = "windows")]
+  pub unsafe fn create_remote_thread(resources: &mut RemoteResources, what: &str, start: u64, parameter: u64, deadline: &Deadline) -> Result<u64, String> {
+    unsafe {
+      let thread = CreateRemoteThread(resources.process(), std::ptr::null_mut(), 0, Some(std::mem::transmute(start as usize)), parameter as _, 0, std::ptr::null_mut());
 
       if thread.is_null() {
-        CloseHandle(h_process);
         return Err(format!("CreateRemoteThread failed, error: {:#X?}", std::io::Error::last_os_error()));
       }
+      resources.track(thread);
 
-      WaitForSingleObject(thread, INFINITE);
+      // A DllMain that deadlocks, e.g. on the loader lock, never returns
+      loop {
+        match WaitForSingleObject(thread, 50) {
+          WAIT_OBJECT_0 => break,
+          WAIT_FAILED => return Err(format!("WaitForSingleObject failed, error: {:#X?}", std::io::Error::last_os_error())),
+          _ if deadline.is_over() => return Err(format!("{}. {}", deadline.error(what), resources.keep_allocations())),
+          _ => {}
+        }
+      }
 
       let mut remote_result: u32 = 0;
-      let got = GetExitCodeThread(thread, &mut remote_result);
+      if GetExitCodeThread(thread, &mut remote_result) == 0 {
+        return Err(format!("GetExitCodeThread failed, error: {:#X?}", std::io::Error::last_os_error()));
+      }
+
+      Ok(remote_result as u64)
+    }
+  }
 
-      CloseHandle(thread);
-      CloseHandle(h_process);
+  /// Queue `LoadLibraryW(remote_path)` as a user APC to every thread of the process, returns how many
+  /// threads accepted it. The APC runs the next time a thread enters an alertable wait.
+  #[cfg(target_os = "windows")]
+  fn queue_apc(process_id: u32, arch: Arch, load_library: u64, remote_path: u64) -> Result<usize, String> {
+    // A 64-bit injector queues APCs to a WOW64 thread through the 64-bit layer, which only runs
+    // 32-bit routines encoded as -(routine << 2), as RtlQueueApcWow64Thread does
+    let routine = if arch != Arch::native() { (load_library as i64).wrapping_shl(2).wrapping_neg() as u64 } else { load_library };
 
-      if got == 0 {
-        Ok(format!("GetExitCodeThread failed."))
-      } else if remote_result == 0 {
-        Ok(format!("LoadLibraryA failed — did not load DLL."))
-      } else {
-        Ok(format!("DLL Kenjected successfully at 0x{:X}", remote_result))
+    let mut queued = 0;
+    for thread_id in Self::get_thread_ids(process_id)? {
+      unsafe {
+        let thread = OpenThread(THREAD_SET_CONTEXT, 0, thread_id);
+        if thread.is_null() {
+          continue;
+        }
+
+        if QueueUserAPC(std::mem::transmute::<usize, PAPCFUNC>(routine as usize), thread, remote_path as usize) != 0 {
+          queued += 1;
+        }
+
+        CloseHandle(thread);
+      }
+    }
+
+    if queued == 0 {
+      return Err(format!("QueueUserAPC failed for every thread, error: {:#X?}", std::io::Error::last_os_error()));
+    }
+
+    Ok(queued)
+  }
+
+  /// Poll the module list until the DLL at `path` shows up or `deadline` is over.
+  #[cfg(target_os = "windows")]
+  pub fn wait_for_module(process_id: u32, path: &Path, deadline: &Deadline) -> Result<Option<RemoteModule>, String> {
+    loop {
+      if let Some(module) = Self::find_module(process_id, path)? {
+        return Ok(Some(module));
+      }
+      if deadline.is_over() {
+        return Ok(None);
       }
+      std::thread::sleep(Duration::from_millis(100));
     }
   }
 
-  fn get_pid(name: &str) -> Result<u32, String> {
+  /// IDs of every thread of the process.
+  #[cfg(target_os = "windows")]
+  pub fn get_thread_ids(process_id: u32) -> Result<Vec<u32>, String> {
     unsafe {
-      let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
-      if snapshot == std::ptr::null_mut() {
+      let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+      if snapshot == INVALID_HANDLE_VALUE {
         return Err(format!("CreateToolhelp32Snapshot failed, error: {:#X?}", std::io::Error::last_os_error()));
       }
 
-      let mut entry: PROCESSENTRY32 = std::mem::zeroed();
-      entry.dwSize = std::mem::size_of::<PROCESSENTRY32>() as u32;
+      let mut entry: THREADENTRY32 = std::mem::zeroed();
+      entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
 
-      if Process32First(snapshot, &mut entry) == 0 {
-        CloseHandle(snapshot);
-        return Err(format!("Process32First failed, error: {:#X?}", std::io::Error::last_os_error()));
-      }
+      // The snapshot holds the threads of every process, keep the target's
+      let mut thread_ids = Vec::new();
+      if Thread32First(snapshot, &mut entry) != 0 {
+        loop {
+          if entry.th32OwnerProcessID == process_id {
+            thread_ids.push(entry.th32ThreadID);
+          }
 
-      loop {
-        let exe_name_cstr = CStr::from_ptr(entry.szExeFile.as_ptr());
-        if let Ok(exe_name) = exe_name_cstr.to_str() {
-          if exe_name.eq_ignore_ascii_case(name) {
-            CloseHandle(snapshot);
-            return Ok(entry.th32ProcessID);
+          if Thread32Next(snapshot, &mut entry) == 0 {
+            break;
           }
         }
+      }
+
+      CloseHandle(snapshot);
+      Ok(thread_ids)
+    }
+  }
 
-        if Process32Next(snapshot, &mut entry) == 0 {
+  /// Every module loaded in the process, including 32-bit modules of a WOW64 process.
+  #[cfg(target_os = "windows")]
+  pub fn get_modules(process_id: u32) -> Result<Vec<RemoteModule>, String> {
+    unsafe {
+      // The snapshot fails with ERROR_BAD_LENGTH while the target is loading or unloading modules, so retry
+      let mut snapshot = INVALID_HANDLE_VALUE;
+      for _ in 0..5 {
+        snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32, process_id);
+        if snapshot != INVALID_HANDLE_VALUE || GetLastError() != ERROR_BAD_LENGTH {
           break;
         }
       }
 
+      if snapshot == INVALID_HANDLE_VALUE {
+        return Err(format!("CreateToolhelp32Snapshot failed, error: {:#X?}", std::io::Error::last_os_error()));
+      }
+
+      let mut entry: MODULEENTRY32W = std::mem::zeroed();
+      entry.dwSize = std::mem::size_of::<MODULEENTRY32W>() as u32;
+
+      let mut modules = Vec::new();
+      if Module32FirstW(snapshot, &mut entry) != 0 {
+        loop {
+          modules.push(RemoteModule {
+            name: winpath::decode_wide(&entry.szModule),
+            path: PathBuf::from(winpath::decode_wide(&entry.szExePath)),
+            base: entry.modBaseAddr as u64,
+            size: entry.modBaseSize,
+          });
+
+          if Module32NextW(snapshot, &mut entry) == 0 {
+            break;
+          }
+        }
+      }
+
       CloseHandle(snapshot);
-      Err("Process not found".to_string())
+      Ok(modules)
+    }
+  }
+
+  /// Every shared object mapped into the process and its executable, from `/proc/<pid>/maps`.
+  #[cfg(target_os = "linux")]
+  pub fn get_modules(process_id: u32) -> Result<Vec<RemoteModule>, String> {
+    // The mappings of one object are in address order, the first is its load base
+    let mut modules: Vec<RemoteModule> = Vec::new();
+    for region in regions::query(process_id)?.into_iter().filter(|r| r.kind == Some(RegionType::Image)) {
+      match modules.iter_mut().find(|m| m.path.as_os_str() == region.owner.as_str()) {
+        Some(module) => module.size = (region.end() - module.base) as u32,
+        None => modules.push(RemoteModule {
+          name: Path::new(&region.owner).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| region.owner.clone()),
+          path: PathBuf::from(&region.owner),
+          base: region.base,
+          size: region.size as u32,
+        }),
+      }
+    }
+    Ok(modules)
+  }
+
+  /// Find the module loaded from `path` in the process.
+  #[cfg(target_os = "windows")]
+  pub fn find_module(process_id: u32, path: &Path) -> Result<Option<RemoteModule>, String> {
+    let wanted = path.to_string_lossy();
+    Ok(Self::get_modules(process_id)?.into_iter().find(|m| winpath::same_windows_path(&m.path.to_string_lossy(), &wanted)))
+  }
+
+  /// Find the module loaded from `path` in the process. The maps have the path with symlinks resolved,
+  /// as [`winpath::normalize_for_injection`] gives it.
+  #[cfg(target_os = "linux")]
+  pub fn find_module(process_id: u32, path: &Path) -> Result<Option<RemoteModule>, String> { Ok(Self::get_modules(process_id)?.into_iter().find(|m| m.path == path)) }
+
+  /// RVA of the export `export_name` in the DLL at `path`, PE32 or PE32+.
+  #[cfg(target_os = "windows")]
+  pub fn export_rva(path: &Path, export_name: &str) -> Result<u32, String> {
+    let file_map = FileMap::open(path).map_err(|e| format!("Failed to open {}, error: {}", path.display(), e))?;
+
+    let export = match Pe64File::from_bytes(file_map.as_ref()) {
+      Ok(file) => file.exports().and_then(|e| e.by()).and_then(|by| by.name(export_name)),
+      Err(_) => Pe32File::from_bytes(file_map.as_ref()).map_err(|e| format!("Failed to parse {}, error: {}", path.display(), e))?.exports().and_then(|e| e.by()).and_then(|by| by.name(export_name)),
+    };
+
+    match export {
+      Ok(pelite::pe64::exports::Export::Symbol(rva)) => Ok(*rva),
+      Ok(pelite::pe64::exports::Export::Forward(name)) => Err(format!("{} is forwarded to {}", export_name, name)),
+      Err(e) => Err(format!("Export {} not found in {}, error: {}", export_name, path.display(), e)),
     }
   }
 
+  /// Offset of the exported symbol `export_name` from the load base of the shared object at `path`.
+  #[cfg(target_os = "linux")]
+  pub fn export_rva(path: &Path, export_name: &str) -> Result<u32, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to open {}, error: {}", path.display(), e))?;
+    let elf = goblin::elf::Elf::parse(&bytes).map_err(|e| format!("Failed to parse {}, error: {}", path.display(), e))?;
+    // Undefined symbols, imports from other objects, have no value
+    let symbol = elf.dynsyms.iter().find(|s| s.st_value != 0 && elf.dynstrtab.get_at(s.st_name) == Some(export_name)).ok_or_else(|| format!("Export {} not found in {}", export_name, path.display()))?;
+    u32::try_from(symbol.st_value).map_err(|_| format!("{} is too far into {}", export_name, path.display()))
+  }
+
+  /// Address of `export_name` inside a module loaded at `module_base`, e.g. the base returned by [`Self::kennject`].
+  pub fn remote_export_address(module_base: u64, dll_path: &Path, export_name: &str) -> Result<u64, String> { Ok(module_base + Self::export_rva(dll_path, export_name)? as u64) }
+
+  /// Address of `export_name` in the target's copy of `module_name`, e.g. `LoadLibraryW` in a WOW64
+  /// process's 32-bit kernel32, which is not where our own kernel32 has it.
+  #[cfg(target_os = "windows")]
+  pub fn remote_proc_address(process_id: u32, module_name: &str, export_name: &str) -> Result<u64, String> {
+    let module = Self::get_modules(process_id)?.into_iter().find(|m| m.name.eq_ignore_ascii_case(module_name)).ok_or_else(|| format!("{} is not loaded in process {}", module_name, process_id))?;
+    Self::remote_export_address(module.base, &module.path, export_name)
+  }
+
+  #[cfg(target_os = "windows")]
   pub fn open_process(access: Access, process_id: u32) -> Result<HANDLE, Box<dyn std::error::Error>> {
     let handle = unsafe { OpenProcess(access as u32, 0, process_id) };
     if !handle.is_null() { Ok(handle) } else { Err(format!("Failed to retrieve handle of the process, process_id {}, error: {:#X?}", process_id, std::io::Error::last_os_error()).into()) }
   }
 
-  pub fn get_processes() -> Vec<ProcessInfo> {
+  /// Whether the process can be opened with `access` for a Kenjection and why not, `we_elevated` is
+  /// whether we run as administrator. Tells an elevated target from a protected or foreign one, which all fail with
+  /// `ERROR_ACCESS_DENIED`, by what the limited query handle still reveals.
+  #[cfg(target_os = "windows")]
+  pub fn access_status(process_id: u32, access: u32, we_elevated: bool) -> AccessStatus {
+    unsafe {
+      let limited = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id);
+      if limited.is_null() {
+        let error = std::io::Error::last_os_error();
+        return match error.raw_os_error().map(|code| code as u32) {
+          // The PID no longer names a process
+          Some(ERROR_INVALID_PARAMETER) => AccessStatus::Exited,
+          Some(ERROR_ACCESS_DENIED) => AccessStatus::Denied(error.to_string()),
+          _ => AccessStatus::Failed(error.to_string()),
+        };
+      }
+
+      let mut exit_code = 0;
+      let status = if GetExitCodeProcess(limited, &mut exit_code) != 0 && exit_code != STILL_ACTIVE {
+        AccessStatus::Exited
+      } else {
+        let process = OpenProcess(access, 0, process_id);
+        if !process.is_null() {
+          CloseHandle(process);
+          AccessStatus::Accessible
+        } else {
+          let error = std::io::Error::last_os_error();
+          let mut protection = PROTECTION_LEVEL_NONE;
+          let protected = GetProcessInformation(limited, PROCESS_PROTECTION_LEVEL_INFO, &mut protection as *mut _ as *mut _, std::mem::size_of::<u32>() as u32) != 0 && protection != PROTECTION_LEVEL_NONE;
+          match error.raw_os_error().map(|code| code as u32) {
+            Some(ERROR_ACCESS_DENIED) if protected => AccessStatus::Protected(error.to_string()),
+            Some(ERROR_ACCESS_DENIED) if !we_elevated && Self::is_elevated(limited).unwrap_or(false) => AccessStatus::Elevated(error.to_string()),
+            Some(ERROR_ACCESS_DENIED) => AccessStatus::Denied(error.to_string()),
+            _ => AccessStatus::Failed(error.to_string()),
+          }
+        }
+      };
+
+      CloseHandle(limited);
+      status
+    }
+  }
+
+  /// Whether ptrace may attach to the process and if not why, checked as [`PtraceAccess::preflight`]
+  /// does. `access` and `we_elevated` only matter on Windows.
+  #[cfg(target_os = "linux")]
+  pub fn access_status(process_id: u32, _access: u32, we_elevated: bool) -> AccessStatus {
+    match PtraceTarget::read(process_id) {
+      Ok(target) => match PtraceAccess::current().preflight(&target) {
+        Ok(()) => AccessStatus::Accessible,
+        Err(e) if target.uids[1] == 0 && !we_elevated => AccessStatus::Elevated(e),
+        Err(e) if !target.dumpable => AccessStatus::Protected(e),
+        Err(e) => AccessStatus::Denied(e),
+      },
+      Err(_) if !Path::new(&format!("/proc/{}", process_id)).exists() => AccessStatus::Exited,
+      Err(e) => AccessStatus::Failed(e),
+    }
+  }
+
+  /// Whether Kenjector itself runs as administrator, or as root on Linux.
+  pub fn running_elevated() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+      Self::is_elevated(unsafe { GetCurrentProcess() }).unwrap_or(false)
+    }
+    #[cfg(target_os = "linux")]
+    {
+      unsafe { libc::geteuid() == 0 }
+    }
+  }
+
+  pub fn get_processes() -> Vec<ProcessInfo> { Self::enumerate_processes(true) }
+
+  /// Same as [`Self::get_processes`] but skips icon extraction, which needs GTK to be initialised.
+  pub fn get_processes_without_icons() -> Vec<ProcessInfo> { Self::enumerate_processes(false) }
+
+  #[cfg(target_os = "windows")]
+  fn enumerate_processes(with_icons: bool) -> Vec<ProcessInfo> {
     let mut processes: Vec<ProcessInfo> = Vec::new();
 
     unsafe {
@@ -170,29 +721,25 @@ impl Kenjector {
         return processes;
       }
 
+      let we_elevated = Self::running_elevated();
       loop {
         let process_id = process_entry.th32ProcessID;
         let mut arch = Arch::Unknown;
-        let mut elevated = true;
-
-        let process = Self::open_process(Access::Limited, process_id);
+        let mut elevated = false;
 
-        if process.is_ok() {
-          let process = process.unwrap();
-          elevated = match Self::is_elevated(process) {
-            Ok(v) => v,
-            Err(_) => true,
-          };
-
-          arch = match Self::architecture(process) {
-            Ok(v) => v,
-            Err(_) => Arch::Unknown,
-          };
+        if let Ok(process) = Self::open_process(Access::Limited, process_id) {
+          elevated = Self::is_elevated(process).unwrap_or(false);
+          arch = Self::architecture(process).unwrap_or(Arch::Unknown);
+          CloseHandle(process);
         }
+        // What the default method needs, the others need about the same
+        let access = Self::access_status(process_id, InjectionMethod::CreateRemoteThread.info().process_access, we_elevated);
 
         let name = CStr::from_ptr(process_entry.szExeFile.as_ptr()).to_string_lossy().into_owned();
 
-        processes.push(ProcessInfo { icon: Self::get_process_icon(process_id), elevated, name, arch, process_id });
+        let icon = if with_icons { Self::get_process_icon(process_id) } else { None };
+
+        processes.push(ProcessInfo { icon, elevated, name, arch, process_id, favourite: false, access });
 
         // Get next process
         if Process32Next(snapshot, &mut process_entry) == 0 {
@@ -207,6 +754,31 @@ impl Kenjector {
     processes
   }
 
+  /// Every process in `/proc`. Linux executables carry no icon, so there are none.
+  #[cfg(target_os = "linux")]
+  fn enumerate_processes(_with_icons: bool) -> Vec<ProcessInfo> {
+    let Ok(entries) = std::fs::read_dir("/proc") else { return Vec::new() };
+    let we_elevated = Self::running_elevated();
+    let mut processes = entries
+      .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+      .filter_map(|process_id| {
+        // The executable's name like Toolhelp32 gives it, `comm` is cut at 15 bytes and all there is for kernel threads
+        let name = match std::fs::read_link(format!("/proc/{}/exe", process_id)) {
+          Ok(exe) => exe.file_name()?.to_string_lossy().into_owned(),
+          // Gone since the directory was read when this fails too
+          Err(_) => std::fs::read_to_string(format!("/proc/{}/comm", process_id)).ok()?.trim_end().to_string(),
+        };
+        let elevated = PtraceTarget::read(process_id).is_ok_and(|target| target.uids[1] == 0);
+        let arch = Self::architecture(process_id).unwrap_or(Arch::Unknown);
+        let access = Self::access_status(process_id, 0, we_elevated);
+        Some(ProcessInfo { icon: None, elevated, name, arch, process_id, favourite: false, access })
+      })
+      .collect::<Vec<_>>();
+    processes.sort_by_key(|p| p.process_id);
+    processes
+  }
+
+  #[cfg(target_os = "windows")]
   pub fn is_elevated(process: HANDLE) -> Result<bool, Box<dyn std::error::Error>> {
     unsafe {
       let mut token = std::ptr::null_mut();
@@ -230,6 +802,7 @@ impl Kenjector {
     }
   }
 
+  #[cfg(target_os = "windows")]
   pub fn architecture(process: HANDLE) -> Result<Arch, Box<dyn std::error::Error>> {
     let mut process_machine = 0;
     let mut native_machine = 0;
@@ -243,16 +816,42 @@ impl Kenjector {
     match (process_machine, native_machine) {
       (IMAGE_FILE_MACHINE_UNKNOWN, IMAGE_FILE_MACHINE_AMD64) => Ok(Arch::AMDx64), // 64-bit native process
       (IMAGE_FILE_MACHINE_I386, IMAGE_FILE_MACHINE_AMD64) => Ok(Arch::AMDx86),    // 32-bit on 64-bit
-      (IMAGE_FILE_MACHINE_I386, IMAGE_FILE_MACHINE_I386) => Ok(Arch::AMDx86),     // 32-bit on 32-bit
+      (IMAGE_FILE_MACHINE_UNKNOWN, IMAGE_FILE_MACHINE_I386) => Ok(Arch::AMDx86),  // 32-bit on 32-bit
       (IMAGE_FILE_MACHINE_UNKNOWN, IMAGE_FILE_MACHINE_ARM64) => Ok(Arch::Arm64),
       _ => Ok(Arch::Unknown),
     }
   }
 
-  pub fn is_pe_dll(path: &PathBuf) -> Result<bool, Box<dyn std::error::Error>> {
+  /// Architecture of the process, from the ELF header of its executable.
+  #[cfg(target_os = "linux")]
+  pub fn architecture(process_id: u32) -> Result<Arch, String> {
+    use goblin::elf::header::{EM_386, EM_AARCH64, EM_X86_64};
+    use std::io::Read;
+
+    let mut header = [0u8; goblin::elf::header::header64::SIZEOF_EHDR];
+    std::fs::File::open(format!("/proc/{}/exe", process_id)).and_then(|mut exe| exe.read_exact(&mut header)).map_err(|e| format!("Failed to read the executable of process {}, error: {}", process_id, e))?;
+    let header = goblin::elf::Elf::parse_header(&header).map_err(|e| format!("The executable of process {} is not an ELF file, error: {}", process_id, e))?;
+    Ok(match header.e_machine {
+      EM_X86_64 => Arch::AMDx64,
+      EM_386 => Arch::AMDx86,
+      EM_AARCH64 => Arch::Arm64,
+      _ => Arch::Unknown,
+    })
+  }
+
+  /// Whether the file at `path` is something Kenjector can load: a DLL on Windows, a shared object on Linux.
+  pub fn is_library(path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
     let bytes = std::fs::read(path)?;
-    let pe = goblin::pe::PE::parse(&bytes)?;
-    Ok(pe.header.coff_header.characteristics & goblin::pe::characteristic::IMAGE_FILE_DLL != 0)
+    #[cfg(target_os = "windows")]
+    {
+      let pe = goblin::pe::PE::parse(&bytes)?;
+      Ok(pe.header.coff_header.characteristics & goblin::pe::characteristic::IMAGE_FILE_DLL != 0)
+    }
+    #[cfg(target_os = "linux")]
+    {
+      let header = goblin::elf::Elf::parse_header(&bytes)?;
+      Ok(header.e_type == goblin::elf::header::ET_DYN)
+    }
   }
 
   // pub fn get_version_info(path: &PathBuf) -> Result<VersionInfo, Box<dyn std::error::Error>> {
@@ -332,6 +931,7 @@ impl Kenjector {
     return Ok(version_info);
   }
 
+  #[cfg(target_os = "windows")]
   pub fn get_process_icon(process_id: u32) -> Option<gtk4::gdk::Paintable> {
     let process;
 
@@ -347,6 +947,7 @@ impl Kenjector {
   }
 
   // Retrieves the first large icon from a process's executable
+  #[cfg(target_os = "windows")]
   fn get_process_hicon(process: HANDLE) -> Result<winapi::shared::windef::HICON, Box<dyn std::error::Error>> {
     // Buffer for executable path (supports long paths)
     const BUF_SIZE: usize = 0x8000;
@@ -381,6 +982,7 @@ impl Kenjector {
     }
   }
 
+  #[cfg(target_os = "windows")]
   fn hicon_to_paintable(hicon: HICON) -> Option<gtk4::gdk::Paintable> {
     unsafe {
       // 1) Retrieve ICONINFO to get the HBITMAP for color
@@ -538,3 +1140,59 @@ impl GtkHelper {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn loaded_base_needs_the_full_base() {
+    let module = RemoteModule {
+      name: String::from("payload.dll"),
+      path: PathBuf::from(r"C:\payload.dll"),
+      base: 0x7FF8_1234_0000,
+      size: 0x1000,
+    };
+    assert_eq!(Kenjector::loaded_base(0x1234_0000, true, Ok(Some(module))), Ok((0x7FF8_1234_0000, None)));
+    assert_eq!(Kenjector::loaded_base(0, true, Ok(None)), Err(String::from("LoadLibraryW failed — did not load DLL.")));
+    assert_eq!(Kenjector::loaded_base(0, true, Err(String::from("snapshot failed"))), Err(String::from("snapshot failed")));
+    // A thread exit code can't be used as the base, a hijacked thread's full return value can
+    let error = Kenjector::loaded_base(0x1234_0000, true, Err(String::from("snapshot failed"))).unwrap_err();
+    assert!(error.starts_with("LoadLibraryW returned 0x12340000, which may be cut to 32 bits") && error.ends_with("snapshot failed"), "{}", error);
+    let (base, warning) = Kenjector::loaded_base(0x7FF8_1234_0000, false, Err(String::from("snapshot failed"))).unwrap();
+    assert_eq!(base, 0x7FF8_1234_0000);
+    assert!(warning.unwrap().ends_with("snapshot failed"));
+  }
+
+  #[test]
+  fn restarted_deadline_gets_the_full_timeout_and_shares_cancel() {
+    let deadline = Deadline::new(Duration::from_millis(50));
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(deadline.is_over());
+    let next = deadline.restart();
+    assert!(!next.is_over());
+    deadline.cancel();
+    assert!(next.is_over() && next.is_cancelled());
+  }
+
+  #[test]
+  fn access_error_is_the_underlying_one() {
+    assert_eq!(AccessStatus::Accessible.error(), "");
+    assert_eq!(AccessStatus::Exited.error(), "");
+    assert_eq!(AccessStatus::Denied(String::from("Access is denied. (os error 5)")).error(), "Access is denied. (os error 5)");
+    assert_eq!(AccessStatus::Failed(String::from("EPERM")).error(), "EPERM");
+  }
+
+  #[test]
+  fn deadline_error_names_the_stage() {
+    let deadline = Deadline::new(Duration::from_millis(0));
+    assert!(deadline.is_over());
+    assert_eq!(deadline.error(INIT_EXPORT), format!("{} did not return within 0 ms. The target may be left in an inconsistent state", INIT_EXPORT));
+
+    let deadline = Deadline::new(Duration::from_secs(60));
+    assert!(!deadline.is_over());
+    deadline.clone().cancel();
+    assert!(deadline.is_over());
+    assert_eq!(deadline.error("FreeLibrary"), "Cancelled while waiting for FreeLibrary to return. The target may be left in an inconsistent state");
+  }
+}
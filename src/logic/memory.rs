@@ -0,0 +1,274 @@
+#[cfg(target_os = "windows")]
+use crate::logic::kenjector::{Arch, Kenjector};
+#[cfg(target_os = "linux")]
+use crate::logic::privilege::{PtraceAccess, PtraceTarget};
+use crate::logic::regions::{self, MemoryRegion};
+use std::ops::Range;
+#[cfg(target_os = "windows")]
+use winapi::um::{handleapi::CloseHandle, memoryapi::{ReadProcessMemory, VirtualProtectEx, WriteProcessMemory}, processthreadsapi::OpenProcess, winnt::{HANDLE, PAGE_EXECUTE_READWRITE, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_VM_OPERATION, PROCESS_VM_READ, PROCESS_VM_WRITE}};
+
+/// Granularity of [`ProcessMemory::read_lossy`], the smallest unit memory is mapped in.
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Another process's memory, for looking at and patching a target after a Kenjection.
+pub struct ProcessMemory {
+  pub process_id: u32,
+  #[cfg(target_os = "windows")]
+  process: HANDLE,
+  /// `/proc/<pid>/mem`, which writes through page protections the way a debugger does.
+  #[cfg(target_os = "linux")]
+  mem: std::fs::File,
+}
+
+impl ProcessMemory {
+  #[cfg(target_os = "windows")]
+  pub fn open(process_id: u32) -> Result<Self, String> {
+    let process = unsafe { OpenProcess(PROCESS_VM_READ | PROCESS_VM_WRITE | PROCESS_VM_OPERATION | PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id) };
+    if process.is_null() {
+      return Err(format!("OpenProcess failed, error: {:#X?}", std::io::Error::last_os_error()));
+    }
+    Ok(Self { process_id, process })
+  }
+
+  /// Open the process's memory, which takes the same access as attaching with ptrace.
+  #[cfg(target_os = "linux")]
+  pub fn open(process_id: u32) -> Result<Self, String> {
+    PtraceAccess::current().preflight(&PtraceTarget::read(process_id)?)?;
+    let mem = std::fs::OpenOptions::new().read(true).write(true).open(format!("/proc/{}/mem", process_id)).map_err(|e| format!("Failed to open the memory of process {}, error: {}", process_id, e))?;
+    Ok(Self { process_id, mem })
+  }
+
+  /// Fill `buffer` from `address`, failing unless all of it could be read.
+  #[cfg(target_os = "windows")]
+  pub fn read(&self, address: u64, buffer: &mut [u8]) -> Result<(), String> {
+    let mut read = 0;
+    if unsafe { ReadProcessMemory(self.process, address as _, buffer.as_mut_ptr() as _, buffer.len(), &mut read) } == 0 || read != buffer.len() {
+      return Err(format!("ReadProcessMemory failed at {:#X}, error: {:#X?}", address, std::io::Error::last_os_error()));
+    }
+    Ok(())
+  }
+
+  #[cfg(target_os = "linux")]
+  pub fn read(&self, address: u64, buffer: &mut [u8]) -> Result<(), String> {
+    use std::os::unix::fs::FileExt;
+    self.mem.read_exact_at(buffer, address).map_err(|e| format!("Reading {} bytes at {:#X} failed, error: {}", buffer.len(), address, e))
+  }
+
+  /// Write `data` to `address`. Read-only pages, e.g. code, are written too.
+  #[cfg(target_os = "windows")]
+  pub fn write(&self, address: u64, data: &[u8]) -> Result<(), String> {
+    unsafe {
+      if WriteProcessMemory(self.process, address as _, data.as_ptr() as _, data.len(), std::ptr::null_mut()) != 0 {
+        return Ok(());
+      }
+
+      // Most likely a read-only page, make it writable for as long as the write takes
+      let mut protect = 0;
+      if VirtualProtectEx(self.process, address as _, data.len(), PAGE_EXECUTE_READWRITE, &mut protect) == 0 {
+        return Err(format!("VirtualProtectEx failed at {:#X}, error: {:#X?}", address, std::io::Error::last_os_error()));
+      }
+      let written = WriteProcessMemory(self.process, address as _, data.as_ptr() as _, data.len(), std::ptr::null_mut());
+      let error = std::io::Error::last_os_error();
+      VirtualProtectEx(self.process, address as _, data.len(), protect, &mut protect);
+      if written == 0 {
+        return Err(format!("WriteProcessMemory failed at {:#X}, error: {:#X?}", address, error));
+      }
+      Ok(())
+    }
+  }
+
+  #[cfg(target_os = "linux")]
+  pub fn write(&self, address: u64, data: &[u8]) -> Result<(), String> {
+    use std::os::unix::fs::FileExt;
+    self.mem.write_all_at(data, address).map_err(|e| format!("Writing {} bytes at {:#X} failed, error: {}", data.len(), address, e))
+  }
+
+  /// Read `len` bytes from `address` a page at a time, bytes on pages that can't be read are `None`.
+  pub fn read_lossy(&self, address: u64, len: usize) -> Vec<Option<u8>> {
+    let mut bytes = Vec::with_capacity(len);
+    let end = address.saturating_add(len as u64);
+    let mut start = address;
+    while start < end {
+      let page_end = ((start / PAGE_SIZE) + 1).saturating_mul(PAGE_SIZE).min(end);
+      let mut page = vec![0u8; (page_end - start) as usize];
+      match self.read(start, &mut page) {
+        Ok(()) => bytes.extend(page.into_iter().map(Some)),
+        Err(_) => bytes.extend(std::iter::repeat_n(None, page.len())),
+      }
+      start = page_end;
+    }
+    bytes
+  }
+
+  /// How wide the target's pointers are, 4 bytes for a 32-bit process.
+  #[cfg(target_os = "windows")]
+  pub fn pointer_size(&self) -> Result<usize, String> {
+    match Kenjector::architecture(self.process).map_err(|e| e.to_string())? {
+      Arch::AMDx86 => Ok(4),
+      _ => Ok(8),
+    }
+  }
+
+  /// From the class byte of the executable's ELF header, 1 for 32-bit and 2 for 64-bit.
+  #[cfg(target_os = "linux")]
+  pub fn pointer_size(&self) -> Result<usize, String> {
+    use std::io::Read;
+    let mut ident = [0u8; 5];
+    std::fs::File::open(format!("/proc/{}/exe", self.process_id)).and_then(|mut exe| exe.read_exact(&mut ident)).map_err(|e| format!("Failed to read the executable of process {}, error: {}", self.process_id, e))?;
+    match ident {
+      [0x7F, b'E', b'L', b'F', 1] => Ok(4),
+      [0x7F, b'E', b'L', b'F', 2] => Ok(8),
+      _ => Err(format!("The executable of process {} is not an ELF file", self.process_id)),
+    }
+  }
+
+  /// The regions that can be read, see [`MemoryRegion::is_readable`].
+  pub fn readable_regions(&self) -> Result<Vec<Range<u64>>, String> { Ok(regions::query(self.process_id)?.into_iter().filter(MemoryRegion::is_readable).map(|r| r.base..r.end()).collect()) }
+
+  /// Where the image of the loaded module `name`, e.g. `game.exe`, spans.
+  #[cfg(target_os = "windows")]
+  pub fn module_range(&self, name: &str) -> Result<Range<u64>, String> {
+    let module = Kenjector::get_modules(self.process_id)?.into_iter().find(|m| m.name.eq_ignore_ascii_case(name)).ok_or_else(|| format!("{} is not loaded in process {}", name, self.process_id))?;
+    Ok(module.base..module.base + module.size as u64)
+  }
+
+  /// From the first to the last mapping of the file called `name`, e.g. `libc.so.6`.
+  #[cfg(target_os = "linux")]
+  pub fn module_range(&self, name: &str) -> Result<Range<u64>, String> {
+    let regions = regions::query(self.process_id)?.into_iter().filter(|r| std::path::Path::new(&r.owner).file_name().is_some_and(|file| file == name)).collect::<Vec<_>>();
+    match (regions.first(), regions.last()) {
+      (Some(first), Some(last)) => Ok(first.base..last.end()),
+      _ => Err(format!("{} is not mapped in process {}", name, self.process_id)),
+    }
+  }
+
+  /// Where to start looking at the process called `name`: the image of its executable, else its first
+  /// readable region, e.g. when a Linux process's name is cut short and matches no file.
+  pub fn first_address(&self, name: &str) -> Result<u64, String> {
+    match self.module_range(name) {
+      Ok(range) => Ok(range.start),
+      Err(e) => self.readable_regions()?.first().map(|r| r.start).ok_or(e),
+    }
+  }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for ProcessMemory {
+  fn drop(&mut self) { unsafe { CloseHandle(self.process) }; }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+  use std::{path::PathBuf, process::{Child, Command}, time::{Duration, Instant}};
+
+  /// A `sleep` to look at, killed when dropped.
+  struct Sleeper {
+    child: Child,
+    executable: PathBuf,
+  }
+
+  impl Sleeper {
+    /// Start it and wait until it is sleeping, so the executable and its libraries are mapped.
+    fn spawn() -> Self {
+      let child = Command::new("sleep").arg("30").spawn().unwrap();
+      let mut sleeper = Self { child, executable: PathBuf::new() };
+      let started = Instant::now();
+      loop {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", sleeper.pid())).unwrap_or_default();
+        let state = stat.rsplit_once(") ").and_then(|(_, rest)| rest.chars().next());
+        let executable = std::fs::read_link(format!("/proc/{}/exe", sleeper.pid())).unwrap_or_default();
+        if state == Some('S') && executable.file_name().is_some_and(|f| f == "sleep") {
+          sleeper.executable = executable;
+          return sleeper;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10), "sleep did not start");
+        std::thread::sleep(Duration::from_millis(5));
+      }
+    }
+
+    fn pid(&self) -> u32 { self.child.id() }
+
+    /// The child's mappings of its executable, in address order.
+    fn image(&self) -> Vec<MemoryRegion> { regions::query(self.pid()).unwrap().into_iter().filter(|r| r.owner == self.executable.to_string_lossy()).collect() }
+  }
+
+  impl Drop for Sleeper {
+    fn drop(&mut self) {
+      let _ = self.child.kill();
+      let _ = self.child.wait();
+    }
+  }
+
+  #[test]
+  fn reads_the_mapped_executable() {
+    let sleeper = Sleeper::spawn();
+    let memory = ProcessMemory::open(sleeper.pid()).unwrap();
+    // The first mapping is the start of the file
+    let base = sleeper.image()[0].base;
+    let mut header = [0u8; 64];
+    memory.read(base, &mut header).unwrap();
+    assert_eq!(header[..], std::fs::read(&sleeper.executable).unwrap()[..64]);
+  }
+
+  #[test]
+  fn writes_read_only_code() {
+    let sleeper = Sleeper::spawn();
+    let memory = ProcessMemory::open(sleeper.pid()).unwrap();
+    let code = sleeper.image().into_iter().find(|r| r.protection.execute).unwrap();
+    assert!(!code.protection.write);
+
+    // The end of the code mapping, which a sleeping `sleep` doesn't run
+    let address = code.end() - 16;
+    let mut original = [0u8; 16];
+    memory.read(address, &mut original).unwrap();
+    let patch = original.map(|b| !b);
+    memory.write(address, &patch).unwrap();
+    let mut read = [0u8; 16];
+    memory.read(address, &mut read).unwrap();
+    assert_eq!(read, patch);
+    memory.write(address, &original).unwrap();
+    memory.read(address, &mut read).unwrap();
+    assert_eq!(read, original);
+  }
+
+  #[test]
+  fn read_lossy_stops_at_unmapped_pages() {
+    let sleeper = Sleeper::spawn();
+    let memory = ProcessMemory::open(sleeper.pid()).unwrap();
+    let regions = regions::query(sleeper.pid()).unwrap();
+    // A readable region with nothing mapped right after it
+    let region = regions.iter().find(|r| r.is_readable() && !regions.iter().any(|next| next.base == r.end())).unwrap();
+
+    let bytes = memory.read_lossy(region.end() - 8, 16);
+    assert_eq!(bytes.len(), 16);
+    assert!(bytes[..8].iter().all(Option::is_some), "{:?}", bytes);
+    assert!(bytes[8..].iter().all(Option::is_none), "{:?}", bytes);
+    // A plain read of the same bytes fails as a whole
+    assert!(memory.read(region.end() - 8, &mut [0u8; 16]).is_err());
+    assert!(memory.write(region.end(), &[0]).is_err());
+  }
+
+  #[test]
+  fn pointer_size_and_module_range() {
+    let sleeper = Sleeper::spawn();
+    let memory = ProcessMemory::open(sleeper.pid()).unwrap();
+    assert_eq!(memory.pointer_size().unwrap(), size_of::<usize>());
+
+    let image = sleeper.image();
+    let range = memory.module_range("sleep").unwrap();
+    assert_eq!(range, image[0].base..image[image.len() - 1].end());
+    assert_eq!(memory.first_address("sleep").unwrap(), range.start);
+    assert_eq!(memory.module_range("not-loaded.so").unwrap_err(), format!("not-loaded.so is not mapped in process {}", sleeper.pid()));
+    // A name that matches no file falls back on the first readable region
+    assert_eq!(memory.first_address("sleep-cut-short").unwrap(), memory.readable_regions().unwrap()[0].start);
+  }
+
+  #[test]
+  fn open_fails_for_a_missing_process() {
+    let sleeper = Sleeper::spawn();
+    let pid = sleeper.pid();
+    drop(sleeper);
+    assert!(ProcessMemory::open(pid).is_err());
+  }
+}
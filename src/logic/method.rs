@@ -0,0 +1,180 @@
+#[cfg(target_os = "windows")]
+use crate::logic::kenjector::Access;
+use crate::logic::kenjector::{Arch, Kenjector};
+#[cfg(target_os = "linux")]
+use crate::logic::privilege::{PtraceAccess, PtraceTarget};
+#[cfg(target_os = "windows")]
+use winapi::um::{handleapi::CloseHandle, processthreadsapi::{OpenProcess, OpenThread}, winnt::{PROCESS_CREATE_THREAD, PROCESS_QUERY_INFORMATION, PROCESS_VM_OPERATION, PROCESS_VM_READ, PROCESS_VM_WRITE, THREAD_GET_CONTEXT, THREAD_SET_CONTEXT, THREAD_SUSPEND_RESUME}};
+
+/// How the DLL gets into the target, chosen per Kenjection. See [`REGISTRY`] for what each needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum InjectionMethod {
+  #[cfg(target_os = "windows")]
+  #[default]
+  CreateRemoteThread,
+  #[cfg(target_os = "windows")]
+  ThreadHijack,
+  #[cfg(target_os = "windows")]
+  QueueApc,
+  #[cfg(target_os = "windows")]
+  ReflectiveLoader,
+  #[cfg(target_os = "linux")]
+  #[default]
+  Ptrace,
+}
+
+/// Everything the UI and CLI need to know about an injection method.
+#[derive(Debug)]
+pub struct MethodInfo {
+  pub method: InjectionMethod,
+  /// Shown in the picker, accepted by `--method` and recorded in the history.
+  pub name: &'static str,
+  pub description: &'static str,
+  /// Target architectures the method can handle, see also [`Arch::can_target`].
+  pub archs: &'static [Arch],
+  /// Access rights the target process is opened with, 0 on Linux where ptrace has none.
+  pub process_access: u32,
+  /// Access rights the target's threads are opened with, 0 when the method does not touch them.
+  #[cfg(target_os = "windows")]
+  pub thread_access: u32,
+  /// Whether the DLL shows up in the target's module list, where its exports are found afterwards.
+  pub in_module_list: bool,
+}
+
+#[cfg(target_os = "windows")]
+const ALL_ARCHS: &[Arch] = &[Arch::AMDx64, Arch::AMDx86, Arch::Arm64];
+#[cfg(target_os = "windows")]
+const REMOTE_MEMORY: u32 = PROCESS_QUERY_INFORMATION | PROCESS_VM_OPERATION | PROCESS_VM_READ | PROCESS_VM_WRITE;
+
+/// Every injection method, in the order the picker lists them.
+#[cfg(target_os = "windows")]
+pub const REGISTRY: &[MethodInfo] = &[
+  MethodInfo {
+    method: InjectionMethod::CreateRemoteThread,
+    name: "CreateRemoteThread",
+    description: "Starts a new thread in the target that calls LoadLibraryW. Reliable, but the first thing anti-cheats and sandboxes look for.",
+    archs: ALL_ARCHS,
+    process_access: REMOTE_MEMORY | PROCESS_CREATE_THREAD,
+    thread_access: 0,
+    in_module_list: true,
+  },
+  MethodInfo {
+    method: InjectionMethod::ThreadHijack,
+    name: "ThreadHijack",
+    description: "Suspends an existing thread, points it at a stub that calls LoadLibraryW and puts it back afterwards. A thread blocked in a wait only runs the stub once the wait ends.",
+    archs: ALL_ARCHS,
+    process_access: REMOTE_MEMORY,
+    thread_access: THREAD_SUSPEND_RESUME | THREAD_GET_CONTEXT | THREAD_SET_CONTEXT,
+    in_module_list: true,
+  },
+  MethodInfo {
+    method: InjectionMethod::QueueApc,
+    name: "QueueApc",
+    description: "Queues LoadLibraryW as an APC to every thread. Completes only when a thread of the target enters an alertable wait, which some processes never do.",
+    archs: ALL_ARCHS,
+    process_access: REMOTE_MEMORY,
+    thread_access: THREAD_SET_CONTEXT,
+    in_module_list: true,
+  },
+  MethodInfo {
+    method: InjectionMethod::ReflectiveLoader,
+    name: "ReflectiveLoader",
+    description: "Writes the DLL file itself into the target and starts its ReflectiveLoader export, which maps it without LoadLibraryW. Only for DLLs built with such an export, and the DLL does not show up in the module list.",
+    archs: ALL_ARCHS,
+    process_access: REMOTE_MEMORY | PROCESS_CREATE_THREAD,
+    thread_access: 0,
+    in_module_list: false,
+  },
+];
+
+#[cfg(target_os = "linux")]
+pub const REGISTRY: &[MethodInfo] = &[MethodInfo {
+  method: InjectionMethod::Ptrace,
+  name: "Ptrace",
+  description: "Attaches to the main thread with ptrace, points it at a stub that calls dlopen and puts it back afterwards, like ThreadHijack does on Windows. What ptrace_scope allows decides which processes it can reach.",
+  archs: &[Arch::AMDx64, Arch::Arm64],
+  process_access: 0,
+  in_module_list: true,
+}];
+
+#[cfg(target_os = "windows")]
+const PROCESS_ACCESS_NAMES: &[(u32, &str)] = &[(PROCESS_CREATE_THREAD, "PROCESS_CREATE_THREAD"), (PROCESS_QUERY_INFORMATION, "PROCESS_QUERY_INFORMATION"), (PROCESS_VM_OPERATION, "PROCESS_VM_OPERATION"), (PROCESS_VM_READ, "PROCESS_VM_READ"), (PROCESS_VM_WRITE, "PROCESS_VM_WRITE")];
+#[cfg(target_os = "windows")]
+const THREAD_ACCESS_NAMES: &[(u32, &str)] = &[(THREAD_SUSPEND_RESUME, "THREAD_SUSPEND_RESUME"), (THREAD_GET_CONTEXT, "THREAD_GET_CONTEXT"), (THREAD_SET_CONTEXT, "THREAD_SET_CONTEXT")];
+
+impl MethodInfo {
+  /// The required access rights by name, e.g. `PROCESS_VM_WRITE | THREAD_SET_CONTEXT`.
+  #[cfg(target_os = "windows")]
+  pub fn access_names(&self) -> String {
+    let process = PROCESS_ACCESS_NAMES.iter().filter(|(bit, _)| self.process_access & bit != 0);
+    let thread = THREAD_ACCESS_NAMES.iter().filter(|(bit, _)| self.thread_access & bit != 0);
+    process.chain(thread).map(|(_, name)| *name).collect::<Vec<_>>().join(" | ")
+  }
+
+  #[cfg(target_os = "linux")]
+  pub fn access_names(&self) -> String { String::from("PTRACE_SEIZE") }
+
+  /// Whether the method handles a process of `arch` from this build.
+  fn check_arch(&self, arch: Arch) -> Result<(), String> {
+    if !self.archs.contains(&arch) {
+      return Err(format!("{} does not support {} processes", self.name, arch));
+    }
+    if !arch.can_target() {
+      return Err(format!("A {} build of {} can't inject into a {} process", Arch::native(), crate::APP_NAME, arch));
+    }
+    Ok(())
+  }
+}
+
+impl std::fmt::Display for InjectionMethod {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { f.write_str(self.info().name) }
+}
+
+impl InjectionMethod {
+  pub fn info(&self) -> &'static MethodInfo { REGISTRY.iter().find(|i| i.method == *self).expect("every method is in the registry") }
+
+  /// The method called `name`, ignoring case, as given to `--method` or recorded in the history.
+  pub fn from_name(name: &str) -> Option<Self> { REGISTRY.iter().find(|i| i.name.eq_ignore_ascii_case(name)).map(|i| i.method) }
+
+  /// Check the method can be used on the process before attempting it: the target's architecture
+  /// must be supported and we must be able to open it, and its threads, with the rights it needs.
+  #[cfg(target_os = "windows")]
+  pub fn supports(&self, process_id: u32) -> Result<(), String> {
+    let info = self.info();
+
+    let process = Kenjector::open_process(Access::Limited, process_id).map_err(|e| e.to_string())?;
+    let arch = Kenjector::architecture(process);
+    unsafe { CloseHandle(process) };
+    let arch = arch.map_err(|e| format!("Failed to get the architecture of process {}, error: {}", process_id, e))?;
+    info.check_arch(arch)?;
+
+    unsafe {
+      let process = OpenProcess(info.process_access, 0, process_id);
+      if process.is_null() {
+        return Err(format!("{} needs {} on process {}, error: {:#X?}", info.name, info.access_names(), process_id, std::io::Error::last_os_error()));
+      }
+      CloseHandle(process);
+
+      if info.thread_access != 0 {
+        let opened = Kenjector::get_thread_ids(process_id)?.into_iter().map(|id| OpenThread(info.thread_access, 0, id)).find(|thread| !thread.is_null());
+        match opened {
+          Some(thread) => {
+            CloseHandle(thread);
+          }
+          None => return Err(format!("{} needs {} on a thread of process {}, error: {:#X?}", info.name, info.access_names(), process_id, std::io::Error::last_os_error())),
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Check the method can be used on the process before attempting it: the target's architecture
+  /// must be supported and ptrace must be allowed to attach to it.
+  #[cfg(target_os = "linux")]
+  pub fn supports(&self, process_id: u32) -> Result<(), String> {
+    let arch = Kenjector::architecture(process_id)?;
+    self.info().check_arch(arch)?;
+    PtraceAccess::current().preflight(&PtraceTarget::read(process_id)?)
+  }
+}
@@ -1 +1,22 @@
+pub(crate) mod channel;
+pub(crate) mod config;
+pub(crate) mod debugoutput;
+pub(crate) mod filter;
+#[cfg(target_os = "windows")]
+pub(crate) mod hijack;
+pub(crate) mod history;
 pub(crate) mod kenjector;
+pub(crate) mod memory;
+pub(crate) mod method;
+pub(crate) mod pattern;
+pub(crate) mod pointer;
+pub(crate) mod privilege;
+#[cfg(target_os = "linux")]
+pub(crate) mod ptrace;
+pub(crate) mod reflective;
+pub(crate) mod regions;
+#[cfg(any(target_os = "windows", test))]
+pub(crate) mod remote;
+pub(crate) mod stub;
+pub(crate) mod threads;
+pub(crate) mod winpath;
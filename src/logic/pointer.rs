@@ -0,0 +1,208 @@
+use crate::logic::memory::ProcessMemory;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+/// The longest string a watch reads, up to the first NUL.
+const MAX_STRING_LEN: usize = 256;
+
+/// How the value at the end of a chain is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, Serialize, Deserialize)]
+pub enum ValueType {
+  #[default]
+  #[display("i32")]
+  I32,
+  #[display("f32")]
+  F32,
+  #[display("f64")]
+  F64,
+  /// UTF-8, up to the first NUL.
+  #[display("string")]
+  String,
+}
+
+impl ValueType {
+  pub const ALL: [Self; 4] = [Self::I32, Self::F32, Self::F64, Self::String];
+
+  /// Read a value of this type at `address` and show it.
+  pub fn read(self, memory: &ProcessMemory, address: u64) -> Result<String, String> {
+    Ok(match self {
+      Self::I32 => i32::from_le_bytes(read_array(memory, address)?).to_string(),
+      Self::F32 => f32::from_le_bytes(read_array(memory, address)?).to_string(),
+      Self::F64 => f64::from_le_bytes(read_array(memory, address)?).to_string(),
+      Self::String => {
+        // The string may end just before an unreadable page, so stop at the first byte that isn't there
+        let bytes = memory.read_lossy(address, MAX_STRING_LEN).into_iter().map_while(|b| b).take_while(|b| *b != 0).collect::<Vec<_>>();
+        if bytes.is_empty() && memory.read(address, &mut [0]).is_err() {
+          return Err(format!("Nothing at {:#X} can be read", address));
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+      }
+    })
+  }
+}
+
+fn read_array<const N: usize>(memory: &ProcessMemory, address: u64) -> Result<[u8; N], String> {
+  let mut bytes = [0u8; N];
+  memory.read(address, &mut bytes)?;
+  Ok(bytes)
+}
+
+/// A multi-level pointer such as `game.exe+0x1A2B3C -> +0x10 -> +0x48`, the way Cheat Engine writes them:
+/// the pointer at the module's base plus `base_offset` is followed, each offset is added to the pointer
+/// read before it and followed in turn, except the last one, which gives the address of the value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PointerChain {
+  pub name: String,
+  /// The module the chain starts from, e.g. `game.exe`.
+  pub module: String,
+  pub base_offset: u64,
+  pub offsets: Vec<i64>,
+  pub value_type: ValueType,
+}
+
+impl PointerChain {
+  /// Parse a chain written as `module+base -> +offset -> -offset ...`, offsets in hex with or without
+  /// `0x`. Commas work as separators too, e.g. `game.exe+1A2B3C, 10, 48`.
+  pub fn parse(name: &str, text: &str, value_type: ValueType) -> Result<Self, String> {
+    let mut parts = text.split(['>', ',']).map(|part| part.trim().trim_end_matches('-').trim());
+    let start = parts.next().filter(|s| !s.is_empty()).ok_or_else(|| String::from("The chain is empty"))?;
+    let (module, base_offset) = match start.rsplit_once('+') {
+      Some((module, offset)) if !module.trim().is_empty() => (module.trim(), parse_offset(offset)?),
+      _ => return Err(format!("{} is not a module and offset, e.g. game.exe+0x1A2B3C", start)),
+    };
+    if base_offset < 0 {
+      return Err(format!("{} has a negative offset into the module", start));
+    }
+    let offsets = parts.map(parse_offset).collect::<Result<Vec<_>, _>>()?;
+    Ok(Self {
+      name: name.to_string(),
+      module: module.to_string(),
+      base_offset: base_offset as u64,
+      offsets,
+      value_type,
+    })
+  }
+
+  /// The chain as the text [`Self::parse`] takes, e.g. `game.exe+0x1A2B3C -> +0x10 -> +0x48`.
+  pub fn path(&self) -> String { std::iter::once(format!("{}+{:#X}", self.module, self.base_offset)).chain(self.offsets.iter().map(|o| if *o < 0 { format!("-{:#X}", o.unsigned_abs()) } else { format!("+{:#X}", o) })).collect::<Vec<_>>().join(" -> ") }
+
+  /// Follow the chain in the process, returning the address of the value. A step that can't be read names
+  /// the part of the chain it got to.
+  pub fn resolve(&self, memory: &ProcessMemory) -> Result<u64, String> {
+    let pointer_size = memory.pointer_size()?;
+    let mut address = memory.module_range(&self.module)?.start.wrapping_add(self.base_offset);
+    for (level, offset) in self.offsets.iter().enumerate() {
+      let mut pointer = [0u8; 8];
+      memory.read(address, &mut pointer[..pointer_size]).map_err(|_| format!("Can't follow the pointer at {:#X}, level {} of {}", address, level + 1, self.offsets.len()))?;
+      let pointer = u64::from_le_bytes(pointer);
+      if pointer == 0 {
+        return Err(format!("The pointer at {:#X} is null, level {} of {}", address, level + 1, self.offsets.len()));
+      }
+      address = pointer.wrapping_add_signed(*offset);
+    }
+    Ok(address)
+  }
+}
+
+/// A hex offset such as `0x10`, `+10` or `-0x8`.
+fn parse_offset(text: &str) -> Result<i64, String> {
+  let trimmed = text.trim();
+  if trimmed.is_empty() {
+    return Err(String::from("An offset is missing between two separators"));
+  }
+  let (negative, digits) = match trimmed.strip_prefix('-') {
+    Some(rest) => (true, rest),
+    None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+  };
+  let digits = digits.trim();
+  let digits = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")).unwrap_or(digits);
+  // from_str_radix takes a sign of its own, which would let `--8` through as 8
+  if digits.starts_with(['+', '-']) {
+    return Err(format!("{} is not a hex offset", trimmed));
+  }
+  let value = u64::from_str_radix(digits, 16).map_err(|_| format!("{} is not a hex offset", trimmed))?;
+  // Parsed unsigned so `-0x8000000000000000`, which [`PointerChain::path`] writes for `i64::MIN`, fits
+  match (negative, i64::try_from(value)) {
+    (false, Ok(value)) => Ok(value),
+    (true, Ok(value)) => Ok(-value),
+    (true, Err(_)) if value == i64::MIN.unsigned_abs() => Ok(i64::MIN),
+    _ => Err(format!("{} is out of range for an offset", trimmed)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(text: &str) -> Result<PointerChain, String> { PointerChain::parse("health", text, ValueType::I32) }
+
+  #[test]
+  fn cheat_engine_form() {
+    let chain = parse("game.exe+0x1A2B3C -> +0x10 -> +0x48").unwrap();
+    assert_eq!(
+      chain,
+      PointerChain {
+        name: String::from("health"),
+        module: String::from("game.exe"),
+        base_offset: 0x1A2B3C,
+        offsets: vec![0x10, 0x48],
+        value_type: ValueType::I32
+      }
+    );
+    // Without `0x`, spaces or a sign on the offsets
+    assert_eq!(parse("game.exe+1a2b3c->10->48").unwrap(), chain);
+    assert_eq!(parse("game.exe + 0X1A2B3C  ->  0x10 -> 48").unwrap(), chain);
+    // A module offset alone is a static address
+    assert!(parse("game.exe+0x20").unwrap().offsets.is_empty());
+  }
+
+  #[test]
+  fn negative_offsets() {
+    assert_eq!(parse("game.exe+0x100 -> -8 -> +0x10 -> -0x1C").unwrap().offsets, [-8, 0x10, -0x1C]);
+    assert_eq!(parse("game.exe+0x100 -> -8").unwrap().offsets, [-8]);
+  }
+
+  #[test]
+  fn comma_form() {
+    assert_eq!(parse("game.exe+1A2B3C, 10, 48").unwrap(), parse("game.exe+0x1A2B3C -> +0x10 -> +0x48").unwrap());
+    assert_eq!(parse("game.exe+100, -8, 10").unwrap().offsets, [-8, 0x10]);
+  }
+
+  #[test]
+  fn module_names_with_signs() {
+    let chain = parse("anti-cheat-x64.dll+0x40 -> -0x8").unwrap();
+    assert_eq!((chain.module.as_str(), chain.base_offset, chain.offsets), ("anti-cheat-x64.dll", 0x40, vec![-8]));
+    let chain = parse("libstdc++-6.dll+0x1000 -> 0x18").unwrap();
+    assert_eq!((chain.module.as_str(), chain.base_offset, chain.offsets), ("libstdc++-6.dll", 0x1000, vec![0x18]));
+  }
+
+  #[test]
+  fn invalid_chains() {
+    assert_eq!(parse("").unwrap_err(), "The chain is empty");
+    assert_eq!(parse("  -> 0x10").unwrap_err(), "The chain is empty");
+    assert_eq!(parse("0x1A2B3C -> 0x10").unwrap_err(), "0x1A2B3C is not a module and offset, e.g. game.exe+0x1A2B3C");
+    assert_eq!(parse("+0x1A2B3C -> 0x10").unwrap_err(), "+0x1A2B3C is not a module and offset, e.g. game.exe+0x1A2B3C");
+    assert_eq!(parse("game.exe+-0x10 -> 0x10").unwrap_err(), "game.exe+-0x10 has a negative offset into the module");
+    assert_eq!(parse("game.exe+0x10 -> 0xZZ").unwrap_err(), "0xZZ is not a hex offset");
+    assert_eq!(parse("game.exe+0x10 -> --8").unwrap_err(), "--8 is not a hex offset");
+    assert_eq!(parse("game.exe+0x10 -> -> 8").unwrap_err(), "An offset is missing between two separators");
+    assert_eq!(parse("game.exe+0x10 -> 0x8000000000000000").unwrap_err(), "0x8000000000000000 is out of range for an offset");
+  }
+
+  #[test]
+  fn path_round_trips() {
+    for text in ["game.exe+0x1A2B3C -> +0x10 -> +0x48", "game.exe+0x100 -> -0x8 -> +0x0", "libstdc++-6.dll+0x0", "anti-cheat.dll+0xFF -> -0x7FFFFFFFFFFFFFFF"] {
+      let chain = parse(text).unwrap();
+      assert_eq!(chain.path(), text);
+      assert_eq!(parse(&chain.path()).unwrap(), chain);
+    }
+    let chain = PointerChain {
+      name: String::new(),
+      module: String::from("game.exe"),
+      base_offset: u64::MAX >> 1,
+      offsets: vec![i64::MIN, -1, 0, i64::MAX],
+      value_type: ValueType::F64,
+    };
+    assert_eq!(PointerChain::parse("", &chain.path(), ValueType::F64).unwrap(), chain);
+  }
+}
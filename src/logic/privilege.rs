@@ -0,0 +1,230 @@
+#[cfg(target_os = "windows")]
+use winapi::{shared::winerror::ERROR_NOT_ALL_ASSIGNED, um::{errhandlingapi::GetLastError, handleapi::CloseHandle, processthreadsapi::{GetCurrentProcess, OpenProcessToken}, securitybaseapi::AdjustTokenPrivileges, shellapi::ShellExecuteW, winbase::LookupPrivilegeValueW, winnt::{LUID_AND_ATTRIBUTES, SE_DEBUG_NAME, SE_PRIVILEGE_ENABLED, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES, TOKEN_QUERY}, winuser::SW_SHOWNORMAL}};
+
+/// Enable `SeDebugPrivilege` in our token, which lets Kenjector open processes of other users and
+/// services regardless of their security descriptor. Only an elevated administrator token holds it.
+#[cfg(target_os = "windows")]
+pub fn enable_debug_privilege() -> Result<(), String> {
+  unsafe {
+    let mut token = std::ptr::null_mut();
+    if OpenProcessToken(GetCurrentProcess(), TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY, &mut token) == 0 {
+      return Err(format!("OpenProcessToken failed, error: {:#X?}", std::io::Error::last_os_error()));
+    }
+
+    let mut privileges = TOKEN_PRIVILEGES {
+      PrivilegeCount: 1,
+      Privileges: [LUID_AND_ATTRIBUTES { Luid: std::mem::zeroed(), Attributes: SE_PRIVILEGE_ENABLED }],
+    };
+    let name = wide(SE_DEBUG_NAME);
+    let adjusted = if LookupPrivilegeValueW(std::ptr::null(), name.as_ptr(), &mut privileges.Privileges[0].Luid) == 0 {
+      Err(format!("LookupPrivilegeValueW failed, error: {:#X?}", std::io::Error::last_os_error()))
+    } else if AdjustTokenPrivileges(token, 0, &mut privileges, 0, std::ptr::null_mut(), std::ptr::null_mut()) == 0 {
+      Err(format!("AdjustTokenPrivileges failed, error: {:#X?}", std::io::Error::last_os_error()))
+    } else if GetLastError() == ERROR_NOT_ALL_ASSIGNED {
+      // AdjustTokenPrivileges succeeds without enabling anything when the token lacks the privilege
+      Err(String::from("The token does not hold SeDebugPrivilege, run as administrator"))
+    } else {
+      Ok(())
+    };
+
+    CloseHandle(token);
+    adjusted
+  }
+}
+
+/// Start Kenjector again elevated with `args`, through the UAC prompt. Returns once the new instance is
+/// started, the caller should exit.
+#[cfg(target_os = "windows")]
+pub fn restart_as_admin(args: &[String]) -> Result<(), String> {
+  let exe = std::env::current_exe().map_err(|e| format!("Failed to find our own executable, error: {}", e))?;
+  let parameters = quote_arguments(args);
+
+  let (verb, exe, parameters) = (wide("runas"), wide(&exe.to_string_lossy()), wide(&parameters));
+  // ShellExecuteW returns a value above 32 on success, an error code otherwise, e.g. when the prompt is declined
+  let result = unsafe { ShellExecuteW(std::ptr::null_mut(), verb.as_ptr(), exe.as_ptr(), parameters.as_ptr(), std::ptr::null(), SW_SHOWNORMAL) } as isize;
+  match result {
+    33.. => Ok(()),
+    // SE_ERR_ACCESSDENIED, which is also what declining the prompt gives
+    5 => Err(String::from("Failed to restart as administrator, ShellExecuteW returned 5 (access denied), was the prompt declined?")),
+    _ => Err(format!("Failed to restart as administrator, ShellExecuteW returned {}", result)),
+  }
+}
+
+/// Quote `args` into one command line that `CommandLineToArgvW` splits back into the same arguments.
+/// Backslashes are literal there unless a quote follows, then each pair is one backslash.
+#[cfg(any(target_os = "windows", test))]
+fn quote_arguments(args: &[String]) -> String {
+  let quote = |arg: &String| {
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+      if c == '\\' {
+        backslashes += 1;
+        continue;
+      }
+      let escapes = if c == '"' { backslashes * 2 + 1 } else { backslashes };
+      quoted.extend(std::iter::repeat_n('\\', escapes));
+      quoted.push(c);
+      backslashes = 0;
+    }
+    // Doubled so they don't escape the closing quote
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+  };
+  args.iter().map(quote).collect::<Vec<_>>().join(" ")
+}
+
+/// There's no prompt to elevate through on Linux, the user has to restart us themselves.
+#[cfg(target_os = "linux")]
+pub fn restart_as_admin(_args: &[String]) -> Result<(), String> { Err(String::from("Restart Kenjector with sudo, or grant it CAP_SYS_PTRACE with `setcap cap_sys_ptrace+ep`")) }
+
+#[cfg(target_os = "windows")]
+fn wide(text: &str) -> Vec<u16> { text.encode_utf16().chain(Some(0)).collect() }
+
+/// `CAP_SYS_PTRACE`, the capability that lifts the Yama and same-user ptrace restrictions.
+#[cfg(target_os = "linux")]
+const CAP_SYS_PTRACE: u32 = 19;
+
+/// What limits ptrace for our process on this system, see ptrace(2) and the kernel's Yama documentation.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy)]
+pub struct PtraceAccess {
+  /// `/proc/sys/kernel/yama/ptrace_scope`, `None` when the kernel has no Yama.
+  pub scope: Option<u32>,
+  pub cap_sys_ptrace: bool,
+  /// Our real uid and gid, which attaching compares with the target's.
+  pub uid: u32,
+  pub gid: u32,
+}
+
+#[cfg(target_os = "linux")]
+impl PtraceAccess {
+  pub fn current() -> Self {
+    let scope = std::fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope").ok().and_then(|s| s.trim().parse().ok());
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let cap_sys_ptrace = status_field(&status, "CapEff").and_then(|mask| u64::from_str_radix(mask, 16).ok()).is_some_and(|caps| caps & (1 << CAP_SYS_PTRACE) != 0);
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    Self { scope, cap_sys_ptrace, uid, gid }
+  }
+
+  /// Which processes are out of reach and how to fix it, `None` when only the usual same-user rule applies.
+  pub fn restriction(&self) -> Option<String> {
+    match self.scope {
+      Some(3) => Some(String::from("ptrace_scope is 3, ptrace is disabled for everyone until the next reboot")),
+      _ if self.cap_sys_ptrace => None,
+      Some(1) => Some(String::from("ptrace_scope is 1, only processes started by Kenjector can be injected into. Grant it CAP_SYS_PTRACE with `setcap cap_sys_ptrace+ep` or set kernel.yama.ptrace_scope to 0")),
+      Some(2) => Some(String::from("ptrace_scope is 2, only processes with CAP_SYS_PTRACE can attach. Grant it with `setcap cap_sys_ptrace+ep` or run as root")),
+      _ => None,
+    }
+  }
+
+  /// Whether we may attach to `target`, checked the way the kernel does before Yama gets a say. The error
+  /// names the restriction in the way and how to lift it, so a Kenjection can fail before attaching.
+  pub fn preflight(&self, target: &PtraceTarget) -> Result<(), String> {
+    if self.scope == Some(3) || (self.scope == Some(2) && !self.cap_sys_ptrace) {
+      return Err(self.restriction().unwrap_or_default());
+    }
+    if self.cap_sys_ptrace {
+      return Ok(());
+    }
+
+    let fix = "Run Kenjector as that user, or grant it CAP_SYS_PTRACE with `setcap cap_sys_ptrace+ep`";
+    // All of the target's real, effective and saved ids have to be ours
+    if target.uids[..3].iter().any(|&uid| uid != self.uid) {
+      return Err(format!("Process {} runs as uid {} (effective {}, saved {}) but Kenjector as uid {}. {}", target.process_id, target.uids[0], target.uids[1], target.uids[2], self.uid, fix));
+    }
+    if target.gids[..3].iter().any(|&gid| gid != self.gid) {
+      return Err(format!("Process {} runs as gid {} (effective {}, saved {}) but Kenjector as gid {}. {}", target.process_id, target.gids[0], target.gids[1], target.gids[2], self.gid, fix));
+    }
+    if !target.dumpable {
+      return Err(format!("Process {} is not dumpable, it changed credentials or called prctl(PR_SET_DUMPABLE, 0). Only a process with CAP_SYS_PTRACE can attach, grant it with `setcap cap_sys_ptrace+ep` or run as root", target.process_id));
+    }
+    if self.scope == Some(1) && !target.descendant {
+      return Err(format!("ptrace_scope is 1 and process {} was not started by Kenjector. Launch the target through Kenjector, grant it CAP_SYS_PTRACE with `setcap cap_sys_ptrace+ep` or set kernel.yama.ptrace_scope to 0", target.process_id));
+    }
+    Ok(())
+  }
+}
+
+/// What ptrace's access check looks at in a target process, from its `/proc` entry.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone)]
+pub struct PtraceTarget {
+  pub process_id: u32,
+  /// Real, effective, saved and filesystem ids.
+  pub uids: [u32; 4],
+  pub gids: [u32; 4],
+  /// False for processes that changed credentials, e.g. setuid programs, or opted out with `PR_SET_DUMPABLE`.
+  pub dumpable: bool,
+  /// Whether we are among the process's ancestors, which is what Yama's scope 1 allows.
+  pub descendant: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl PtraceTarget {
+  pub fn read(process_id: u32) -> Result<Self, String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let status = std::fs::read_to_string(format!("/proc/{}/status", process_id)).map_err(|e| format!("Failed to read the status of process {}, error: {}", process_id, e))?;
+    let ids = |field| -> Result<[u32; 4], String> {
+      let ids = status_field(&status, field).map(|ids| ids.split_whitespace().filter_map(|id| id.parse().ok()).collect::<Vec<u32>>()).unwrap_or_default();
+      ids.try_into().map_err(|_| format!("The status of process {} has no valid {} line", process_id, field))
+    };
+    let (uids, gids) = (ids("Uid")?, ids("Gid")?);
+
+    // The kernel hands the files in the /proc entry of a process that isn't dumpable to root, whatever uid it runs as
+    let owner = std::fs::metadata(format!("/proc/{}/status", process_id)).map_err(|e| format!("Failed to stat the status of process {}, error: {}", process_id, e))?.uid();
+    let dumpable = owner != 0 || uids[1] == 0;
+
+    let us = std::process::id();
+    let mut descendant = false;
+    let mut parent = parent_id(process_id);
+    while let Some(pid) = parent.filter(|&pid| pid > 1) {
+      if pid == us {
+        descendant = true;
+        break;
+      }
+      parent = parent_id(pid);
+    }
+
+    Ok(Self { process_id, uids, gids, dumpable, descendant })
+  }
+}
+
+/// The `PPid` of a process, `None` once it is gone.
+#[cfg(target_os = "linux")]
+fn parent_id(process_id: u32) -> Option<u32> { status_field(&std::fs::read_to_string(format!("/proc/{}/status", process_id)).ok()?, "PPid").and_then(|pid| pid.parse().ok()) }
+
+/// The value of `field` in a `/proc/<pid>/status` file.
+#[cfg(target_os = "linux")]
+fn status_field<'a>(status: &'a str, field: &str) -> Option<&'a str> { status.lines().find_map(|line| line.strip_prefix(field)?.strip_prefix(':')).map(str::trim) }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn quote(args: &[&str]) -> String { quote_arguments(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>()) }
+
+  #[test]
+  fn quotes_every_argument() {
+    assert_eq!(quote(&["inject", "--dll", r"C:\Program Files\payload.dll"]), r#""inject" "--dll" "C:\Program Files\payload.dll""#);
+    assert_eq!(quote(&[""]), r#""""#);
+    assert_eq!(quote(&[]), "");
+  }
+
+  #[test]
+  fn escapes_quotes_and_the_backslashes_before_them() {
+    assert_eq!(quote(&[r#"say "hi""#]), r#""say \"hi\"""#);
+    assert_eq!(quote(&[r#"a\"b"#]), r#""a\\\"b""#);
+    assert_eq!(quote(&[r#"a\\"b"#]), r#""a\\\\\"b""#);
+  }
+
+  #[test]
+  fn doubles_trailing_backslashes() {
+    assert_eq!(quote(&[r"C:\dir\", "next"]), r#""C:\dir\\" "next""#);
+    assert_eq!(quote(&[r"\\server\share\\"]), r#""\\server\share\\\\""#);
+    // Backslashes that no quote follows stay as they are
+    assert_eq!(quote(&[r"a\\b"]), r#""a\\b""#);
+  }
+}
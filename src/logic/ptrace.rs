@@ -0,0 +1,490 @@
+use crate::logic::{kenjector::{Arch, Deadline}, privilege::{PtraceAccess, PtraceTarget}, stub::{CallConv, CallStub, StubExit}};
+use std::{ffi::CString, path::Path, time::Duration};
+
+/// Layout of the block mapped in the target: the stub, then the call's data, the result and the done flag.
+const BLOCK_SIZE: u64 = 0x1000;
+const DATA_OFFSET: u64 = 0x400;
+const RESULT_OFFSET: u64 = 0xF00;
+const DONE_OFFSET: u64 = 0xF08;
+
+/// `NT_ARM_SYSTEM_CALL`, the regset holding the syscall number of a stopped AArch64 thread.
+#[cfg(target_arch = "aarch64")]
+const NT_ARM_SYSTEM_CALL: libc::c_int = 0x404;
+
+/// `syscall; int3` and `svc #0; brk #0`, patched over the thread's instruction pointer to run one syscall.
+#[cfg(target_arch = "x86_64")]
+const SYSCALL_TRAP: &[u8] = &[0x0F, 0x05, 0xCC];
+#[cfg(target_arch = "aarch64")]
+const SYSCALL_TRAP: &[u8] = &[0x01, 0x00, 0x00, 0xD4, 0x00, 0x00, 0x20, 0xD4];
+
+#[cfg(target_arch = "x86_64")]
+const SYS_MMAP: u64 = 9;
+#[cfg(target_arch = "aarch64")]
+const SYS_MMAP: u64 = 222;
+#[cfg(target_arch = "x86_64")]
+const SYS_MUNMAP: u64 = 11;
+#[cfg(target_arch = "aarch64")]
+const SYS_MUNMAP: u64 = 215;
+
+/// How a run of the stub ended, short of an error.
+enum StubRun {
+  /// The stub returned this and the thread is back where it was.
+  Returned(u64),
+  /// The timeout passed with the thread inside the stub, which has to stay mapped for it.
+  Running,
+}
+
+/// Load the shared object at `path` into a running process by hijacking its main thread with ptrace,
+/// the Linux counterpart of the Windows thread hijack. Returns the load base of the object.
+pub fn load_library(process_id: u32, path: &Path, deadline: &Deadline) -> Result<u64, String> {
+  let path = path.canonicalize().map_err(|e| format!("Failed to resolve {}, error: {}", path.display(), e))?;
+  let c_path = CString::new(path.as_os_str().as_encoded_bytes()).map_err(|_| format!("{} contains a nul byte", path.display()))?;
+  let dlopen = remote_symbol(process_id, "dlopen")?;
+
+  let handle = call(process_id, "dlopen", dlopen, c_path.as_bytes_with_nul(), |path| vec![path, libc::RTLD_NOW as u64], deadline)?;
+  if handle == 0 {
+    return Err(String::from("dlopen failed — did not load the shared object."));
+  }
+  module_base(process_id as libc::pid_t, &path)?.ok_or_else(|| format!("dlopen returned {:#X} but {} is not mapped in the target", handle, path.display()))
+}
+
+/// Drop the reference [`load_library`] took on the shared object at `path`, which unloads it unless
+/// something else still holds it.
+pub fn unload_library(process_id: u32, path: &Path, deadline: &Deadline) -> Result<(), String> {
+  let c_path = CString::new(path.as_os_str().as_encoded_bytes()).map_err(|_| format!("{} contains a nul byte", path.display()))?;
+  let (dlopen, dlclose) = (remote_symbol(process_id, "dlopen")?, remote_symbol(process_id, "dlclose")?);
+
+  // RTLD_NOLOAD hands out the handle of an object already loaded, taking another reference on it
+  let handle = call(process_id, "dlopen", dlopen, c_path.as_bytes_with_nul(), |path| vec![path, (libc::RTLD_NOW | libc::RTLD_NOLOAD) as u64], deadline)?;
+  if handle == 0 {
+    return Err(format!("{} is not loaded in process {}", path.display(), process_id));
+  }
+  for _ in 0..2 {
+    if call(process_id, "dlclose", dlclose, &[], |_| vec![handle], deadline)? as u32 != 0 {
+      return Err(format!("dlclose failed for {} in process {}", path.display(), process_id));
+    }
+  }
+  Ok(())
+}
+
+/// Call `function` on the main thread of the process and return what it returned, `name` says what
+/// it is in errors. `data` is copied into the target for the call and `args` builds the arguments
+/// from where it went, e.g. to pass a string.
+///
+/// The thread is stopped, a page is mapped in the target with an `mmap` syscall run on the thread,
+/// and a [`CallStub`] making the call is written to it. The thread then runs the stub, and once the
+/// stub signals completion it is stopped again, its original registers are restored and the page is
+/// unmapped again.
+pub fn call(process_id: u32, name: &str, function: u64, data: &[u8], args: impl FnOnce(u64) -> Vec<u64>, deadline: &Deadline) -> Result<u64, String> {
+  if data.len() as u64 > RESULT_OFFSET - DATA_OFFSET {
+    return Err(format!("The {} bytes passed to {} don't fit next to the stub", data.len(), name));
+  }
+  let conv = CallConv::linux(native_arch()?)?;
+
+  // Yama, the target's ids and dumpability each make PTRACE_SEIZE fail with the same EPERM, tell them apart first
+  PtraceAccess::current().preflight(&PtraceTarget::read(process_id)?)?;
+
+  let tracee = Tracee::seize(process_id as libc::pid_t)?;
+  tracee.interrupt()?;
+
+  let saved = tracee.regs()?;
+  let block = tracee.remote_mmap(&saved, BLOCK_SIZE)?;
+
+  let stub = CallStub {
+    conv,
+    target: function,
+    args: args(block + DATA_OFFSET),
+    result: block + RESULT_OFFSET,
+    done: block + DONE_OFFSET,
+    exit: StubExit::Spin,
+  };
+  let run = run_stub(&tracee, &saved, block, &stub, data, name, deadline);
+  // On an error the thread has been put back or never left, so the block can go either way
+  if !matches!(run, Ok(StubRun::Running))
+    && let Err(e) = tracee.remote_munmap(&saved, block, BLOCK_SIZE)
+  {
+    eprintln!("Failed to unmap the stub at {:#X}, error: {}", block, e);
+  }
+  drop(tracee);
+
+  match run? {
+    StubRun::Returned(result) => Ok(result),
+    StubRun::Running => Err(format!("{}. The stub at {:#X} stays mapped until it returns", deadline.error(name), block)),
+  }
+}
+
+/// Send the stopped thread through `stub` written to `block`, with `data` after it, and put it back
+/// at `saved` once it is done. The thread is stopped again when this returns.
+fn run_stub(tracee: &Tracee, saved: &Regs, block: u64, stub: &CallStub, data: &[u8], name: &str, deadline: &Deadline) -> Result<StubRun, String> {
+  let code = stub.assemble()?;
+  let spin = block + stub.spin_offset(&code) as u64;
+  tracee.write(block, &code)?;
+  tracee.write(block + DATA_OFFSET, data)?;
+
+  let mut regs = *saved;
+  regs.set_ip(block);
+  tracee.set_regs(&regs)?;
+  tracee.cancel_syscall_restart()?;
+  tracee.cont()?;
+
+  // Poll the flag while the thread runs. It is set before the stub restores the x87 and SSE state our
+  // registers don't cover, so once it is the thread is stopped and let go again until it reached the spin
+  let done = loop {
+    let flagged = tracee.read_u64(block + DONE_OFFSET)? as u32 == 1;
+    if flagged || deadline.is_over() {
+      tracee.interrupt()?;
+      if flagged && tracee.regs()?.ip() == spin {
+        break true;
+      }
+      if deadline.is_over() {
+        break false;
+      }
+      tracee.cont()?;
+    }
+    tracee.forward_signals()?;
+    std::thread::sleep(Duration::from_millis(10));
+  };
+
+  // Only put the thread back if it finished or never entered the stub, anything else would corrupt it
+  if !done && tracee.regs()?.ip() != block {
+    return Ok(StubRun::Running);
+  }
+  tracee.set_regs(saved)?;
+
+  if !done {
+    return Err(deadline.error(name));
+  }
+  Ok(StubRun::Returned(tracee.read_u64(block + RESULT_OFFSET)?))
+}
+
+/// Address of `symbol` in the target, found at the same offset from its libc as in ours.
+/// This only works when both processes use the same libc file, as processes on one system normally do.
+fn remote_symbol(process_id: u32, symbol: &str) -> Result<u64, String> {
+  let name = CString::new(symbol).map_err(|_| format!("{} contains a nul byte", symbol))?;
+  let local = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) } as u64;
+  if local == 0 {
+    return Err(format!("{} not found in this process", symbol));
+  }
+
+  let (library, local_base) = module_at(std::process::id() as libc::pid_t, local)?.ok_or_else(|| format!("No module contains {} at {:#X}", symbol, local))?;
+  let remote_base = module_base(process_id as libc::pid_t, Path::new(&library))?.ok_or_else(|| format!("The target does not use {}", library))?;
+  Ok(remote_base + (local - local_base))
+}
+
+/// One line of `/proc/<pid>/maps`: start, end, file offset and path.
+fn maps(pid: libc::pid_t) -> Result<Vec<(u64, u64, u64, String)>, String> {
+  let maps = std::fs::read_to_string(format!("/proc/{}/maps", pid)).map_err(|e| format!("Failed to read the memory map of {}, error: {}", pid, e))?;
+
+  Ok(
+    maps
+      .lines()
+      .filter_map(|line| {
+        let mut fields = line.split_whitespace();
+        let (start, end) = fields.next()?.split_once('-')?;
+        let offset = fields.nth(1)?;
+        let path = fields.nth(2).map(String::from).unwrap_or_default();
+        Some((u64::from_str_radix(start, 16).ok()?, u64::from_str_radix(end, 16).ok()?, u64::from_str_radix(offset, 16).ok()?, path))
+      })
+      .collect(),
+  )
+}
+
+/// Load base of the file mapped from `path`, if the process maps it.
+fn module_base(pid: libc::pid_t, path: &Path) -> Result<Option<u64>, String> {
+  let wanted = path.to_string_lossy();
+  Ok(maps(pid)?.into_iter().filter(|(_, _, _, p)| *p == wanted).map(|(start, _, offset, _)| start - offset).min())
+}
+
+/// Path and load base of the file mapping `address`.
+fn module_at(pid: libc::pid_t, address: u64) -> Result<Option<(String, u64)>, String> {
+  let maps = maps(pid)?;
+  let Some((_, _, _, path)) = maps.iter().find(|(start, end, _, p)| (*start..*end).contains(&address) && p.starts_with('/')) else { return Ok(None) };
+  let base = maps.iter().filter(|(_, _, _, p)| p == path).map(|(start, _, offset, _)| start - offset).min();
+  Ok(base.map(|b| (path.clone(), b)))
+}
+
+fn read_remote_u64(pid: libc::pid_t, address: u64) -> Result<u64, String> {
+  let mut value = [0u8; 8];
+  let local = libc::iovec { iov_base: value.as_mut_ptr() as _, iov_len: value.len() };
+  let remote = libc::iovec { iov_base: address as _, iov_len: value.len() };
+  if unsafe { libc::process_vm_readv(pid, &local, 1, &remote, 1, 0) } != value.len() as isize {
+    return Err(format!("process_vm_readv failed at {:#X}, error: {}", address, std::io::Error::last_os_error()));
+  }
+  Ok(u64::from_ne_bytes(value))
+}
+
+fn native_arch() -> Result<Arch, String> {
+  if cfg!(target_arch = "x86_64") {
+    Ok(Arch::AMDx64)
+  } else if cfg!(target_arch = "aarch64") {
+    Ok(Arch::Arm64)
+  } else {
+    Err(String::from("ptrace injection is only supported on x86-64 and AArch64"))
+  }
+}
+
+/// General purpose registers of a stopped thread.
+#[derive(Clone, Copy)]
+struct Regs(libc::user_regs_struct);
+
+impl Regs {
+  #[cfg(target_arch = "x86_64")]
+  fn ip(&self) -> u64 { self.0.rip }
+  #[cfg(target_arch = "aarch64")]
+  fn ip(&self) -> u64 { self.0.pc }
+
+  #[cfg(target_arch = "x86_64")]
+  fn set_ip(&mut self, ip: u64) { self.0.rip = ip }
+  #[cfg(target_arch = "aarch64")]
+  fn set_ip(&mut self, ip: u64) { self.0.pc = ip }
+
+  #[cfg(target_arch = "x86_64")]
+  fn set_syscall(&mut self, number: u64, args: [u64; 6]) {
+    self.0.rax = number;
+    [self.0.rdi, self.0.rsi, self.0.rdx, self.0.r10, self.0.r8, self.0.r9] = args;
+  }
+  #[cfg(target_arch = "aarch64")]
+  fn set_syscall(&mut self, number: u64, args: [u64; 6]) {
+    self.0.regs[8] = number;
+    self.0.regs[..6].copy_from_slice(&args);
+  }
+
+  #[cfg(target_arch = "x86_64")]
+  fn syscall_result(&self) -> u64 { self.0.rax }
+  #[cfg(target_arch = "aarch64")]
+  fn syscall_result(&self) -> u64 { self.0.regs[0] }
+}
+
+/// A thread we are attached to with `PTRACE_SEIZE`, detached again on drop.
+pub(crate) struct Tracee {
+  pid: libc::pid_t,
+}
+
+impl Tracee {
+  pub(crate) fn seize(pid: libc::pid_t) -> Result<Self, String> {
+    if unsafe { libc::ptrace(libc::PTRACE_SEIZE, pid, 0usize, 0usize) } == -1 {
+      let error = std::io::Error::last_os_error();
+      return match PtraceAccess::current().restriction() {
+        Some(restriction) if error.raw_os_error() == Some(libc::EPERM) => Err(format!("PTRACE_SEIZE failed, error: {}. {}", error, restriction)),
+        _ => Err(format!("PTRACE_SEIZE failed, error: {}", error)),
+      };
+    }
+    Ok(Self { pid })
+  }
+
+  /// Stop the thread and wait until it is stopped, forwarding any signal that arrives first.
+  pub(crate) fn interrupt(&self) -> Result<(), String> {
+    if unsafe { libc::ptrace(libc::PTRACE_INTERRUPT, self.pid, 0usize, 0usize) } == -1 {
+      return Err(format!("PTRACE_INTERRUPT failed, error: {}", std::io::Error::last_os_error()));
+    }
+
+    loop {
+      let status = self.wait(0)?;
+      if status >> 16 == libc::PTRACE_EVENT_STOP {
+        return Ok(());
+      }
+      self.cont_with(libc::WSTOPSIG(status))?;
+    }
+  }
+
+  /// Pass on signals the target received while running, ptrace stops it for each one.
+  fn forward_signals(&self) -> Result<(), String> {
+    loop {
+      let status = self.wait(libc::WNOHANG)?;
+      if status == 0 {
+        return Ok(());
+      }
+      let signal = if status >> 16 == 0 { libc::WSTOPSIG(status) } else { 0 };
+      self.cont_with(signal)?;
+    }
+  }
+
+  /// `waitpid` for the thread, 0 when `WNOHANG` is given and it has not changed state.
+  fn wait(&self, flags: libc::c_int) -> Result<libc::c_int, String> {
+    let mut status = 0;
+    let waited = unsafe { libc::waitpid(self.pid, &mut status, libc::__WALL | flags) };
+    if waited == -1 {
+      return Err(format!("waitpid failed, error: {}", std::io::Error::last_os_error()));
+    }
+    if waited == 0 {
+      return Ok(0);
+    }
+    if libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+      return Err(format!("Process {} exited", self.pid));
+    }
+    Ok(status)
+  }
+
+  fn cont(&self) -> Result<(), String> { self.cont_with(0) }
+
+  fn cont_with(&self, signal: libc::c_int) -> Result<(), String> {
+    if unsafe { libc::ptrace(libc::PTRACE_CONT, self.pid, 0usize, signal as usize) } == -1 {
+      return Err(format!("PTRACE_CONT failed, error: {}", std::io::Error::last_os_error()));
+    }
+    Ok(())
+  }
+
+  fn regs(&self) -> Result<Regs, String> {
+    let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+    let mut iov = libc::iovec { iov_base: &mut regs as *mut _ as _, iov_len: std::mem::size_of::<libc::user_regs_struct>() };
+    if unsafe { libc::ptrace(libc::PTRACE_GETREGSET, self.pid, libc::NT_PRSTATUS as usize, &mut iov) } == -1 {
+      return Err(format!("PTRACE_GETREGSET failed, error: {}", std::io::Error::last_os_error()));
+    }
+    Ok(Regs(regs))
+  }
+
+  fn set_regs(&self, regs: &Regs) -> Result<(), String> {
+    let mut iov = libc::iovec { iov_base: &regs.0 as *const _ as _, iov_len: std::mem::size_of::<libc::user_regs_struct>() };
+    if unsafe { libc::ptrace(libc::PTRACE_SETREGSET, self.pid, libc::NT_PRSTATUS as usize, &mut iov) } == -1 {
+      return Err(format!("PTRACE_SETREGSET failed, error: {}", std::io::Error::last_os_error()));
+    }
+    Ok(())
+  }
+
+  /// Stop the kernel from restarting the syscall the thread was interrupted in, which would rewind
+  /// the instruction pointer we just set.
+  #[cfg(target_arch = "x86_64")]
+  fn cancel_syscall_restart(&self) -> Result<(), String> {
+    let mut regs = self.regs()?;
+    regs.0.orig_rax = u64::MAX;
+    self.set_regs(&regs)
+  }
+  #[cfg(target_arch = "aarch64")]
+  fn cancel_syscall_restart(&self) -> Result<(), String> {
+    let mut number: libc::c_int = -1;
+    let mut iov = libc::iovec { iov_base: &mut number as *mut _ as _, iov_len: std::mem::size_of::<libc::c_int>() };
+    if unsafe { libc::ptrace(libc::PTRACE_SETREGSET, self.pid, NT_ARM_SYSTEM_CALL as usize, &mut iov) } == -1 {
+      return Err(format!("PTRACE_SETREGSET failed, error: {}", std::io::Error::last_os_error()));
+    }
+    Ok(())
+  }
+
+  fn read_u64(&self, address: u64) -> Result<u64, String> { read_remote_u64(self.pid, address) }
+
+  /// Write through ptrace, which ignores page protection so code can be patched too.
+  fn write(&self, address: u64, bytes: &[u8]) -> Result<(), String> {
+    for (i, chunk) in bytes.chunks(8).enumerate() {
+      let word_address = address + i as u64 * 8;
+      let mut word = [0u8; 8];
+      if chunk.len() < 8 {
+        word = self.peek(word_address)?.to_ne_bytes();
+      }
+      word[..chunk.len()].copy_from_slice(chunk);
+
+      if unsafe { libc::ptrace(libc::PTRACE_POKEDATA, self.pid, word_address, u64::from_ne_bytes(word)) } == -1 {
+        return Err(format!("PTRACE_POKEDATA failed at {:#X}, error: {}", word_address, std::io::Error::last_os_error()));
+      }
+    }
+    Ok(())
+  }
+
+  fn peek(&self, address: u64) -> Result<u64, String> {
+    // PEEKDATA returns the word itself, so -1 is only an error if errno says so
+    unsafe {
+      *libc::__errno_location() = 0;
+      let word = libc::ptrace(libc::PTRACE_PEEKDATA, self.pid, address, 0usize);
+      if word == -1 && *libc::__errno_location() != 0 {
+        return Err(format!("PTRACE_PEEKDATA failed at {:#X}, error: {}", address, std::io::Error::last_os_error()));
+      }
+      Ok(word as u64)
+    }
+  }
+
+  /// Map `size` bytes of RWX memory in the target by running an `mmap` syscall on the stopped thread.
+  fn remote_mmap(&self, saved: &Regs, size: u64) -> Result<u64, String> {
+    let prot = (libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC) as u64;
+    let flags = (libc::MAP_PRIVATE | libc::MAP_ANONYMOUS) as u64;
+    self.remote_syscall(saved, SYS_MMAP, [0, size, prot, flags, u64::MAX, 0]).map_err(|e| format!("mmap in the target failed, error: {}", e))
+  }
+
+  /// Unmap memory mapped with [`Self::remote_mmap`].
+  fn remote_munmap(&self, saved: &Regs, address: u64, size: u64) -> Result<(), String> { self.remote_syscall(saved, SYS_MUNMAP, [address, size, 0, 0, 0, 0]).map(|_| ()).map_err(|e| format!("munmap in the target failed, error: {}", e)) }
+
+  /// Run one syscall on the stopped thread. The code at the instruction pointer is patched for the call
+  /// and restored afterwards, `saved` are the thread's registers at the stop.
+  fn remote_syscall(&self, saved: &Regs, number: u64, args: [u64; 6]) -> Result<u64, String> {
+    let ip = saved.ip();
+    let original: Vec<u8> = (0..SYSCALL_TRAP.len().div_ceil(8)).map(|i| self.peek(ip + i as u64 * 8)).collect::<Result<Vec<_>, _>>()?.into_iter().flat_map(u64::to_ne_bytes).collect();
+
+    let mut regs = *saved;
+    regs.set_syscall(number, args);
+
+    self.write(ip, SYSCALL_TRAP)?;
+    self.set_regs(&regs)?;
+    self.cancel_syscall_restart()?;
+    self.cont()?;
+
+    // Run until the trap after the syscall, forwarding anything else
+    let result = loop {
+      let status = match self.wait(0) {
+        Ok(v) => v,
+        Err(e) => break Err(e),
+      };
+      if status >> 16 == 0 && libc::WSTOPSIG(status) == libc::SIGTRAP {
+        break self.regs().map(|r| r.syscall_result());
+      }
+      if let Err(e) = self.cont_with(if status >> 16 == 0 { libc::WSTOPSIG(status) } else { 0 }) {
+        break Err(e);
+      }
+    };
+
+    self.write(ip, &original)?;
+    self.set_regs(saved)?;
+
+    let result = result?;
+    // The kernel returns -errno on failure
+    if result > -4096i64 as u64 {
+      return Err(std::io::Error::from_raw_os_error(-(result as i64) as i32).to_string());
+    }
+    Ok(result)
+  }
+}
+
+impl Drop for Tracee {
+  fn drop(&mut self) {
+    // Detaching needs the thread stopped, which it may not be after an error
+    unsafe {
+      if libc::ptrace(libc::PTRACE_DETACH, self.pid, 0usize, 0usize) == -1 && self.interrupt().is_ok() {
+        libc::ptrace(libc::PTRACE_DETACH, self.pid, 0usize, 0usize);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{path::PathBuf, process::Command};
+
+  /// A shared object we have loaded ourselves, so it exists here, but `sleep` doesn't link.
+  fn library() -> PathBuf {
+    let maps = maps(std::process::id() as libc::pid_t).unwrap();
+    let path = maps.iter().map(|(_, _, _, path)| path).find(|path| path.contains("libgcc_s")).expect("the test binary maps libgcc_s");
+    PathBuf::from(path)
+  }
+
+  #[test]
+  fn load_and_unload_into_child() {
+    let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+    let pid = child.id();
+    // Let the loader finish so the thread sits in nanosleep
+    std::thread::sleep(Duration::from_millis(200));
+
+    let path = library();
+    let deadline = Deadline::new(Duration::from_secs(10));
+    let loaded = load_library(pid, &path, &deadline);
+    let base = module_base(pid as libc::pid_t, &path);
+    let unloaded = unload_library(pid, &path, &deadline);
+    let base_after = module_base(pid as libc::pid_t, &path);
+    // Still asleep after both, it would have died on a bad context
+    let alive = child.try_wait().unwrap().is_none();
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let loaded = loaded.unwrap();
+    assert_eq!(base.unwrap(), Some(loaded));
+    unloaded.unwrap();
+    assert_eq!(base_after.unwrap(), None);
+    assert!(alive);
+  }
+}
@@ -0,0 +1,157 @@
+#[cfg(any(target_os = "windows", test))]
+use crate::logic::kenjector::Arch;
+#[cfg(target_os = "windows")]
+use crate::logic::{kenjector::{Deadline, Kenjector}, remote::RemoteResources};
+#[cfg(any(target_os = "windows", test))]
+use pelite::{image::{IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_I386}, pe32::{Pe as Pe32, PeFile as Pe32File}, pe64::{Pe as Pe64, PeFile as Pe64File, exports::Export}, util::CStr};
+#[cfg(target_os = "windows")]
+use winapi::um::winnt::PAGE_EXECUTE_READWRITE;
+
+/// The bootstrap export of a reflective DLL, which maps the raw file it lives in and calls its `DllMain`.
+#[cfg(any(target_os = "windows", test))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflectiveLoader {
+  /// The export's name as the DLL has it, compilers decorate it e.g. `_ReflectiveLoader@4` or `?ReflectiveLoader@@YA_KPEAX@Z`.
+  pub name: String,
+  pub arch: Arch,
+  pub rva: u32,
+  /// Where the loader starts in the raw file. The DLL is written into the target unmapped, so this is
+  /// the offset execution starts at, not the RVA.
+  pub file_offset: usize,
+}
+
+/// Not in pelite's list of machines.
+#[cfg(any(target_os = "windows", test))]
+const IMAGE_FILE_MACHINE_ARM64: u16 = 0xAA64;
+
+/// Find the `ReflectiveLoader` export of the DLL in `image`, PE32 or PE32+. `Ok(None)` when it is a
+/// regular DLL without one.
+#[cfg(any(target_os = "windows", test))]
+pub fn find_loader(image: &[u8]) -> Result<Option<ReflectiveLoader>, String> {
+  match Pe64File::from_bytes(image) {
+    Ok(file) => {
+      let arch = match file.file_header().Machine {
+        IMAGE_FILE_MACHINE_AMD64 => Arch::AMDx64,
+        IMAGE_FILE_MACHINE_ARM64 => Arch::Arm64,
+        machine => return Err(format!("The DLL is built for machine {:#06X}, which can't be Kenjected reflectively", machine)),
+      };
+      let Some((name, rva)) = read_exports(file.exports().and_then(|e| e.by()))?.and_then(|by| loader_export(by.iter_names())) else {
+        return Ok(None);
+      };
+      let file_offset = file.rva_to_file_offset(rva).map_err(|e| format!("{} at RVA {:#X} is not backed by the file, error: {}", name, rva, e))?;
+      Ok(Some(ReflectiveLoader { name, arch, rva, file_offset }))
+    }
+    Err(_) => {
+      let file = Pe32File::from_bytes(image).map_err(|e| format!("Failed to parse the DLL, error: {}", e))?;
+      if file.file_header().Machine != IMAGE_FILE_MACHINE_I386 {
+        return Err(format!("The DLL is built for machine {:#06X}, which can't be Kenjected reflectively", file.file_header().Machine));
+      }
+      let Some((name, rva)) = read_exports(file.exports().and_then(|e| e.by()))?.and_then(|by| loader_export(by.iter_names())) else {
+        return Ok(None);
+      };
+      let file_offset = file.rva_to_file_offset(rva).map_err(|e| format!("{} at RVA {:#X} is not backed by the file, error: {}", name, rva, e))?;
+      Ok(Some(ReflectiveLoader { name, arch: Arch::AMDx86, rva, file_offset }))
+    }
+  }
+}
+
+/// The export lookup of a DLL, `None` when it has no export directory. One that can't be read is an
+/// error, e.g. a cut off file, not a DLL without a loader.
+#[cfg(any(target_os = "windows", test))]
+fn read_exports<T>(by: pelite::Result<T>) -> Result<Option<T>, String> {
+  match by {
+    Ok(by) => Ok(Some(by)),
+    Err(e) if e.is_null() => Ok(None),
+    Err(e) => Err(format!("Failed to read the DLL's exports, error: {}", e)),
+  }
+}
+
+/// The first export whose name contains `ReflectiveLoader` and its RVA, forwarded exports don't count.
+#[cfg(any(target_os = "windows", test))]
+fn loader_export<'a>(mut names: impl Iterator<Item = (pelite::Result<&'a CStr>, pelite::Result<Export<'a>>)>) -> Option<(String, u32)> {
+  names.find_map(|(name, export)| match (name.ok()?.to_str().ok()?, export.ok()?) {
+    (name, Export::Symbol(rva)) if name.contains("ReflectiveLoader") => Some((name.to_string(), *rva)),
+    _ => None,
+  })
+}
+
+/// Write the raw DLL `image` into the process and run its loader on a new thread, returns the thread's
+/// exit code, which is what the loader returned truncated to 32 bits. The DLL never shows up in the
+/// target's module list.
+#[cfg(target_os = "windows")]
+pub fn load(resources: &mut RemoteResources, image: &[u8], loader: &ReflectiveLoader, deadline: &Deadline) -> Result<u64, String> {
+  // The loader runs from the raw copy until it has mapped the real image, so it needs to be executable.
+  // It maps the image somewhere else, so the copy can go once the loader has returned.
+  let base = resources.alloc_copy(image, PAGE_EXECUTE_READWRITE)?;
+  unsafe { Kenjector::create_remote_thread(resources, &loader.name, base + loader.file_offset as u64, 0, deadline) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// pelite reads the headers in place, so the fixtures need the alignment a file read into a `Vec` gets.
+  #[repr(C, align(8))]
+  struct Aligned<T: ?Sized>(T);
+
+  /// Built from the sources next to them, see `tests/fixtures/reflective_x64.s`.
+  static X64: &Aligned<[u8]> = &Aligned(*include_bytes!("../../tests/fixtures/reflective_x64.dll"));
+  static X86: &Aligned<[u8]> = &Aligned(*include_bytes!("../../tests/fixtures/reflective_x86.dll"));
+  static ARM64: &Aligned<[u8]> = &Aligned(*include_bytes!("../../tests/fixtures/reflective_arm64.dll"));
+  static PLAIN: &Aligned<[u8]> = &Aligned(*include_bytes!("../../tests/fixtures/plain_x64.dll"));
+
+  // Each fixture has .text at RVA 0x1000 and file offset 0x400, so the offset is the RVA less 0xC00
+
+  #[test]
+  fn finds_the_pe32_plus_loader() {
+    let loader = find_loader(&X64.0).unwrap().unwrap();
+    assert_eq!(
+      loader,
+      ReflectiveLoader {
+        name: String::from("ReflectiveLoader"),
+        arch: Arch::AMDx64,
+        rva: 0x1009,
+        file_offset: 0x409
+      }
+    );
+    // `mov rax, rcx; ret`
+    assert_eq!(X64.0[loader.file_offset..loader.file_offset + 4], [0x48, 0x89, 0xC8, 0xC3]);
+  }
+
+  #[test]
+  fn finds_the_decorated_pe32_loader() {
+    let loader = find_loader(&X86.0).unwrap().unwrap();
+    assert_eq!(
+      loader,
+      ReflectiveLoader {
+        name: String::from("_ReflectiveLoader@4"),
+        arch: Arch::AMDx86,
+        rva: 0x100B,
+        file_offset: 0x40B
+      }
+    );
+    // `mov eax, [esp + 4]; ret 4`
+    assert_eq!(X86.0[loader.file_offset..loader.file_offset + 7], [0x8B, 0x44, 0x24, 0x04, 0xC2, 0x04, 0x00]);
+  }
+
+  #[test]
+  fn tells_arm64_from_x64() {
+    let loader = find_loader(&ARM64.0).unwrap().unwrap();
+    assert_eq!(loader, ReflectiveLoader { name: String::from("ReflectiveLoader"), arch: Arch::Arm64, rva: 0x1008, file_offset: 0x408 });
+    // `ret`
+    assert_eq!(ARM64.0[loader.file_offset..loader.file_offset + 4], [0xC0, 0x03, 0x5F, 0xD6]);
+  }
+
+  #[test]
+  fn regular_dll_has_no_loader() {
+    assert_eq!(find_loader(&PLAIN.0).unwrap(), None);
+  }
+
+  #[test]
+  fn rejects_what_is_not_a_pe() {
+    assert!(find_loader(b"\x7FELF not a DLL").is_err());
+    assert!(find_loader(&X64.0[..0x100]).is_err());
+    // Headers intact, the sections with the export table cut off
+    assert!(find_loader(&X64.0[..0x600]).is_err());
+  }
+}
@@ -0,0 +1,286 @@
+#[cfg(target_os = "windows")]
+use crate::logic::kenjector::Kenjector;
+use derive_more::Display;
+#[cfg(target_os = "windows")]
+use winapi::um::{handleapi::CloseHandle, memoryapi::VirtualQueryEx, processthreadsapi::OpenProcess, psapi::GetMappedFileNameW, winnt::{MEM_COMMIT, MEM_IMAGE, MEM_MAPPED, MEM_PRIVATE, MEM_RESERVE, MEMORY_BASIC_INFORMATION, PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_GUARD, PAGE_READONLY, PAGE_READWRITE, PAGE_WRITECOPY, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum RegionState {
+  Committed,
+  /// Address space set aside without memory behind it yet.
+  #[cfg(target_os = "windows")]
+  Reserved,
+  Free,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum RegionType {
+  /// An executable or library mapped as an image.
+  Image,
+  /// A mapped file or section.
+  Mapped,
+  /// Memory of the process's own, heaps, stacks and allocations.
+  Private,
+}
+
+/// What a region may be used for, shown `rwx` style with `c` for copy-on-write and `g` for guard pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display)]
+#[display("{}{}{}{}{}", if *read { "r" } else { "-" }, if *write { "w" } else { "-" }, if *execute { "x" } else { "-" }, if *copy_on_write { "c" } else { "" }, if *guard { "g" } else { "" })]
+pub struct Protection {
+  pub read: bool,
+  pub write: bool,
+  pub execute: bool,
+  /// Writes go to a private copy of the page.
+  pub copy_on_write: bool,
+  /// The first access raises an exception, Windows uses these to grow stacks.
+  pub guard: bool,
+}
+
+impl Protection {
+  /// From a `PAGE_*` constant, e.g. `PAGE_EXECUTE_READ | PAGE_GUARD`.
+  #[cfg(target_os = "windows")]
+  pub fn from_page(protect: u32) -> Self {
+    let (read, write, execute, copy_on_write) = match protect & 0xFF {
+      PAGE_READONLY => (true, false, false, false),
+      PAGE_READWRITE => (true, true, false, false),
+      PAGE_WRITECOPY => (true, true, false, true),
+      PAGE_EXECUTE => (false, false, true, false),
+      PAGE_EXECUTE_READ => (true, false, true, false),
+      PAGE_EXECUTE_READWRITE => (true, true, true, false),
+      PAGE_EXECUTE_WRITECOPY => (true, true, true, true),
+      // PAGE_NOACCESS, or no protection at all for reserved and free regions
+      _ => (false, false, false, false),
+    };
+    Self { read, write, execute, copy_on_write, guard: protect & PAGE_GUARD != 0 }
+  }
+
+  /// From the permissions of a `/proc/<pid>/maps` line, e.g. `r-xp`. Private writable mappings are
+  /// copy-on-write.
+  #[cfg(target_os = "linux")]
+  pub fn from_maps(perms: &str) -> Option<Self> {
+    let perms = perms.as_bytes();
+    if perms.len() != 4 {
+      return None;
+    }
+    let write = perms[1] == b'w';
+    Some(Self {
+      read: perms[0] == b'r',
+      write,
+      execute: perms[2] == b'x',
+      copy_on_write: write && perms[3] == b'p',
+      guard: false,
+    })
+  }
+}
+
+/// A run of pages in another process that share state, type and protection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+  pub base: u64,
+  pub size: u64,
+  pub state: RegionState,
+  /// `None` for free regions.
+  pub kind: Option<RegionType>,
+  pub protection: Protection,
+  /// The module the region belongs to, else the mapped file, or on Linux what the kernel calls it,
+  /// e.g. `[heap]`. Empty for anonymous memory.
+  pub owner: String,
+}
+
+impl MemoryRegion {
+  pub fn end(&self) -> u64 { self.base + self.size }
+
+  /// Committed, readable and not a guard page, so reading it won't fail or have side effects.
+  pub fn is_readable(&self) -> bool { self.state == RegionState::Committed && self.protection.read && !self.protection.guard }
+}
+
+/// Every region of the process's address space, in address order.
+#[cfg(target_os = "windows")]
+pub fn query(process_id: u32) -> Result<Vec<MemoryRegion>, String> {
+  // Modules name their image regions, the rest fall back on the mapped file's device path
+  let modules = Kenjector::get_modules(process_id).unwrap_or_default();
+
+  let mut regions = Vec::new();
+  unsafe {
+    let process = OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, 0, process_id);
+    if process.is_null() {
+      return Err(format!("OpenProcess failed, error: {:#X?}", std::io::Error::last_os_error()));
+    }
+
+    let mut address = 0u64;
+    let mut info: MEMORY_BASIC_INFORMATION = std::mem::zeroed();
+    while VirtualQueryEx(process, address as _, &mut info, std::mem::size_of::<MEMORY_BASIC_INFORMATION>()) != 0 {
+      let (base, size) = (info.BaseAddress as u64, info.RegionSize as u64);
+      let state = match info.State {
+        MEM_COMMIT => RegionState::Committed,
+        MEM_RESERVE => RegionState::Reserved,
+        _ => RegionState::Free,
+      };
+      let kind = match info.Type {
+        MEM_IMAGE => Some(RegionType::Image),
+        MEM_MAPPED => Some(RegionType::Mapped),
+        MEM_PRIVATE => Some(RegionType::Private),
+        _ => None,
+      };
+
+      let owner = match modules.iter().find(|m| (m.base..m.base + m.size as u64).contains(&base)) {
+        Some(module) => module.name.clone(),
+        None if matches!(kind, Some(RegionType::Image | RegionType::Mapped)) => {
+          let mut name = [0u16; 1024];
+          let len = GetMappedFileNameW(process, base as _, name.as_mut_ptr(), name.len() as u32);
+          String::from_utf16_lossy(&name[..len as usize])
+        }
+        None => String::new(),
+      };
+
+      regions.push(MemoryRegion { base, size, state, kind, protection: Protection::from_page(info.Protect), owner });
+      match base.checked_add(size) {
+        Some(next) if next > address => address = next,
+        _ => break,
+      }
+    }
+
+    CloseHandle(process);
+  }
+
+  if regions.is_empty() {
+    return Err(format!("VirtualQueryEx failed, error: {:#X?}", std::io::Error::last_os_error()));
+  }
+  Ok(regions)
+}
+
+/// Every mapping of the process, from `/proc/<pid>/maps`. Linux commits memory lazily and doesn't say
+/// which pages are, so every mapping counts as committed.
+#[cfg(target_os = "linux")]
+pub fn query(process_id: u32) -> Result<Vec<MemoryRegion>, String> {
+  let maps = std::fs::read_to_string(format!("/proc/{}/maps", process_id)).map_err(|e| format!("Failed to read the memory map of {}, error: {}", process_id, e))?;
+  Ok(parse_maps(&maps))
+}
+
+/// Parse the lines of a `/proc/<pid>/maps` file, lines that don't parse are skipped.
+///
+/// A line is `start-end perms offset dev inode path`, the path padded with spaces and possibly
+/// containing some itself, or ending in ` (deleted)`. Every mapping of a file that is executable in
+/// part counts as an image, like the sections of a Windows module.
+#[cfg(target_os = "linux")]
+pub fn parse_maps(maps: &str) -> Vec<MemoryRegion> {
+  let mut regions: Vec<MemoryRegion> = maps
+    .lines()
+    .filter_map(|line| {
+      let mut fields = line.splitn(6, ' ');
+      let (start, end) = fields.next()?.split_once('-')?;
+      let (base, end) = (u64::from_str_radix(start, 16).ok()?, u64::from_str_radix(end, 16).ok()?);
+      let protection = Protection::from_maps(fields.next()?)?;
+      let inode = fields.nth(2)?;
+      let owner = fields.next().unwrap_or_default().trim_start().to_string();
+
+      // Anything backed by a file has an inode, [heap], [stack] and anonymous memory don't
+      let kind = if inode != "0" { RegionType::Mapped } else { RegionType::Private };
+      // Private anonymous memory has no file to copy from
+      let protection = Protection { copy_on_write: protection.copy_on_write && kind == RegionType::Mapped, ..protection };
+      Some(MemoryRegion {
+        base,
+        size: end.checked_sub(base)?,
+        state: RegionState::Committed,
+        kind: Some(kind),
+        protection,
+        owner,
+      })
+    })
+    .collect();
+
+  let images = regions.iter().filter(|r| r.kind == Some(RegionType::Mapped) && r.protection.execute).map(|r| r.owner.clone()).collect::<std::collections::HashSet<_>>();
+  for region in &mut regions {
+    if region.kind == Some(RegionType::Mapped) && images.contains(&region.owner) {
+      region.kind = Some(RegionType::Image);
+    }
+  }
+  regions
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+
+  const MAPS: &str = "\
+55d0c6a00000-55d0c6a02000 r--p 00000000 fd:01 1311237                    /usr/bin/sleep
+55d0c6a02000-55d0c6a06000 r-xp 00002000 fd:01 1311237                    /usr/bin/sleep
+55d0c7b1e000-55d0c7b3f000 rw-p 00000000 00:00 0                          [heap]
+7f3a1c000000-7f3a1c021000 rw-p 00000000 00:00 0 
+7f3a1d200000-7f3a1d228000 r--p 00000000 fd:01 1316109                    /opt/My Tools/lib payload.so
+7f3a1d228000-7f3a1d3bd000 r-xp 00028000 fd:01 1316109                    /opt/My Tools/lib payload.so
+7f3a1d400000-7f3a1d401000 r-xp 00000000 fd:01 1316222                    /tmp/old.so (deleted)
+7f3a1d500000-7f3a1d600000 rw-s 00000000 00:01 2049                       /dev/shm/ring
+7ffd4e8f2000-7ffd4e913000 rw-p 00000000 00:00 0                          [stack]
+ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsyscall]
+";
+
+  fn owned<'a>(regions: &'a [MemoryRegion], owner: &str) -> Vec<&'a MemoryRegion> { regions.iter().filter(|r| r.owner == owner).collect() }
+
+  #[test]
+  fn paths_with_spaces_stay_whole() {
+    let regions = parse_maps(MAPS);
+    let payload = owned(&regions, "/opt/My Tools/lib payload.so");
+    assert_eq!(payload.len(), 2);
+    assert_eq!((payload[0].base, payload[0].size), (0x7f3a1d200000, 0x28000));
+    // Executable in part, so every mapping of it is an image
+    assert!(payload.iter().all(|r| r.kind == Some(RegionType::Image)));
+    assert!(payload[1].protection.execute && !payload[0].protection.execute);
+  }
+
+  #[test]
+  fn deleted_files_keep_the_marker() {
+    let regions = parse_maps(MAPS);
+    let deleted = owned(&regions, "/tmp/old.so (deleted)");
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].kind, Some(RegionType::Image));
+  }
+
+  #[test]
+  fn pseudo_paths_and_anonymous_memory_are_private() {
+    let regions = parse_maps(MAPS);
+    for owner in ["[heap]", "[stack]", "[vsyscall]", ""] {
+      let region = owned(&regions, owner);
+      assert_eq!(region.len(), 1, "{}", owner);
+      assert_eq!(region[0].kind, Some(RegionType::Private), "{}", owner);
+      // Nothing to copy from
+      assert!(!region[0].protection.copy_on_write, "{}", owner);
+    }
+    let heap = owned(&regions, "[heap]")[0];
+    assert!(heap.protection.read && heap.protection.write && !heap.protection.execute);
+    let vsyscall = owned(&regions, "[vsyscall]")[0];
+    assert!(!vsyscall.protection.read && vsyscall.protection.execute && !vsyscall.is_readable());
+  }
+
+  #[test]
+  fn file_mappings() {
+    let regions = parse_maps(MAPS);
+    let sleep = owned(&regions, "/usr/bin/sleep");
+    assert!(sleep.iter().all(|r| r.kind == Some(RegionType::Image) && r.protection.copy_on_write == r.protection.write));
+    // Shared and never executable, so mapped rather than an image
+    let ring = owned(&regions, "/dev/shm/ring")[0];
+    assert_eq!(ring.kind, Some(RegionType::Mapped));
+    assert!(ring.protection.write && !ring.protection.copy_on_write);
+    assert!(regions.iter().all(|r| r.state == RegionState::Committed));
+    assert!(regions.windows(2).all(|w| w[0].end() <= w[1].base));
+  }
+
+  #[test]
+  fn malformed_lines_are_skipped() {
+    let maps = "\
+not a mapping
+7f0000001000 r--p 00000000 00:00 0
+7f0000001000-7f0000002000 r--p
+7f0000001000-7f0000002000 rw 00000000 00:00 0
+7f000000zz00-7f0000002000 r--p 00000000 00:00 0
+7f0000003000-7f0000002000 r--p 00000000 00:00 0
+
+7f0000004000-7f0000005000 r--p 00000000 fd:01 42 /lib/ok.so
+";
+    let regions = parse_maps(maps);
+    assert_eq!(regions.len(), 1);
+    assert_eq!((regions[0].base, regions[0].size, regions[0].owner.as_str()), (0x7f0000004000, 0x1000, "/lib/ok.so"));
+    assert_eq!(regions[0].kind, Some(RegionType::Mapped));
+    assert!(parse_maps("").is_empty());
+  }
+}
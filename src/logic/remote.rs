@@ -0,0 +1,233 @@
+#[cfg(target_os = "windows")]
+use winapi::um::{handleapi::CloseHandle, memoryapi::{VirtualAllocEx, VirtualFreeEx, WriteProcessMemory}, processthreadsapi::OpenProcess, winnt::{HANDLE, MEM_COMMIT, MEM_RELEASE, MEM_RESERVE}};
+
+/// The memory calls [`RemoteResources`] makes into the target, [`Process`] on Windows.
+pub trait RemoteMemory {
+  /// Allocate `size` bytes with the page protection `protect`, returns the address.
+  fn alloc(&mut self, size: usize, protect: u32) -> Result<u64, String>;
+  fn write(&mut self, address: u64, data: &[u8]) -> Result<(), String>;
+  /// Free an allocation made by [`RemoteMemory::alloc`].
+  fn free(&mut self, address: u64) -> Result<(), String>;
+}
+
+/// A process handle, closed on drop.
+#[cfg(target_os = "windows")]
+pub struct Process(HANDLE);
+
+#[cfg(target_os = "windows")]
+impl RemoteMemory for Process {
+  fn alloc(&mut self, size: usize, protect: u32) -> Result<u64, String> {
+    match unsafe { VirtualAllocEx(self.0, std::ptr::null_mut(), size, MEM_COMMIT | MEM_RESERVE, protect) } as u64 {
+      0 => Err(format!("VirtualAllocEx failed, error: {:#X?}", std::io::Error::last_os_error())),
+      address => Ok(address),
+    }
+  }
+
+  fn write(&mut self, address: u64, data: &[u8]) -> Result<(), String> {
+    if unsafe { WriteProcessMemory(self.0, address as _, data.as_ptr() as _, data.len(), std::ptr::null_mut()) } == 0 {
+      return Err(format!("WriteProcessMemory failed, error: {:#X?}", std::io::Error::last_os_error()));
+    }
+    Ok(())
+  }
+
+  fn free(&mut self, address: u64) -> Result<(), String> {
+    if unsafe { VirtualFreeEx(self.0, address as _, 0, MEM_RELEASE) } == 0 {
+      return Err(format!("VirtualFreeEx failed at {:#X}, error: {:#X?}", address, std::io::Error::last_os_error()));
+    }
+    Ok(())
+  }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for Process {
+  fn drop(&mut self) { unsafe { CloseHandle(self.0) }; }
+}
+
+/// The target process of one Kenjection and everything allocated or opened in it along the way.
+/// [`RemoteResources::finish`] frees the allocations and closes the handles and reports what failed.
+/// Dropping it does the same without the report, so early returns on failure don't leak.
+#[cfg(target_os = "windows")]
+pub struct RemoteResources<M: RemoteMemory = Process> {
+  memory: M,
+  allocations: Vec<u64>,
+  handles: Vec<HANDLE>,
+}
+
+/// The target process of one Kenjection and everything allocated in it along the way.
+/// [`RemoteResources::finish`] frees the allocations and reports what failed. Dropping it does the
+/// same without the report, so early returns on failure don't leak.
+#[cfg(not(target_os = "windows"))]
+pub struct RemoteResources<M: RemoteMemory> {
+  memory: M,
+  allocations: Vec<u64>,
+}
+
+#[cfg(target_os = "windows")]
+impl RemoteResources {
+  /// Open the process with `access`.
+  pub fn open(process_id: u32, access: u32) -> Result<Self, String> {
+    let process = unsafe { OpenProcess(access, 0, process_id) };
+    if process.is_null() {
+      return Err(format!("OpenProcess failed, error: {:#X?}", std::io::Error::last_os_error()));
+    }
+    Ok(Self::new(Process(process)))
+  }
+
+  pub fn process(&self) -> HANDLE { self.memory.0 }
+
+  /// Close `handle` when done, e.g. a thread started in the target.
+  pub fn track(&mut self, handle: HANDLE) -> HANDLE {
+    self.handles.push(handle);
+    handle
+  }
+}
+
+impl<M: RemoteMemory> RemoteResources<M> {
+  fn new(memory: M) -> Self {
+    Self {
+      memory,
+      allocations: Vec::new(),
+      #[cfg(target_os = "windows")]
+      handles: Vec::new(),
+    }
+  }
+
+  /// Allocate `size` bytes in the target, freed when done.
+  pub fn alloc(&mut self, size: usize, protect: u32) -> Result<u64, String> {
+    let address = self.memory.alloc(size, protect)?;
+    self.allocations.push(address);
+    Ok(address)
+  }
+
+  /// Allocate room for `data` in the target and copy it there.
+  pub fn alloc_copy<T: Copy>(&mut self, data: &[T], protect: u32) -> Result<u64, String> {
+    let size = std::mem::size_of_val(data);
+    let address = self.alloc(size, protect)?;
+    // Called with integer slices, which have no padding bytes
+    let bytes = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, size) };
+    self.memory.write(address, bytes)?;
+    Ok(address)
+  }
+
+  /// Leave every allocation made so far in the target, for when a thread of it may still be running
+  /// the code or reading the data. Freeing it then would crash the target. Returns a note saying so
+  /// for the error the Kenjection ends with.
+  pub fn keep_allocations(&mut self) -> String {
+    let addresses: Vec<String> = self.allocations.drain(..).map(|address| format!("{:#X}", address)).collect();
+    format!("The memory at {} stays allocated in the target, it may still be in use", addresses.join(", "))
+  }
+
+  /// Free the allocations and close the handles. A successful `result` stays one, with what failed
+  /// on the way as a warning next to it, since the remote call already did its work. A failed one gets
+  /// it added to the error.
+  pub fn finish<T>(mut self, result: Result<T, String>) -> Result<(T, Option<String>), String> {
+    match (result, self.release()) {
+      (Ok(value), Ok(())) => Ok((value, None)),
+      (Ok(value), Err(e)) => Ok((value, Some(format!("Cleaning up in the target failed. {}", e)))),
+      (Err(error), Ok(())) => Err(error),
+      (Err(error), Err(e)) => Err(format!("{}. Cleaning up failed too. {}", error, e)),
+    }
+  }
+
+  fn release(&mut self) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    for handle in self.handles.drain(..) {
+      unsafe { CloseHandle(handle) };
+    }
+    let errors: Vec<String> = self.allocations.drain(..).filter_map(|address| self.memory.free(address).err()).collect();
+    if errors.is_empty() { Ok(()) } else { Err(errors.join(". ")) }
+  }
+}
+
+impl<M: RemoteMemory> Drop for RemoteResources<M> {
+  fn drop(&mut self) { let _ = self.release(); }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+  /// A target whose memory is a map from address to contents, shared with the test so it can look for leaks.
+  #[derive(Default, Clone)]
+  struct FakeProcess {
+    memory: Rc<RefCell<BTreeMap<u64, Vec<u8>>>>,
+    fail_writes: bool,
+    fail_frees: bool,
+  }
+
+  impl RemoteMemory for FakeProcess {
+    fn alloc(&mut self, size: usize, _protect: u32) -> Result<u64, String> {
+      let mut memory = self.memory.borrow_mut();
+      let address = memory.last_key_value().map_or(0x10000, |(address, _)| address + 0x10000);
+      memory.insert(address, vec![0; size]);
+      Ok(address)
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) -> Result<(), String> {
+      if self.fail_writes {
+        return Err(format!("write failed at {:#X}", address));
+      }
+      self.memory.borrow_mut().get_mut(&address).ok_or("not allocated")?.copy_from_slice(data);
+      Ok(())
+    }
+
+    fn free(&mut self, address: u64) -> Result<(), String> {
+      if self.fail_frees {
+        return Err(format!("free failed at {:#X}", address));
+      }
+      self.memory.borrow_mut().remove(&address).map(|_| ()).ok_or_else(|| format!("{:#X} is not allocated", address))
+    }
+  }
+
+  #[test]
+  fn finish_frees_everything() {
+    let process = FakeProcess::default();
+    let mut resources = RemoteResources::new(process.clone());
+    let path = resources.alloc_copy(&[0x43u16, 0x3A, 0], 4).unwrap();
+    resources.alloc(64, 0x40).unwrap();
+    assert_eq!(process.memory.borrow()[&path], [0x43, 0, 0x3A, 0, 0, 0]);
+    assert_eq!(resources.finish(Ok(0x7FF0_0000u64)), Ok((0x7FF0_0000, None)));
+    assert!(process.memory.borrow().is_empty());
+  }
+
+  #[test]
+  fn early_return_does_not_leak() {
+    let process = FakeProcess::default();
+    let inject = |process: FakeProcess| -> Result<u64, String> {
+      let mut resources = RemoteResources::new(process);
+      resources.alloc(64, 0x40)?;
+      resources.alloc_copy(b"payload.dll\0", 4)
+    };
+    assert!(inject(FakeProcess { fail_writes: true, ..process.clone() }).is_err());
+    assert!(process.memory.borrow().is_empty());
+  }
+
+  #[test]
+  fn kept_allocations_stay_and_are_reported() {
+    let process = FakeProcess::default();
+    let mut resources = RemoteResources::new(process.clone());
+    let code = resources.alloc(64, 0x40).unwrap();
+    let data = resources.alloc(8, 4).unwrap();
+    let note = resources.keep_allocations();
+    assert!(note.contains(&format!("{:#X}, {:#X}", code, data)), "{}", note);
+    // Allocations made after keeping the others are still freed
+    resources.alloc(16, 4).unwrap();
+    assert_eq!(resources.finish(Err::<u64, _>(note.clone())), Err(note));
+    assert_eq!(process.memory.borrow().keys().copied().collect::<Vec<_>>(), [code, data]);
+  }
+
+  #[test]
+  fn failed_frees_are_reported() {
+    let process = FakeProcess { fail_frees: true, ..Default::default() };
+    let mut resources = RemoteResources::new(process.clone());
+    resources.alloc(64, 0x40).unwrap();
+    resources.alloc(8, 4).unwrap();
+    // The call still succeeded, the failed frees are a warning
+    assert_eq!(resources.finish(Ok(0x1000u64)), Ok((0x1000, Some(String::from("Cleaning up in the target failed. free failed at 0x10000. free failed at 0x20000")))));
+
+    let mut resources = RemoteResources::new(process);
+    resources.alloc(8, 4).unwrap();
+    assert_eq!(resources.finish(Err::<u64, _>(String::from("Timed out"))), Err(String::from("Timed out. Cleaning up failed too. free failed at 0x30000")));
+  }
+}
@@ -0,0 +1,674 @@
+use crate::logic::kenjector::Arch;
+use derive_more::Display;
+
+/// Calling convention the stub uses to call its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+pub enum CallConv {
+  /// Windows x64: rcx, rdx, r8, r9, then the stack above 32 bytes of shadow space.
+  Win64,
+  /// System V x86-64 (Linux): rdi, rsi, rdx, rcx, r8, r9, then the stack.
+  SysV64,
+  /// 32-bit x86 stdcall or cdecl, every argument on the stack.
+  X86,
+  /// AArch64 procedure call standard, Windows and Linux alike: x0-x7, then the stack.
+  Aapcs64,
+}
+
+impl CallConv {
+  /// The convention used by Windows APIs such as `LoadLibraryW` for a process of `arch`.
+  #[cfg(any(target_os = "windows", test))]
+  pub fn windows(arch: Arch) -> Result<Self, String> {
+    match arch {
+      Arch::AMDx64 => Ok(Self::Win64),
+      Arch::AMDx86 => Ok(Self::X86),
+      Arch::Arm64 => Ok(Self::Aapcs64),
+      Arch::Unknown => Err(String::from("Can't build a call stub for an unknown architecture")),
+    }
+  }
+
+  /// The C convention of a Linux process of `arch`.
+  pub fn linux(arch: Arch) -> Result<Self, String> {
+    match arch {
+      Arch::AMDx64 => Ok(Self::SysV64),
+      Arch::AMDx86 => Ok(Self::X86),
+      Arch::Arm64 => Ok(Self::Aapcs64),
+      Arch::Unknown => Err(String::from("Can't build a call stub for an unknown architecture")),
+    }
+  }
+}
+
+/// What the stub does once the call returned and completion was signalled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StubExit {
+  /// `ret`, for calling the stub like a function as the execution test does.
+  #[cfg(test)]
+  Return,
+  /// Loop in place until the injector restores the thread's original context.
+  Spin,
+}
+
+/// A position-independent stub that calls `target(args...)`, stores the return value at `result`
+/// and then writes the u32 `1` to `done`.
+///
+/// Every general purpose register the call may clobber is saved and restored around it, and so are the
+/// flags, so the stub can run on a hijacked thread. On x86 and x64 `fxsave` covers the x87 and SSE state
+/// (xmm0-15) but not the upper halves of the AVX and AVX-512 registers, which a callee using them leaves
+/// changed. On ARM64 all of q0-q31 are saved. The stack is realigned before the call.
+#[derive(Debug, Clone)]
+pub struct CallStub {
+  pub conv: CallConv,
+  pub target: u64,
+  pub args: Vec<u64>,
+  /// Receives the return value: 8 bytes, or 4 on x86.
+  pub result: u64,
+  /// Receives a u32 `1` after `result` is written.
+  pub done: u64,
+  pub exit: StubExit,
+}
+
+impl CallStub {
+  /// Offset of the final `jmp $` or `b .` in the assembled `code` of a [`StubExit::Spin`] stub. `done` is
+  /// written while the stub still holds its own registers, only a thread that reached this offset has them all restored.
+  pub fn spin_offset(&self, code: &[u8]) -> usize { if self.conv == CallConv::Aapcs64 { code.len() - 4 } else { code.len() - 2 } }
+
+  pub fn assemble(&self) -> Result<Vec<u8>, String> {
+    match self.conv {
+      CallConv::Win64 | CallConv::SysV64 => Ok(self.assemble_x64()),
+      CallConv::X86 => self.assemble_x86(),
+      CallConv::Aapcs64 => Ok(self.assemble_arm64()),
+    }
+  }
+
+  fn assemble_x64(&self) -> Vec<u8> {
+    // (opcode prefix, register number) of `mov reg, imm64` for each register argument
+    const WIN64_ARGS: &[(u8, u8)] = &[(0x48, 1), (0x48, 2), (0x49, 0), (0x49, 1)]; // rcx, rdx, r8, r9
+    const SYSV_ARGS: &[(u8, u8)] = &[(0x48, 7), (0x48, 6), (0x48, 2), (0x48, 1), (0x49, 0), (0x49, 1)]; // rdi, rsi, rdx, rcx, r8, r9
+    // push rax, rcx, rdx, rbx, rbp, rsi, rdi, r8, r9, r10, r11
+    const SAVED: &[&[u8]] = &[&[0x50], &[0x51], &[0x52], &[0x53], &[0x55], &[0x56], &[0x57], &[0x41, 0x50], &[0x41, 0x51], &[0x41, 0x52], &[0x41, 0x53]];
+
+    let (arg_regs, shadow) = if self.conv == CallConv::Win64 { (WIN64_ARGS, 32) } else { (SYSV_ARGS, 0) };
+    let stack_args = &self.args[self.args.len().min(arg_regs.len())..];
+    let frame = (shadow + 8 * stack_args.len() as u32).next_multiple_of(16);
+
+    let mut code = Vec::new();
+
+    // Step over the System V red zone, then save flags and the volatile registers
+    code.extend([0x48, 0x8D, 0x64, 0x24, 0x80]); // lea rsp, [rsp - 0x80]
+    code.push(0x9C); // pushfq
+    for push in SAVED {
+      code.extend(*push);
+    }
+
+    code.extend([0x48, 0x89, 0xE5]); // mov rbp, rsp
+    code.extend([0x48, 0x83, 0xE4, 0xF0]); // and rsp, -16
+    code.extend([0x48, 0x81, 0xEC, 0x00, 0x02, 0x00, 0x00]); // sub rsp, 512
+    code.extend([0x48, 0x0F, 0xAE, 0x04, 0x24]); // fxsave64 [rsp]
+    code.extend([0x48, 0x89, 0xE3]); // mov rbx, rsp
+
+    if frame > 0 {
+      code.extend([0x48, 0x81, 0xEC]); // sub rsp, frame
+      code.extend(frame.to_le_bytes());
+    }
+
+    for (i, arg) in stack_args.iter().enumerate() {
+      code.extend([0x48, 0xB8]); // mov rax, arg
+      code.extend(arg.to_le_bytes());
+      code.extend([0x48, 0x89, 0x84, 0x24]); // mov [rsp + shadow + 8 * i], rax
+      code.extend((shadow + 8 * i as u32).to_le_bytes());
+    }
+
+    for ((prefix, reg), arg) in arg_regs.iter().zip(&self.args) {
+      code.extend([*prefix, 0xB8 + reg]); // mov reg, arg
+      code.extend(arg.to_le_bytes());
+    }
+
+    code.extend([0x48, 0xB8]); // mov rax, target
+    code.extend(self.target.to_le_bytes());
+    code.extend([0xFF, 0xD0]); // call rax
+
+    code.extend([0x48, 0xB9]); // mov rcx, result
+    code.extend(self.result.to_le_bytes());
+    code.extend([0x48, 0x89, 0x01]); // mov [rcx], rax
+    code.extend([0x48, 0xB9]); // mov rcx, done
+    code.extend(self.done.to_le_bytes());
+    code.extend([0xC7, 0x01, 0x01, 0x00, 0x00, 0x00]); // mov dword [rcx], 1
+
+    code.extend([0x48, 0x89, 0xDC]); // mov rsp, rbx
+    code.extend([0x48, 0x0F, 0xAE, 0x0C, 0x24]); // fxrstor64 [rsp]
+    code.extend([0x48, 0x89, 0xEC]); // mov rsp, rbp
+    for push in SAVED.iter().rev() {
+      // pop is push + 8 on the same register
+      let mut pop = push.to_vec();
+      *pop.last_mut().unwrap() += 8;
+      code.extend(pop);
+    }
+    code.push(0x9D); // popfq
+    code.extend([0x48, 0x8D, 0xA4, 0x24, 0x80, 0x00, 0x00, 0x00]); // lea rsp, [rsp + 0x80]
+
+    match self.exit {
+      #[cfg(test)]
+      StubExit::Return => code.push(0xC3), // ret
+      StubExit::Spin => code.extend([0xEB, 0xFE]), // jmp $
+    }
+
+    code
+  }
+
+  fn assemble_x86(&self) -> Result<Vec<u8>, String> {
+    let imm32 = |value: u64, what: &str| u32::try_from(value).map(u32::to_le_bytes).map_err(|_| format!("{} {:#X} does not fit in 32 bits", what, value));
+
+    let mut code = Vec::new();
+
+    code.push(0x9C); // pushfd
+    code.push(0x60); // pushad
+    code.extend([0x89, 0xE5]); // mov ebp, esp
+    code.extend([0x83, 0xE4, 0xF0]); // and esp, -16
+    code.extend([0x81, 0xEC, 0x00, 0x02, 0x00, 0x00]); // sub esp, 512
+    code.extend([0x0F, 0xAE, 0x04, 0x24]); // fxsave [esp]
+    code.extend([0x89, 0xE6]); // mov esi, esp
+
+    // Keep esp 16-byte aligned at the call
+    let padding = (16 - (4 * self.args.len()) % 16) % 16;
+    if padding > 0 {
+      code.extend([0x83, 0xEC, padding as u8]); // sub esp, padding
+    }
+
+    for arg in self.args.iter().rev() {
+      code.push(0x68); // push arg
+      code.extend(imm32(*arg, "Argument")?);
+    }
+
+    code.push(0xB8); // mov eax, target
+    code.extend(imm32(self.target, "Target")?);
+    code.extend([0xFF, 0xD0]); // call eax
+
+    // esi is callee-saved, so it still points at the fxsave area whether the callee popped its arguments or not
+    code.push(0xA3); // mov [result], eax
+    code.extend(imm32(self.result, "Result address")?);
+    code.extend([0xC7, 0x05]); // mov dword [done], 1
+    code.extend(imm32(self.done, "Done address")?);
+    code.extend(1u32.to_le_bytes());
+
+    code.extend([0x89, 0xF4]); // mov esp, esi
+    code.extend([0x0F, 0xAE, 0x0C, 0x24]); // fxrstor [esp]
+    code.extend([0x89, 0xEC]); // mov esp, ebp
+    code.push(0x61); // popad
+    code.push(0x9D); // popfd
+
+    match self.exit {
+      #[cfg(test)]
+      StubExit::Return => code.push(0xC3), // ret
+      StubExit::Spin => code.extend([0xEB, 0xFE]), // jmp $
+    }
+
+    Ok(code)
+  }
+
+  fn assemble_arm64(&self) -> Vec<u8> {
+    const IP0: u32 = 16;
+    const IP1: u32 = 17;
+    const SP: u32 = 31;
+
+    let stack_args = &self.args[self.args.len().min(8)..];
+    let frame = (8 * stack_args.len() as u32).next_multiple_of(16);
+
+    let mut ops: Vec<u32> = Vec::new();
+
+    // Save the frame pair, x0-x17, flags and all of q0-q31
+    ops.push(a64::stp_pre(29, 30, SP, -16));
+    for reg in (0..18).step_by(2) {
+      ops.push(a64::stp_pre(reg, reg + 1, SP, -16));
+    }
+    ops.push(a64::mrs_nzcv(IP0));
+    ops.push(a64::str_pre(IP0, SP, -16));
+    for reg in (0..32).step_by(2) {
+      ops.push(a64::stp_q_pre(reg, reg + 1, SP, -32));
+    }
+
+    if frame > 0 {
+      ops.push(a64::sub_imm(SP, SP, frame));
+      for (i, arg) in stack_args.iter().enumerate() {
+        ops.extend(a64::mov_imm64(IP0, *arg));
+        ops.push(a64::str_uoff(IP0, SP, 8 * i as u32));
+      }
+    }
+
+    for (reg, arg) in self.args.iter().take(8).enumerate() {
+      ops.extend(a64::mov_imm64(reg as u32, *arg));
+    }
+
+    ops.extend(a64::mov_imm64(IP0, self.target));
+    ops.push(a64::blr(IP0));
+
+    if frame > 0 {
+      ops.push(a64::add_imm(SP, SP, frame));
+    }
+
+    ops.extend(a64::mov_imm64(IP0, self.result));
+    ops.push(a64::str_uoff(0, IP0, 0));
+    ops.extend(a64::mov_imm64(IP0, self.done));
+    ops.push(a64::movz_w(IP1, 1));
+    ops.push(a64::str_w_uoff(IP1, IP0, 0));
+
+    for reg in (0..32).step_by(2).rev() {
+      ops.push(a64::ldp_q_post(reg, reg + 1, SP, 32));
+    }
+    ops.push(a64::ldr_post(IP0, SP, 16));
+    ops.push(a64::msr_nzcv(IP0));
+    for reg in (0..18).step_by(2).rev() {
+      ops.push(a64::ldp_post(reg, reg + 1, SP, 16));
+    }
+    ops.push(a64::ldp_post(29, 30, SP, 16));
+
+    match self.exit {
+      #[cfg(test)]
+      StubExit::Return => ops.push(a64::RET),
+      StubExit::Spin => ops.push(a64::B_SELF),
+    }
+
+    ops.iter().flat_map(|op| op.to_le_bytes()).collect()
+  }
+}
+
+/// The handful of AArch64 encodings the stubs need.
+mod a64 {
+  #[cfg(test)]
+  pub const RET: u32 = 0xD65F03C0;
+  /// `b .`
+  pub const B_SELF: u32 = 0x14000000;
+
+  fn imm7(offset: i32, scale: i32) -> u32 { ((offset / scale) as u32 & 0x7F) << 15 }
+
+  /// `stp xt, xt2, [xn, #offset]!`
+  pub fn stp_pre(rt: u32, rt2: u32, rn: u32, offset: i32) -> u32 { 0xA9800000 | imm7(offset, 8) | rt2 << 10 | rn << 5 | rt }
+  /// `ldp xt, xt2, [xn], #offset`
+  pub fn ldp_post(rt: u32, rt2: u32, rn: u32, offset: i32) -> u32 { 0xA8C00000 | imm7(offset, 8) | rt2 << 10 | rn << 5 | rt }
+  /// `stp qt, qt2, [xn, #offset]!`
+  pub fn stp_q_pre(rt: u32, rt2: u32, rn: u32, offset: i32) -> u32 { 0xAD800000 | imm7(offset, 16) | rt2 << 10 | rn << 5 | rt }
+  /// `ldp qt, qt2, [xn], #offset`
+  pub fn ldp_q_post(rt: u32, rt2: u32, rn: u32, offset: i32) -> u32 { 0xACC00000 | imm7(offset, 16) | rt2 << 10 | rn << 5 | rt }
+  /// `str xt, [xn, #offset]!`
+  pub fn str_pre(rt: u32, rn: u32, offset: i32) -> u32 { 0xF8000C00 | (offset as u32 & 0x1FF) << 12 | rn << 5 | rt }
+  /// `ldr xt, [xn], #offset`
+  pub fn ldr_post(rt: u32, rn: u32, offset: i32) -> u32 { 0xF8400400 | (offset as u32 & 0x1FF) << 12 | rn << 5 | rt }
+  /// `str xt, [xn, #offset]`
+  pub fn str_uoff(rt: u32, rn: u32, offset: u32) -> u32 { 0xF9000000 | (offset / 8) << 10 | rn << 5 | rt }
+  /// `str wt, [xn, #offset]`
+  pub fn str_w_uoff(rt: u32, rn: u32, offset: u32) -> u32 { 0xB9000000 | (offset / 4) << 10 | rn << 5 | rt }
+  /// `sub xd, xn, #imm`
+  pub fn sub_imm(rd: u32, rn: u32, imm: u32) -> u32 { 0xD1000000 | imm << 10 | rn << 5 | rd }
+  /// `add xd, xn, #imm`
+  pub fn add_imm(rd: u32, rn: u32, imm: u32) -> u32 { 0x91000000 | imm << 10 | rn << 5 | rd }
+  /// `movz wd, #imm`
+  pub fn movz_w(rd: u32, imm: u16) -> u32 { 0x52800000 | (imm as u32) << 5 | rd }
+  /// `blr xn`
+  pub fn blr(rn: u32) -> u32 { 0xD63F0000 | rn << 5 }
+  /// `mrs xt, nzcv`
+  pub fn mrs_nzcv(rt: u32) -> u32 { 0xD53B4200 | rt }
+  /// `msr nzcv, xt`
+  pub fn msr_nzcv(rt: u32) -> u32 { 0xD51B4200 | rt }
+
+  /// `movz` + three `movk`, always four instructions so stub sizes don't depend on the values.
+  pub fn mov_imm64(rd: u32, value: u64) -> [u32; 4] {
+    let part = |shift: u32| ((value >> (16 * shift)) & 0xFFFF) as u32;
+    [0xD2800000 | part(0) << 5 | rd, 0xF2A00000 | part(1) << 5 | rd, 0xF2C00000 | part(2) << 5 | rd, 0xF2E00000 | part(3) << 5 | rd]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// `"48 89 E5"` style hex, one string per instruction.
+  fn bytes(instructions: &[&str]) -> Vec<u8> { instructions.iter().flat_map(|i| i.split_whitespace()).map(|b| u8::from_str_radix(b, 16).unwrap()).collect() }
+
+  fn words(ops: &[u32]) -> Vec<u8> { ops.iter().flat_map(|op| op.to_le_bytes()).collect() }
+
+  #[test]
+  fn win64_golden() {
+    let stub = CallStub {
+      conv: CallConv::Win64,
+      target: 0x1122334455667788,
+      args: vec![1, 2, 3, 4, 5],
+      result: 0xAAAA0000,
+      done: 0xAAAA0008,
+      exit: StubExit::Spin,
+    };
+    let expected = bytes(&[
+      "48 8D 64 24 80",                // lea rsp, [rsp - 0x80]
+      "9C",                            // pushfq
+      "50",                            // push rax
+      "51",                            // push rcx
+      "52",                            // push rdx
+      "53",                            // push rbx
+      "55",                            // push rbp
+      "56",                            // push rsi
+      "57",                            // push rdi
+      "41 50",                         // push r8
+      "41 51",                         // push r9
+      "41 52",                         // push r10
+      "41 53",                         // push r11
+      "48 89 E5",                      // mov rbp, rsp
+      "48 83 E4 F0",                   // and rsp, -0x10
+      "48 81 EC 00 02 00 00",          // sub rsp, 0x200
+      "48 0F AE 04 24",                // fxsave64 [rsp]
+      "48 89 E3",                      // mov rbx, rsp
+      "48 81 EC 30 00 00 00",          // sub rsp, 0x30
+      "48 B8 05 00 00 00 00 00 00 00", // mov rax, 5
+      "48 89 84 24 20 00 00 00",       // mov qword [rsp + 0x20], rax
+      "48 B9 01 00 00 00 00 00 00 00", // mov rcx, 1
+      "48 BA 02 00 00 00 00 00 00 00", // mov rdx, 2
+      "49 B8 03 00 00 00 00 00 00 00", // mov r8, 3
+      "49 B9 04 00 00 00 00 00 00 00", // mov r9, 4
+      "48 B8 88 77 66 55 44 33 22 11", // mov rax, 0x1122334455667788
+      "FF D0",                         // call rax
+      "48 B9 00 00 AA AA 00 00 00 00", // mov rcx, 0xaaaa0000
+      "48 89 01",                      // mov qword [rcx], rax
+      "48 B9 08 00 AA AA 00 00 00 00", // mov rcx, 0xaaaa0008
+      "C7 01 01 00 00 00",             // mov dword [rcx], 1
+      "48 89 DC",                      // mov rsp, rbx
+      "48 0F AE 0C 24",                // fxrstor64 [rsp]
+      "48 89 EC",                      // mov rsp, rbp
+      "41 5B",                         // pop r11
+      "41 5A",                         // pop r10
+      "41 59",                         // pop r9
+      "41 58",                         // pop r8
+      "5F",                            // pop rdi
+      "5E",                            // pop rsi
+      "5D",                            // pop rbp
+      "5B",                            // pop rbx
+      "5A",                            // pop rdx
+      "59",                            // pop rcx
+      "58",                            // pop rax
+      "9D",                            // popfq
+      "48 8D A4 24 80 00 00 00",       // lea rsp, [rsp + 0x80]
+      "EB FE",                         // jmp $
+    ]);
+    assert_eq!(stub.assemble().unwrap(), expected);
+  }
+
+  #[test]
+  fn sysv64_golden() {
+    let stub = CallStub {
+      conv: CallConv::SysV64,
+      target: 0x7F0011223344,
+      args: vec![0x10, 0x20],
+      result: 0xBBBB0000,
+      done: 0xBBBB0008,
+      exit: StubExit::Return,
+    };
+    let expected = bytes(&[
+      "48 8D 64 24 80",                // lea rsp, [rsp - 0x80]
+      "9C",                            // pushfq
+      "50",                            // push rax
+      "51",                            // push rcx
+      "52",                            // push rdx
+      "53",                            // push rbx
+      "55",                            // push rbp
+      "56",                            // push rsi
+      "57",                            // push rdi
+      "41 50",                         // push r8
+      "41 51",                         // push r9
+      "41 52",                         // push r10
+      "41 53",                         // push r11
+      "48 89 E5",                      // mov rbp, rsp
+      "48 83 E4 F0",                   // and rsp, -0x10
+      "48 81 EC 00 02 00 00",          // sub rsp, 0x200
+      "48 0F AE 04 24",                // fxsave64 [rsp]
+      "48 89 E3",                      // mov rbx, rsp
+      "48 BF 10 00 00 00 00 00 00 00", // mov rdi, 0x10
+      "48 BE 20 00 00 00 00 00 00 00", // mov rsi, 0x20
+      "48 B8 44 33 22 11 00 7F 00 00", // mov rax, 0x7f0011223344
+      "FF D0",                         // call rax
+      "48 B9 00 00 BB BB 00 00 00 00", // mov rcx, 0xbbbb0000
+      "48 89 01",                      // mov qword [rcx], rax
+      "48 B9 08 00 BB BB 00 00 00 00", // mov rcx, 0xbbbb0008
+      "C7 01 01 00 00 00",             // mov dword [rcx], 1
+      "48 89 DC",                      // mov rsp, rbx
+      "48 0F AE 0C 24",                // fxrstor64 [rsp]
+      "48 89 EC",                      // mov rsp, rbp
+      "41 5B",                         // pop r11
+      "41 5A",                         // pop r10
+      "41 59",                         // pop r9
+      "41 58",                         // pop r8
+      "5F",                            // pop rdi
+      "5E",                            // pop rsi
+      "5D",                            // pop rbp
+      "5B",                            // pop rbx
+      "5A",                            // pop rdx
+      "59",                            // pop rcx
+      "58",                            // pop rax
+      "9D",                            // popfq
+      "48 8D A4 24 80 00 00 00",       // lea rsp, [rsp + 0x80]
+      "C3",                            // ret
+    ]);
+    assert_eq!(stub.assemble().unwrap(), expected);
+  }
+
+  #[test]
+  fn x86_golden() {
+    let stub = CallStub {
+      conv: CallConv::X86,
+      target: 0x77001122,
+      args: vec![0x1000, 0x2000],
+      result: 0x00400F00,
+      done: 0x00400F08,
+      exit: StubExit::Spin,
+    };
+    let expected = bytes(&[
+      "9C",                            // pushfd
+      "60",                            // pushal
+      "89 E5",                         // mov ebp, esp
+      "83 E4 F0",                      // and esp, -0x10
+      "81 EC 00 02 00 00",             // sub esp, 0x200
+      "0F AE 04 24",                   // fxsave [esp]
+      "89 E6",                         // mov esi, esp
+      "83 EC 08",                      // sub esp, 8
+      "68 00 20 00 00",                // push 0x2000
+      "68 00 10 00 00",                // push 0x1000
+      "B8 22 11 00 77",                // mov eax, 0x77001122
+      "FF D0",                         // call eax
+      "A3 00 0F 40 00",                // mov dword [0x400f00], eax
+      "C7 05 08 0F 40 00 01 00 00 00", // mov dword [0x400f08], 1
+      "89 F4",                         // mov esp, esi
+      "0F AE 0C 24",                   // fxrstor [esp]
+      "89 EC",                         // mov esp, ebp
+      "61",                            // popal
+      "9D",                            // popfd
+      "EB FE",                         // jmp $
+    ]);
+    assert_eq!(stub.assemble().unwrap(), expected);
+  }
+
+  #[test]
+  fn arm64_golden() {
+    let stub = CallStub {
+      conv: CallConv::Aapcs64,
+      target: 0xFFFF00112233,
+      args: vec![0x42],
+      result: 0xCCCC0000,
+      done: 0xCCCC0008,
+      exit: StubExit::Spin,
+    };
+    let expected = words(&[
+      0xA9BF7BFD, // stp x29, x30, [sp, #-0x10]!
+      0xA9BF07E0, // stp x0, x1, [sp, #-0x10]!
+      0xA9BF0FE2, // stp x2, x3, [sp, #-0x10]!
+      0xA9BF17E4, // stp x4, x5, [sp, #-0x10]!
+      0xA9BF1FE6, // stp x6, x7, [sp, #-0x10]!
+      0xA9BF27E8, // stp x8, x9, [sp, #-0x10]!
+      0xA9BF2FEA, // stp x10, x11, [sp, #-0x10]!
+      0xA9BF37EC, // stp x12, x13, [sp, #-0x10]!
+      0xA9BF3FEE, // stp x14, x15, [sp, #-0x10]!
+      0xA9BF47F0, // stp x16, x17, [sp, #-0x10]!
+      0xD53B4210, // mrs x16, NZCV
+      0xF81F0FF0, // str x16, [sp, #-0x10]!
+      0xADBF07E0, // stp q0, q1, [sp, #-0x20]!
+      0xADBF0FE2, // stp q2, q3, [sp, #-0x20]!
+      0xADBF17E4, // stp q4, q5, [sp, #-0x20]!
+      0xADBF1FE6, // stp q6, q7, [sp, #-0x20]!
+      0xADBF27E8, // stp q8, q9, [sp, #-0x20]!
+      0xADBF2FEA, // stp q10, q11, [sp, #-0x20]!
+      0xADBF37EC, // stp q12, q13, [sp, #-0x20]!
+      0xADBF3FEE, // stp q14, q15, [sp, #-0x20]!
+      0xADBF47F0, // stp q16, q17, [sp, #-0x20]!
+      0xADBF4FF2, // stp q18, q19, [sp, #-0x20]!
+      0xADBF57F4, // stp q20, q21, [sp, #-0x20]!
+      0xADBF5FF6, // stp q22, q23, [sp, #-0x20]!
+      0xADBF67F8, // stp q24, q25, [sp, #-0x20]!
+      0xADBF6FFA, // stp q26, q27, [sp, #-0x20]!
+      0xADBF77FC, // stp q28, q29, [sp, #-0x20]!
+      0xADBF7FFE, // stp q30, q31, [sp, #-0x20]!
+      0xD2800840, // mov x0, #0x42
+      0xF2A00000, // movk x0, #0, lsl #16
+      0xF2C00000, // movk x0, #0, lsl #32
+      0xF2E00000, // movk x0, #0, lsl #48
+      0xD2844670, // mov x16, #0x2233
+      0xF2A00230, // movk x16, #0x11, lsl #16
+      0xF2DFFFF0, // movk x16, #0xffff, lsl #32
+      0xF2E00010, // movk x16, #0, lsl #48
+      0xD63F0200, // blr x16
+      0xD2800010, // mov x16, #0
+      0xF2B99990, // movk x16, #0xcccc, lsl #16
+      0xF2C00010, // movk x16, #0, lsl #32
+      0xF2E00010, // movk x16, #0, lsl #48
+      0xF9000200, // str x0, [x16]
+      0xD2800110, // mov x16, #8
+      0xF2B99990, // movk x16, #0xcccc, lsl #16
+      0xF2C00010, // movk x16, #0, lsl #32
+      0xF2E00010, // movk x16, #0, lsl #48
+      0x52800031, // mov w17, #1
+      0xB9000211, // str w17, [x16]
+      0xACC17FFE, // ldp q30, q31, [sp], #0x20
+      0xACC177FC, // ldp q28, q29, [sp], #0x20
+      0xACC16FFA, // ldp q26, q27, [sp], #0x20
+      0xACC167F8, // ldp q24, q25, [sp], #0x20
+      0xACC15FF6, // ldp q22, q23, [sp], #0x20
+      0xACC157F4, // ldp q20, q21, [sp], #0x20
+      0xACC14FF2, // ldp q18, q19, [sp], #0x20
+      0xACC147F0, // ldp q16, q17, [sp], #0x20
+      0xACC13FEE, // ldp q14, q15, [sp], #0x20
+      0xACC137EC, // ldp q12, q13, [sp], #0x20
+      0xACC12FEA, // ldp q10, q11, [sp], #0x20
+      0xACC127E8, // ldp q8, q9, [sp], #0x20
+      0xACC11FE6, // ldp q6, q7, [sp], #0x20
+      0xACC117E4, // ldp q4, q5, [sp], #0x20
+      0xACC10FE2, // ldp q2, q3, [sp], #0x20
+      0xACC107E0, // ldp q0, q1, [sp], #0x20
+      0xF84107F0, // ldr x16, [sp], #0x10
+      0xD51B4210, // msr NZCV, x16
+      0xA8C147F0, // ldp x16, x17, [sp], #0x10
+      0xA8C13FEE, // ldp x14, x15, [sp], #0x10
+      0xA8C137EC, // ldp x12, x13, [sp], #0x10
+      0xA8C12FEA, // ldp x10, x11, [sp], #0x10
+      0xA8C127E8, // ldp x8, x9, [sp], #0x10
+      0xA8C11FE6, // ldp x6, x7, [sp], #0x10
+      0xA8C117E4, // ldp x4, x5, [sp], #0x10
+      0xA8C10FE2, // ldp x2, x3, [sp], #0x10
+      0xA8C107E0, // ldp x0, x1, [sp], #0x10
+      0xA8C17BFD, // ldp x29, x30, [sp], #0x10
+      0x14000000, // b .
+    ]);
+    assert_eq!(stub.assemble().unwrap(), expected);
+  }
+
+  #[test]
+  fn arm64_stack_arguments() {
+    let stub = CallStub {
+      conv: CallConv::Aapcs64,
+      target: 0x1000,
+      args: (0..9).collect(),
+      result: 0x2000,
+      done: 0x2008,
+      exit: StubExit::Spin,
+    };
+    let code = stub.assemble().unwrap();
+    let ops: Vec<u32> = code.chunks(4).map(|op| u32::from_le_bytes(op.try_into().unwrap())).collect();
+
+    // The ninth argument goes to [sp] in a 16-byte frame that is dropped again after the call
+    let sub = ops.iter().position(|op| *op == 0xD10043FF).expect("sub sp, sp, #0x10"); // sub sp, sp, #0x10
+    assert_eq!(ops[sub + 1..sub + 5], a64::mov_imm64(16, 8));
+    assert_eq!(ops[sub + 5], 0xF90003F0); // str x16, [sp]
+    let blr = ops.iter().position(|op| *op == 0xD63F0200).expect("blr x16"); // blr x16
+    assert_eq!(ops[blr + 1], 0x910043FF); // add sp, sp, #0x10
+  }
+
+  #[test]
+  fn x86_rejects_64_bit_addresses() {
+    let stub = CallStub {
+      conv: CallConv::X86,
+      target: 0x1_0000_0000,
+      args: vec![],
+      result: 0x1000,
+      done: 0x1008,
+      exit: StubExit::Spin,
+    };
+    assert_eq!(stub.assemble().unwrap_err(), "Target 0x100000000 does not fit in 32 bits");
+  }
+
+  #[test]
+  fn spin_offset_points_at_the_spin() {
+    for conv in [CallConv::Win64, CallConv::SysV64, CallConv::X86, CallConv::Aapcs64] {
+      let stub = CallStub { conv, target: 0x1000, args: vec![1], result: 0x2000, done: 0x2008, exit: StubExit::Spin };
+      let code = stub.assemble().unwrap();
+      let spin: &[u8] = if conv == CallConv::Aapcs64 { &[0x00, 0x00, 0x00, 0x14] } else { &[0xEB, 0xFE] };
+      assert_eq!(&code[stub.spin_offset(&code)..], spin, "{}", conv);
+    }
+  }
+
+  #[test]
+  fn conventions_per_platform() {
+    assert_eq!(CallConv::windows(Arch::AMDx64), Ok(CallConv::Win64));
+    assert_eq!(CallConv::windows(Arch::AMDx86), Ok(CallConv::X86));
+    assert_eq!(CallConv::linux(Arch::AMDx64), Ok(CallConv::SysV64));
+    assert_eq!(CallConv::linux(Arch::Arm64), Ok(CallConv::Aapcs64));
+    assert!(CallConv::linux(Arch::Unknown).is_err());
+  }
+
+  /// Trashes the SSE registers the stub has to restore, and checks the two stack arguments landed in order.
+  #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+  extern "C" fn weighted_sum(a: u64, b: u64, c: u64, d: u64, e: u64, f: u64, g: u64, h: u64) -> u64 {
+    unsafe { std::arch::asm!("xorps xmm0, xmm0", "xorps xmm7, xmm7", "xorps xmm15, xmm15", out("xmm0") _, out("xmm7") _, out("xmm15") _) };
+    [a, b, c, d, e, f, g, h].iter().enumerate().map(|(i, v)| (i as u64 + 1) * v).sum()
+  }
+
+  #[test]
+  #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+  fn executes_on_x86_64() {
+    const SIZE: usize = 0x1000;
+    let page = unsafe { libc::mmap(std::ptr::null_mut(), SIZE, libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0) };
+    assert_ne!(page, libc::MAP_FAILED, "mmap failed, error: {}", std::io::Error::last_os_error());
+    let base = page as u64;
+
+    let stub = CallStub {
+      conv: CallConv::SysV64,
+      target: weighted_sum as *const () as u64,
+      args: (1..=8).map(|v| v * 100).collect(),
+      result: base + 0xF00,
+      done: base + 0xF08,
+      exit: StubExit::Return,
+    };
+    let code = stub.assemble().unwrap();
+    unsafe { std::ptr::copy_nonoverlapping(code.as_ptr(), page as *mut u8, code.len()) };
+
+    // Every register is live across the call, the stub has to hand all of them back untouched
+    let (mut rax, mut rcx, mut rdx, mut rsi, mut rdi, mut r8, mut r9, mut r10, mut r11) = (1u64, 2u64, 3u64, 4u64, 5u64, 6u64, 7u64, 8u64, 9u64);
+    let (mut xmm0, mut xmm7, mut xmm15) = (1.5f64, -2.25f64, 1e300f64);
+    unsafe {
+      std::arch::asm!(
+        "call {stub}",
+        stub = in(reg) base,
+        inout("rax") rax, inout("rcx") rcx, inout("rdx") rdx, inout("rsi") rsi, inout("rdi") rdi,
+        inout("r8") r8, inout("r9") r9, inout("r10") r10, inout("r11") r11,
+        inout("xmm0") xmm0, inout("xmm7") xmm7, inout("xmm15") xmm15,
+      );
+    }
+    let (result, done) = unsafe { (*((base + 0xF00) as *const u64), *((base + 0xF08) as *const u32)) };
+    unsafe { libc::munmap(page, SIZE) };
+
+    assert_eq!(result, (1..=8).map(|i| i * i * 100).sum::<u64>());
+    assert_eq!(done, 1);
+    assert_eq!((rax, rcx, rdx, rsi, rdi, r8, r9, r10, r11), (1, 2, 3, 4, 5, 6, 7, 8, 9));
+    assert_eq!((xmm0, xmm7, xmm15), (1.5, -2.25, 1e300));
+  }
+}
@@ -0,0 +1,384 @@
+#[cfg(target_os = "windows")]
+use crate::logic::kenjector::{Kenjector, RemoteModule};
+#[cfg(target_os = "linux")]
+use crate::logic::{privilege::{PtraceAccess, PtraceTarget}, ptrace::Tracee};
+use derive_more::Display;
+#[cfg(target_os = "linux")]
+use std::{cell::RefCell, collections::HashMap};
+#[cfg(target_os = "windows")]
+use winapi::um::{handleapi::{CloseHandle, INVALID_HANDLE_VALUE}, processthreadsapi::{OpenProcess, OpenThread, ResumeThread, SuspendThread}, tlhelp32::{CreateToolhelp32Snapshot, TH32CS_SNAPTHREAD, THREADENTRY32, Thread32First, Thread32Next}, winbase::LocalFree, winnt::{HANDLE, PROCESS_SUSPEND_RESUME, THREAD_QUERY_INFORMATION, THREAD_QUERY_LIMITED_INFORMATION, THREAD_SUSPEND_RESUME}};
+
+/// `THREADINFOCLASS` values, winapi doesn't have them.
+#[cfg(target_os = "windows")]
+const THREAD_QUERY_SET_WIN32_START_ADDRESS: u32 = 9;
+#[cfg(target_os = "windows")]
+const THREAD_SUSPEND_COUNT: u32 = 35;
+
+#[cfg(target_os = "windows")]
+#[link(name = "ntdll")]
+unsafe extern "system" {
+  fn NtQueryInformationThread(thread: HANDLE, class: u32, information: *mut std::ffi::c_void, length: u32, return_length: *mut u32) -> i32;
+  fn NtSuspendProcess(process: HANDLE) -> i32;
+  fn NtResumeProcess(process: HANDLE) -> i32;
+}
+
+#[cfg(target_os = "windows")]
+#[link(name = "kernel32")]
+unsafe extern "system" {
+  /// Windows 10 1607 and later.
+  fn GetThreadDescription(thread: HANDLE, description: *mut *mut u16) -> i32;
+}
+
+#[cfg(target_os = "linux")]
+thread_local! {
+  /// Threads suspended with [`suspend_thread`], held in a ptrace stop until resumed. ptrace ties a tracee
+  /// to the thread that attached, so these only ever live on the UI thread. They run again when
+  /// Kenjector exits, as the kernel detaches them.
+  static SUSPENDED: RefCell<HashMap<u32, Tracee>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum ThreadState {
+  #[cfg(target_os = "linux")]
+  Running,
+  #[cfg(target_os = "linux")]
+  Sleeping,
+  /// Uninterruptible sleep, mostly waiting on disk I/O.
+  #[cfg(target_os = "linux")]
+  Waiting,
+  /// Stopped along with the rest of the process, e.g. by `SIGSTOP`.
+  #[cfg(target_os = "linux")]
+  Stopped,
+  /// Stopped by a debugger.
+  #[cfg(target_os = "linux")]
+  Traced,
+  /// With its suspend count.
+  #[display("Suspended ({_0})")]
+  Suspended(u32),
+  /// Not suspended. Windows doesn't tell whether such a thread is running or waiting short of a
+  /// system-wide query.
+  #[cfg(target_os = "windows")]
+  Active,
+  #[cfg(target_os = "linux")]
+  Exited,
+  Unknown,
+}
+
+impl ThreadState {
+  /// From the state letter of a `/proc/<pid>/task/<tid>/stat` line.
+  #[cfg(target_os = "linux")]
+  pub fn from_stat(state: char) -> Self {
+    match state {
+      'R' => Self::Running,
+      'S' | 'I' => Self::Sleeping,
+      'D' => Self::Waiting,
+      'T' => Self::Stopped,
+      't' => Self::Traced,
+      'Z' | 'X' => Self::Exited,
+      _ => Self::Unknown,
+    }
+  }
+}
+
+/// One thread of another process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadInfo {
+  pub thread_id: u32,
+  /// The thread's description on Windows, its `comm` on Linux. Often empty on Windows.
+  pub name: String,
+  /// Where the thread started running. Linux doesn't keep it.
+  pub start_address: Option<u64>,
+  /// The start address as `module+offset`, empty when it isn't in a module.
+  pub start_location: String,
+  pub state: ThreadState,
+  /// The base priority, 0 to 31, on Windows, the nice value, -20 to 19, on Linux.
+  pub priority: i32,
+}
+
+/// `module+offset` for an address inside one of `modules`.
+#[cfg(target_os = "windows")]
+fn locate(modules: &[RemoteModule], address: u64) -> String { modules.iter().find(|m| (m.base..m.base + m.size as u64).contains(&address)).map(|m| format!("{}+{:#X}", m.name, address - m.base)).unwrap_or_default() }
+
+/// Every thread of the process, from a Toolhelp32 snapshot.
+#[cfg(target_os = "windows")]
+pub fn query(process_id: u32) -> Result<Vec<ThreadInfo>, String> {
+  let modules = Kenjector::get_modules(process_id).unwrap_or_default();
+  let mut threads = Vec::new();
+  unsafe {
+    let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+    if snapshot == INVALID_HANDLE_VALUE {
+      return Err(format!("CreateToolhelp32Snapshot failed, error: {:#X?}", std::io::Error::last_os_error()));
+    }
+
+    let mut entry: THREADENTRY32 = std::mem::zeroed();
+    entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+    if Thread32First(snapshot, &mut entry) != 0 {
+      loop {
+        if entry.th32OwnerProcessID == process_id {
+          threads.push(thread_info(entry.th32ThreadID, entry.tpBasePri, &modules));
+        }
+        if Thread32Next(snapshot, &mut entry) == 0 {
+          break;
+        }
+      }
+    }
+
+    CloseHandle(snapshot);
+  }
+  Ok(threads)
+}
+
+/// What the snapshot doesn't say about a thread, whatever of it we are allowed to query.
+#[cfg(target_os = "windows")]
+unsafe fn thread_info(thread_id: u32, priority: i32, modules: &[RemoteModule]) -> ThreadInfo {
+  unsafe {
+    let mut info = ThreadInfo {
+      thread_id,
+      name: String::new(),
+      start_address: None,
+      start_location: String::new(),
+      state: ThreadState::Unknown,
+      priority,
+    };
+    let thread = OpenThread(THREAD_QUERY_INFORMATION | THREAD_QUERY_LIMITED_INFORMATION, 0, thread_id);
+    if thread.is_null() {
+      return info;
+    }
+
+    let mut start = 0usize;
+    if NtQueryInformationThread(thread, THREAD_QUERY_SET_WIN32_START_ADDRESS, &mut start as *mut _ as _, std::mem::size_of::<usize>() as u32, std::ptr::null_mut()) >= 0 {
+      info.start_address = Some(start as u64);
+      info.start_location = locate(modules, start as u64);
+    }
+
+    let mut suspend_count = 0u32;
+    if NtQueryInformationThread(thread, THREAD_SUSPEND_COUNT, &mut suspend_count as *mut _ as _, std::mem::size_of::<u32>() as u32, std::ptr::null_mut()) >= 0 {
+      info.state = if suspend_count > 0 { ThreadState::Suspended(suspend_count) } else { ThreadState::Active };
+    }
+
+    let mut description = std::ptr::null_mut();
+    if GetThreadDescription(thread, &mut description) >= 0 && !description.is_null() {
+      let len = (0..).take_while(|&i| *description.add(i) != 0).count();
+      info.name = String::from_utf16_lossy(std::slice::from_raw_parts(description, len));
+      LocalFree(description as _);
+    }
+
+    CloseHandle(thread);
+    info
+  }
+}
+
+/// Every thread of the process, from `/proc/<pid>/task`. Threads that exit while being read are left out.
+#[cfg(target_os = "linux")]
+pub fn query(process_id: u32) -> Result<Vec<ThreadInfo>, String> {
+  let tasks = std::fs::read_dir(format!("/proc/{}/task", process_id)).map_err(|e| format!("Failed to list the threads of process {}, error: {}", process_id, e))?;
+  let mut threads = tasks
+    .filter_map(|task| {
+      let thread_id = task.ok()?.file_name().to_str()?.parse::<u32>().ok()?;
+      let (state, priority) = parse_stat(&std::fs::read_to_string(format!("/proc/{}/task/{}/stat", process_id, thread_id)).ok()?)?;
+      let name = std::fs::read_to_string(format!("/proc/{}/task/{}/comm", process_id, thread_id)).unwrap_or_default().trim_end().to_string();
+      let state = if SUSPENDED.with_borrow(|suspended| suspended.contains_key(&thread_id)) { ThreadState::Suspended(1) } else { ThreadState::from_stat(state) };
+      Some(ThreadInfo { thread_id, name, start_address: None, start_location: String::new(), state, priority })
+    })
+    .collect::<Vec<_>>();
+  threads.sort_by_key(|t| t.thread_id);
+  Ok(threads)
+}
+
+/// The state letter and nice value of a `/proc/<pid>/task/<tid>/stat` line. The name in parentheses
+/// may hold spaces and parentheses itself, so fields are counted from the last `)`.
+#[cfg(target_os = "linux")]
+pub fn parse_stat(stat: &str) -> Option<(char, i32)> {
+  let fields = stat[stat.rfind(')')? + 1..].split_whitespace().collect::<Vec<_>>();
+  // The state is field 3 and the nice value field 19
+  Some((fields.first()?.chars().next()?, fields.get(16)?.parse().ok()?))
+}
+
+/// Suspend one thread of the process, Windows counts suspensions and needs as many resumes.
+#[cfg(target_os = "windows")]
+pub fn suspend_thread(_process_id: u32, thread_id: u32) -> Result<(), String> { with_thread(thread_id, |thread| if unsafe { SuspendThread(thread) } == u32::MAX { Err(format!("SuspendThread failed, error: {:#X?}", std::io::Error::last_os_error())) } else { Ok(()) }) }
+
+#[cfg(target_os = "windows")]
+pub fn resume_thread(_process_id: u32, thread_id: u32) -> Result<(), String> { with_thread(thread_id, |thread| if unsafe { ResumeThread(thread) } == u32::MAX { Err(format!("ResumeThread failed, error: {:#X?}", std::io::Error::last_os_error())) } else { Ok(()) }) }
+
+#[cfg(target_os = "windows")]
+fn with_thread(thread_id: u32, f: impl FnOnce(HANDLE) -> Result<(), String>) -> Result<(), String> {
+  let thread = unsafe { OpenThread(THREAD_SUSPEND_RESUME, 0, thread_id) };
+  if thread.is_null() {
+    return Err(format!("OpenThread failed, error: {:#X?}", std::io::Error::last_os_error()));
+  }
+  let result = f(thread);
+  unsafe { CloseHandle(thread) };
+  result
+}
+
+/// Suspend one thread of the process by holding it in a ptrace stop, the rest of the process keeps
+/// running. Has to be resumed from the same thread.
+#[cfg(target_os = "linux")]
+pub fn suspend_thread(process_id: u32, thread_id: u32) -> Result<(), String> {
+  if SUSPENDED.with_borrow(|suspended| suspended.contains_key(&thread_id)) {
+    return Err(format!("Thread {} is already suspended", thread_id));
+  }
+  if !std::path::Path::new(&format!("/proc/{}/task/{}", process_id, thread_id)).exists() {
+    return Err(format!("Thread {} is not a thread of process {}", thread_id, process_id));
+  }
+  PtraceAccess::current().preflight(&PtraceTarget::read(process_id)?)?;
+
+  let tracee = Tracee::seize(thread_id as libc::pid_t)?;
+  tracee.interrupt()?;
+  SUSPENDED.with_borrow_mut(|suspended| suspended.insert(thread_id, tracee));
+  Ok(())
+}
+
+/// Resume a thread suspended with [`suspend_thread`] by detaching from it.
+#[cfg(target_os = "linux")]
+pub fn resume_thread(_process_id: u32, thread_id: u32) -> Result<(), String> {
+  match SUSPENDED.with_borrow_mut(|suspended| suspended.remove(&thread_id)) {
+    Some(_tracee) => Ok(()),
+    None => Err(format!("Thread {} wasn't suspended by Kenjector", thread_id)),
+  }
+}
+
+/// Suspend every thread of the process at once.
+#[cfg(target_os = "windows")]
+pub fn suspend_process(process_id: u32) -> Result<(), String> { with_process(process_id, "NtSuspendProcess", |process| unsafe { NtSuspendProcess(process) }) }
+
+#[cfg(target_os = "windows")]
+pub fn resume_process(process_id: u32) -> Result<(), String> { with_process(process_id, "NtResumeProcess", |process| unsafe { NtResumeProcess(process) }) }
+
+#[cfg(target_os = "windows")]
+fn with_process(process_id: u32, call: &str, f: impl FnOnce(HANDLE) -> i32) -> Result<(), String> {
+  let process = unsafe { OpenProcess(PROCESS_SUSPEND_RESUME, 0, process_id) };
+  if process.is_null() {
+    return Err(format!("OpenProcess failed, error: {:#X?}", std::io::Error::last_os_error()));
+  }
+  let status = f(process);
+  unsafe { CloseHandle(process) };
+  if status < 0 {
+    return Err(format!("{} failed, status: {:#X}", call, status));
+  }
+  Ok(())
+}
+
+/// Stop the whole process with `SIGSTOP`.
+#[cfg(target_os = "linux")]
+pub fn suspend_process(process_id: u32) -> Result<(), String> { signal(process_id, libc::SIGSTOP) }
+
+/// Continue the process with `SIGCONT`. Threads held with [`suspend_thread`] stay suspended.
+#[cfg(target_os = "linux")]
+pub fn resume_process(process_id: u32) -> Result<(), String> { signal(process_id, libc::SIGCONT) }
+
+#[cfg(target_os = "linux")]
+fn signal(process_id: u32, signal: libc::c_int) -> Result<(), String> {
+  if unsafe { libc::kill(process_id as libc::pid_t, signal) } == -1 {
+    return Err(format!("kill failed, error: {}", std::io::Error::last_os_error()));
+  }
+  Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+  use std::{process::{Child, Command}, time::{Duration, Instant}};
+
+  /// Set for the copy of the test binary that [`spawn_workers`] starts.
+  const WORKERS_CHILD: &str = "KENJECTOR_THREADS_CHILD";
+
+  /// Not a test of its own, the body of the child [`spawn_workers`] starts: three named threads that sleep.
+  #[test]
+  #[ignore]
+  fn sleeping_workers() {
+    if std::env::var_os(WORKERS_CHILD).is_none() {
+      return;
+    }
+    for i in 0..3 {
+      std::thread::Builder::new().name(format!("kenjector-w{}", i)).spawn(|| std::thread::sleep(Duration::from_secs(30))).unwrap();
+    }
+    std::thread::sleep(Duration::from_secs(30));
+  }
+
+  /// Start the test binary again running only [`sleeping_workers`].
+  fn spawn_workers() -> Child { Command::new(std::env::current_exe().unwrap()).args(["--ignored", "--exact", "logic::threads::tests::sleeping_workers"]).env(WORKERS_CHILD, "1").stdout(std::process::Stdio::null()).spawn().unwrap() }
+
+  /// The threads of `child` once its workers show up and have gone to sleep.
+  fn wait_for_workers(child: &mut Child) -> Vec<ThreadInfo> {
+    let started = Instant::now();
+    loop {
+      let threads = query(child.id()).unwrap_or_default();
+      let workers = threads.iter().filter(|t| t.name.starts_with("kenjector-w")).count();
+      if workers == 3 && threads.iter().all(|t| t.state == ThreadState::Sleeping || t.state == ThreadState::Running) {
+        return threads;
+      }
+      if started.elapsed() > Duration::from_secs(10) {
+        let _ = child.kill();
+        let _ = child.wait();
+        panic!("the workers did not start: {:?}", threads);
+      }
+      std::thread::sleep(Duration::from_millis(20));
+    }
+  }
+
+  fn stat_state(process_id: u32, thread_id: u32) -> char { parse_stat(&std::fs::read_to_string(format!("/proc/{}/task/{}/stat", process_id, thread_id)).unwrap()).unwrap().0 }
+
+  #[test]
+  fn lists_every_thread() {
+    let mut child = spawn_workers();
+    let threads = wait_for_workers(&mut child);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    // The main thread has the process id, the workers come after it
+    assert_eq!(threads[0].thread_id, child.id());
+    let mut names = threads.iter().map(|t| t.name.as_str()).filter(|name| name.starts_with("kenjector-w")).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["kenjector-w0", "kenjector-w1", "kenjector-w2"]);
+    assert!(threads.windows(2).all(|w| w[0].thread_id < w[1].thread_id));
+    assert!(threads.iter().all(|t| t.start_address.is_none() && (-20..=19).contains(&t.priority)));
+  }
+
+  #[test]
+  fn suspends_and_resumes_one_thread() {
+    let mut child = spawn_workers();
+    let threads = wait_for_workers(&mut child);
+    let pid = child.id();
+    let worker = threads.iter().find(|t| t.name == "kenjector-w1").unwrap().thread_id;
+
+    let suspended = suspend_thread(pid, worker);
+    let again = suspend_thread(pid, worker);
+    let while_suspended = (query(pid).unwrap(), stat_state(pid, worker));
+    let resumed = resume_thread(pid, worker);
+    let resumed_again = resume_thread(pid, worker);
+    // Detached, the thread runs for a moment to restart its sleep
+    let started = Instant::now();
+    while stat_state(pid, worker) != 'S' && started.elapsed() < Duration::from_secs(5) {
+      std::thread::sleep(Duration::from_millis(10));
+    }
+    let after = (query(pid).unwrap(), stat_state(pid, worker));
+    let foreign = suspend_thread(pid, std::process::id());
+    let _ = child.kill();
+    let _ = child.wait();
+
+    suspended.unwrap();
+    assert!(again.unwrap_err().contains("already suspended"));
+    let state = |threads: &[ThreadInfo], thread_id| threads.iter().find(|t| t.thread_id == thread_id).unwrap().state;
+    assert_eq!(state(&while_suspended.0, worker), ThreadState::Suspended(1));
+    assert_eq!(while_suspended.1, 't');
+    // Only the one thread
+    assert!(while_suspended.0.iter().filter(|t| t.thread_id != worker).all(|t| !matches!(t.state, ThreadState::Suspended(_) | ThreadState::Traced)));
+
+    resumed.unwrap();
+    assert!(resumed_again.unwrap_err().contains("wasn't suspended"));
+    assert_eq!(state(&after.0, worker), ThreadState::Sleeping);
+    assert_eq!(after.1, 'S');
+    assert!(foreign.unwrap_err().contains("is not a thread of process"));
+  }
+
+  #[test]
+  fn stat_lines() {
+    assert_eq!(parse_stat("1234 (sleep) S 1 1234 1234 0 -1 4194304 100 0 0 0 0 0 0 0 20 0 1 0 5 2000 100 0"), Some(('S', 0)));
+    // Names may hold spaces and parentheses
+    assert_eq!(parse_stat("1235 (a) b (c) t 1 1234 1234 0 -1 4194304 100 0 0 0 0 0 0 0 39 19 1 0 5 2000 100 0"), Some(('t', 19)));
+    assert_eq!(parse_stat("1236 (x) R 1 2 3"), None);
+    assert_eq!(parse_stat("no parentheses"), None);
+  }
+}
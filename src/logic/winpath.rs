@@ -0,0 +1,247 @@
+use std::path::{Path, PathBuf};
+
+/// Paths this long need the `\\?\` prefix to get past `MAX_PATH`.
+#[cfg(any(target_os = "windows", test))]
+const MAX_PATH: usize = 260;
+
+/// Encode `text` as a nul-terminated UTF-16 string, as the `W` Windows APIs expect.
+#[cfg(any(target_os = "windows", test))]
+pub fn encode_wide(text: &str) -> Vec<u16> { text.encode_utf16().chain(std::iter::once(0)).collect() }
+
+/// Encode `path` for the `W` APIs. On Windows the raw `OsStr` is used so unpaired surrogates survive.
+#[cfg(any(target_os = "windows", test))]
+pub fn path_to_wide(path: &Path) -> Vec<u16> {
+  #[cfg(target_os = "windows")]
+  {
+    use std::os::windows::ffi::OsStrExt;
+    path.as_os_str().encode_wide().chain(std::iter::once(0)).collect()
+  }
+  #[cfg(not(target_os = "windows"))]
+  {
+    encode_wide(&path.to_string_lossy())
+  }
+}
+
+/// Decode a UTF-16 buffer up to its first nul, if any.
+#[cfg(any(target_os = "windows", test))]
+pub fn decode_wide(wide: &[u16]) -> String {
+  let len = wide.iter().position(|c| *c == 0).unwrap_or(wide.len());
+  String::from_utf16_lossy(&wide[..len])
+}
+
+/// Normalise `path` for injection: absolute, `\` separated, `.` and `..` resolved, and
+/// `\\?\` prefixed once it is too long for `MAX_PATH`. Relative paths resolve against the current directory.
+/// Paths that aren't valid Unicode are refused, normalising them as strings would load a different file.
+#[cfg(target_os = "windows")]
+pub fn normalize_for_injection(path: &Path) -> Result<PathBuf, String> {
+  let cwd = std::env::current_dir().map_err(|e| format!("Failed to get the current directory, error: {}", e))?;
+  normalize(unicode_path(path)?, unicode_path(&cwd)?).map(PathBuf::from)
+}
+
+/// `path` as a string, or an error naming it if it isn't valid Unicode, e.g. holds unpaired surrogates.
+#[cfg(any(target_os = "windows", test))]
+fn unicode_path(path: &Path) -> Result<&str, String> { path.to_str().ok_or_else(|| format!("The path {} is not valid Unicode", path.display())) }
+
+/// On Linux the path the loader records is the canonical one, symlinks resolved.
+#[cfg(target_os = "linux")]
+pub fn normalize_for_injection(path: &Path) -> Result<PathBuf, String> { path.canonicalize().map_err(|e| format!("Failed to resolve {}, error: {}", path.display(), e)) }
+
+/// Windows path normalisation on plain strings, so it behaves the same on every host.
+///
+/// `cwd` must be an absolute drive (`C:\dir`) or UNC (`\\server\share\dir`) path and is used
+/// for relative, drive-relative (`C:file`) and rooted (`\file`) inputs.
+#[cfg(any(target_os = "windows", test))]
+pub fn normalize(path: &str, cwd: &str) -> Result<String, String> {
+  // Only for the check, leading and trailing spaces are legal in file names
+  if path.trim().is_empty() {
+    return Err(String::from("The path is empty"));
+  }
+  let path = path.replace('/', "\\");
+
+  // Verbatim and device paths are passed through untouched, Windows does not parse them either
+  if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+    return Ok(path);
+  }
+
+  let cwd = cwd.replace('/', "\\");
+  let (cwd_root, cwd_rest) = split_root(&cwd).ok_or_else(|| format!("The current directory {} is not absolute", cwd))?;
+
+  let (root, rest) = match split_root(&path) {
+    Some(v) => v,
+    None => {
+      let bytes = path.as_bytes();
+      if bytes.len() >= 2 && bytes[1] == b':' && bytes[0].is_ascii_alphabetic() {
+        // `C:file` is relative to the current directory only if it is on the same drive
+        let drive = path[..2].to_ascii_uppercase();
+        let rest = &path[2..];
+        if cwd_root.eq_ignore_ascii_case(&format!("{}\\", drive)) { (cwd_root.clone(), format!("{}\\{}", cwd_rest, rest)) } else { (format!("{}\\", drive), rest.to_string()) }
+      } else if path.starts_with('\\') {
+        (cwd_root.clone(), path.trim_start_matches('\\').to_string())
+      } else {
+        (cwd_root.clone(), format!("{}\\{}", cwd_rest, path))
+      }
+    }
+  };
+
+  let mut parts: Vec<&str> = Vec::new();
+  for part in rest.split('\\') {
+    match part {
+      "" | "." => {}
+      ".." => {
+        parts.pop();
+      }
+      _ => parts.push(part),
+    }
+  }
+
+  let normalized = format!("{}{}", root, parts.join("\\"));
+
+  // The limit is in UTF-16 units, not bytes
+  if normalized.encode_utf16().count() < MAX_PATH {
+    Ok(normalized)
+  } else if let Some(unc) = normalized.strip_prefix(r"\\") {
+    Ok(format!(r"\\?\UNC\{}", unc))
+  } else {
+    Ok(format!(r"\\?\{}", normalized))
+  }
+}
+
+/// Split an absolute path into its root (`C:\` or `\\server\share\`) and the rest.
+#[cfg(any(target_os = "windows", test))]
+fn split_root(path: &str) -> Option<(String, String)> {
+  let bytes = path.as_bytes();
+
+  if bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\' {
+    return Some((path[..3].to_ascii_uppercase(), path[3..].to_string()));
+  }
+
+  if let Some(unc) = path.strip_prefix(r"\\") {
+    let mut parts = unc.splitn(3, '\\');
+    let server = parts.next().filter(|s| !s.is_empty())?;
+    let share = parts.next().filter(|s| !s.is_empty())?;
+    return Some((format!(r"\\{}\{}\", server, share), parts.next().unwrap_or_default().to_string()));
+  }
+
+  None
+}
+
+/// Compare two Windows paths the way the loader would: case-insensitively and ignoring the
+/// `\\?\` prefix, so a module path from a snapshot matches the path we injected.
+#[cfg(any(target_os = "windows", test))]
+pub fn same_windows_path(a: &str, b: &str) -> bool {
+  fn strip(path: &str) -> String {
+    let path = path.replace('/', "\\");
+    let path = match path.strip_prefix(r"\\?\UNC\") {
+      Some(unc) => format!(r"\\{}", unc),
+      None => path.strip_prefix(r"\\?\").map(String::from).unwrap_or(path),
+    };
+    path.to_lowercase()
+  }
+
+  strip(a) == strip(b)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CWD: &str = r"C:\Users\me\games";
+
+  #[test]
+  fn separators_and_dots() {
+    assert_eq!(normalize("C:/tools/./x64/../payload.dll", CWD).unwrap(), r"C:\tools\payload.dll");
+    assert_eq!(normalize(r"c:\tools\\\payload.dll", CWD).unwrap(), r"C:\tools\payload.dll");
+    // `..` stops at the root
+    assert_eq!(normalize(r"C:\..\..\payload.dll", CWD).unwrap(), r"C:\payload.dll");
+  }
+
+  #[test]
+  fn relative_paths() {
+    assert_eq!(normalize("payload.dll", CWD).unwrap(), r"C:\Users\me\games\payload.dll");
+    assert_eq!(normalize(r"..\payload.dll", CWD).unwrap(), r"C:\Users\me\payload.dll");
+    assert_eq!(normalize(r"\payload.dll", CWD).unwrap(), r"C:\payload.dll");
+    // Drive-relative, on the current drive and on another one
+    assert_eq!(normalize("c:payload.dll", CWD).unwrap(), r"C:\Users\me\games\payload.dll");
+    assert_eq!(normalize("D:payload.dll", CWD).unwrap(), r"D:\payload.dll");
+  }
+
+  #[test]
+  fn unc_paths() {
+    assert_eq!(normalize(r"\\server\share\dir\..\payload.dll", CWD).unwrap(), r"\\server\share\payload.dll");
+    assert_eq!(normalize("//server/share/payload.dll", CWD).unwrap(), r"\\server\share\payload.dll");
+    // `..` can't climb out of the share
+    assert_eq!(normalize(r"\\server\share\..\payload.dll", CWD).unwrap(), r"\\server\share\payload.dll");
+    assert_eq!(normalize("payload.dll", r"\\server\share\dir").unwrap(), r"\\server\share\dir\payload.dll");
+  }
+
+  #[test]
+  fn verbatim_and_device_paths_pass_through() {
+    assert_eq!(normalize(r"\\?\C:\a\..\payload.dll", CWD).unwrap(), r"\\?\C:\a\..\payload.dll");
+    assert_eq!(normalize(r"\\?\UNC\server\share\payload.dll", CWD).unwrap(), r"\\?\UNC\server\share\payload.dll");
+    assert_eq!(normalize(r"\\.\pipe\kenjector", CWD).unwrap(), r"\\.\pipe\kenjector");
+  }
+
+  #[test]
+  fn long_paths_get_the_verbatim_prefix() {
+    let dir = "d".repeat(250);
+    assert_eq!(normalize(&format!(r"C:\{}\payload.dll", dir), CWD).unwrap(), format!(r"\\?\C:\{}\payload.dll", dir));
+    assert_eq!(normalize(&format!(r"\\server\share\{}\payload.dll", dir), CWD).unwrap(), format!(r"\\?\UNC\server\share\{}\payload.dll", dir));
+    assert_eq!(normalize(r"C:\short\payload.dll", CWD).unwrap(), r"C:\short\payload.dll");
+  }
+
+  #[test]
+  fn non_ascii_paths() {
+    assert_eq!(normalize(r"C:\Spiele\Übung\名前.dll", CWD).unwrap(), r"C:\Spiele\Übung\名前.dll");
+    // 200 CJK chars are 600 bytes but 200 UTF-16 units, still under MAX_PATH
+    let name = "名".repeat(200);
+    assert_eq!(normalize(&format!(r"C:\{}.dll", name), CWD).unwrap(), format!(r"C:\{}.dll", name));
+    // Each emoji takes two UTF-16 units, so 130 of them are over it
+    let name = "🎮".repeat(130);
+    assert!(normalize(&format!(r"C:\{}.dll", name), CWD).unwrap().starts_with(r"\\?\C:\"));
+  }
+
+  #[test]
+  fn invalid_inputs() {
+    assert_eq!(normalize("   ", CWD).unwrap_err(), "The path is empty");
+    assert!(normalize("payload.dll", "relative").is_err());
+  }
+
+  #[test]
+  fn spaces_in_names_are_kept() {
+    assert_eq!(normalize(r"C:\ tools\ payload.dll", CWD).unwrap(), r"C:\ tools\ payload.dll");
+    assert_eq!(normalize(" payload.dll", CWD).unwrap(), r"C:\Users\me\games\ payload.dll");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn non_unicode_paths_are_refused() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    assert_eq!(unicode_path(Path::new(r"C:\Übung\名前.dll")).unwrap(), r"C:\Übung\名前.dll");
+    let error = unicode_path(Path::new(OsStr::from_bytes(b"C:\\pay\xFFload.dll"))).unwrap_err();
+    assert_eq!(error, "The path C:\\pay\u{FFFD}load.dll is not valid Unicode");
+  }
+
+  #[test]
+  fn wide_strings_are_nul_terminated() {
+    assert_eq!(encode_wide("ab"), [0x61, 0x62, 0]);
+    assert_eq!(encode_wide(""), [0]);
+    assert_eq!(encode_wide("名🎮"), [0x540D, 0xD83C, 0xDFAE, 0]);
+    assert_eq!(path_to_wide(Path::new("C:/名")), [0x43, 0x3A, 0x2F, 0x540D, 0]);
+  }
+
+  #[test]
+  fn decode_stops_at_the_first_nul() {
+    assert_eq!(decode_wide(&[0x61, 0x62, 0, 0x63]), "ab");
+    assert_eq!(decode_wide(&[0x540D, 0x61]), "名a");
+    assert_eq!(decode_wide(&encode_wide(r"C:\Übung\名前.dll")), r"C:\Übung\名前.dll");
+  }
+
+  #[test]
+  fn same_path_ignores_case_and_prefix() {
+    assert!(same_windows_path(r"C:\Games\Payload.dll", r"c:\games\payload.DLL"));
+    assert!(same_windows_path(r"\\?\C:\Games\payload.dll", "C:/Games/payload.dll"));
+    assert!(same_windows_path(r"\\?\UNC\server\share\payload.dll", r"\\server\share\payload.dll"));
+    assert!(same_windows_path(r"C:\Spiele\ÜBUNG.dll", r"C:\Spiele\übung.dll"));
+    assert!(!same_windows_path(r"C:\Games\payload.dll", r"D:\Games\payload.dll"));
+  }
+}
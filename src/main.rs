@@ -1,9 +1,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-use crate::{logic::kenjector::{Access, GtkHelper, KenjectionInfo, Kenjector, ProcessInfo}, ui::{listview::{GenericListView, ListRow}, messagebox::message_box}};
+use crate::{cli::GuiArgs, logic::{channel::ChannelServer, config::Config, history::{HistoryLog, InjectionRecord}, kenjector::{AccessStatus, Deadline, GtkHelper, KenjectionInfo, Kenjector, ProcessInfo}, memory::ProcessMemory, method::{self, InjectionMethod, MethodInfo}, privilege, winpath}, ui::{consoleview::console_view, debugview::debug_view, dragdrop::dll_drop_target, export, hexview::hex_view, listview::{GenericListView, ListRow}, messagebox::{confirm_box, message_box}, pointerview::pointer_view, regionview::region_view, scanview::scan_view, threadview::thread_view, toast::toast}};
 use gtk4::prelude::*;
 use parking_lot::RwLock;
-use std::{path::PathBuf, sync::Arc};
-use winapi::um::processthreadsapi::GetCurrentProcess;
+use std::{path::PathBuf, sync::{Arc, mpsc::TryRecvError}, time::{Duration, Instant}};
+mod cli;
 mod logic;
 mod ui;
 
@@ -12,6 +12,13 @@ const APP_NAME: &str = "Kenjector";
 #[derive(Clone, Default)]
 pub struct AppState {
   pub consts: AppConsts,
+  pub history: Vec<InjectionRecord>,
+  pub history_log: HistoryLog,
+  pub config: Config,
+  /// Position in `config.recent_dlls` while cycling with Alt+Up/Down.
+  pub recent_index: usize,
+  /// The Kenjection running in the background, for the cancel button.
+  pub running: Option<Deadline>,
 }
 
 #[derive(Clone)]
@@ -43,20 +50,266 @@ impl<T: IsA<gtk4::Widget>> MarginAll for T {
 }
 
 impl ListRow for ProcessInfo {
-  fn column_types() -> &'static [gtk4::glib::Type] { &[gtk4::glib::Type::OBJECT, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::U64, gtk4::glib::Type::STRING] }
+  fn column_types() -> &'static [gtk4::glib::Type] { &[gtk4::glib::Type::OBJECT, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::U64, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING] }
   fn fill_row(store: &gtk4::ListStore, p: &Self) {
     let icon: Option<gtk4::gdk::Paintable> = p.icon.clone();
     let elev_dsply = if p.elevated { "  Yes" } else { "  No" };
-    store.insert_with_values(None, &[(0, &icon), (1, &elev_dsply), (2, &p.name), (3, &p.arch.to_string()), (4, &p.process_id), (5, &format!("{:#X}", p.process_id))]);
+    let fav_dsply = if p.favourite { "★" } else { "" };
+    store.insert_with_values(None, &[(0, &icon), (1, &elev_dsply), (2, &p.name), (3, &p.arch.to_string()), (4, &p.process_id), (5, &format!("{:#X}", p.process_id)), (6, &fav_dsply), (7, &p.access.name()), (8, &p.access.reason())]);
   }
+  fn filter_columns() -> &'static [(&'static str, i32)] { &[("admin", 1), ("name", 2), ("arch", 3), ("pid", 4), ("access", 7)] }
+  fn export_fields() -> &'static [&'static str] { &["name", "process_id", "arch", "elevated", "favourite", "access", "access_error"] }
+  fn export_values(&self) -> Vec<serde_json::Value> { vec![self.name.clone().into(), self.process_id.into(), self.arch.to_string().into(), self.elevated.into(), self.favourite.into(), self.access.name().into(), self.access.error().into()] }
+}
+
+impl ListRow for InjectionRecord {
+  fn column_types() -> &'static [gtk4::glib::Type] { &[gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::U64, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::U64, gtk4::glib::Type::STRING] }
+  fn fill_row(store: &gtk4::ListStore, r: &Self) {
+    let base = r.module_base.map(|b| format!("{:#X}", b)).unwrap_or_default();
+    let result = r.error.clone().or_else(|| r.warning.as_ref().map(|w| format!("Ok, {}", w))).unwrap_or_else(|| String::from("Ok"));
+    store.insert_with_values(None, &[(0, &r.timestamp.format("%Y-%m-%d %H:%M:%S").to_string()), (1, &r.target_name), (2, &(r.process_id as u64)), (3, &r.dll_path.display().to_string()), (4, &r.method), (5, &base), (6, &r.duration_ms), (7, &result)]);
+  }
+  fn filter_columns() -> &'static [(&'static str, i32)] { &[("time", 0), ("name", 1), ("pid", 2), ("dll", 3), ("method", 4), ("result", 7)] }
+  fn export_fields() -> &'static [&'static str] { &["timestamp", "target_name", "process_id", "dll_path", "dll_sha256", "method", "module_base", "duration_ms", "error", "warning"] }
+  fn export_values(&self) -> Vec<serde_json::Value> {
+    vec![
+      self.timestamp.to_rfc3339().into(),
+      self.target_name.clone().into(),
+      self.process_id.into(),
+      self.dll_path.display().to_string().into(),
+      self.dll_sha256.clone().into(),
+      self.method.clone().into(),
+      self.module_base.into(),
+      self.duration_ms.into(),
+      self.error.clone().into(),
+      self.warning.clone().into(),
+    ]
+  }
+}
+
+/// Enumerate the running processes and mark the user's favourites.
+fn load_processes(config: &Config) -> Vec<ProcessInfo> {
+  let mut processes = Kenjector::get_processes();
+  for p in &mut processes {
+    p.favourite = config.is_favourite(&p.name);
+  }
+  processes
+}
+
+/// Rebuild the recent DLLs popover, flagging files that no longer exist.
+fn fill_recent_dlls(list: &gtk4::ListBox, config: &Config) {
+  list.remove_all();
+
+  if config.recent_dlls.is_empty() {
+    let label = gtk4::Label::new(Some("No recent DLLs"));
+    label.add_css_class("dim-label");
+    list.append(&label);
+    return;
+  }
+
+  for path in &config.recent_dlls {
+    let label = gtk4::Label::builder().label(path.display().to_string()).xalign(0.0).build();
+    if !path.exists() {
+      label.set_label(&format!("{} (missing)", path.display()));
+      label.set_tooltip_text(Some("This file no longer exists"));
+      label.add_css_class("dim-label");
+    }
+    list.append(&label);
+  }
+}
+
+/// Name, description, architectures and access rights of an injection method, for the method picker.
+fn method_tooltip(info: &MethodInfo) -> String {
+  let archs = info.archs.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ");
+  format!("{}\n{}\n\nArchitectures: {}\nNeeds: {}", info.name, info.description, archs, info.access_names())
+}
+
+/// One line on what this instance can reach, `debug_privilege` is `None` when not elevated.
+fn access_summary(processes: &[ProcessInfo], debug_privilege: &Option<Result<(), String>>) -> String {
+  let summary = match debug_privilege {
+    Some(Ok(())) if cfg!(target_os = "linux") => String::from("Running as root"),
+    Some(Ok(())) => String::from("Running as administrator with SeDebugPrivilege"),
+    Some(Err(e)) => format!("Running as administrator without SeDebugPrivilege, {}", e),
+    None => format!("Not running as {}, {} processes are out of reach", if cfg!(target_os = "linux") { "root" } else { "administrator" }, processes.iter().filter(|p| matches!(p.access, AccessStatus::Elevated(_))).count()),
+  };
+  // Protected and foreign processes stay out of reach whether or not we are elevated
+  let summary = match processes.iter().filter(|p| matches!(p.access, AccessStatus::Protected(_) | AccessStatus::Denied(_))).count() {
+    0 => summary,
+    blocked => format!("{}, {} protected or denied", summary, blocked),
+  };
+
+  #[cfg(target_os = "linux")]
+  if let Some(restriction) = privilege::PtraceAccess::current().restriction() {
+    return format!("{}\n{}", summary, restriction);
+  }
+  summary
+}
+
+/// Start an elevated instance with `args` and close this one.
+fn restart_as_admin(window: &gtk4::ApplicationWindow, args: &[String]) {
+  match privilege::restart_as_admin(args) {
+    Ok(()) => {
+      if let Some(app) = window.application() {
+        app.quit();
+      }
+    }
+    Err(e) => message_box(window, "Restart failed", e, None),
+  }
+}
+
+/// Buttons that follow a Kenjection running in the background: the ones starting one are disabled
+/// and the cancel button shows until it ends.
+#[derive(Clone)]
+struct KenjectControls {
+  start_buttons: Vec<gtk4::Button>,
+  cancel_btn: gtk4::Button,
+}
+
+impl KenjectControls {
+  fn set_running(&self, running: bool) {
+    for button in &self.start_buttons {
+      button.set_sensitive(!running);
+    }
+    self.cancel_btn.set_visible(running);
+  }
+}
+
+/// Validate the DLL and target, run the Kenjection on a background thread, then record it in the
+/// history panel and log.
+fn kenject(window: &gtk4::ApplicationWindow, aps: &Arc<RwLock<AppState>>, history_view: &GenericListView<InjectionRecord>, controls: &KenjectControls, kenjection_info: &KenjectionInfo, path: PathBuf, method: InjectionMethod) {
+  // Verify the file is a DLL, or a shared object on Linux
+  match Kenjector::is_library(&path) {
+    Ok(true) => {}
+    Ok(false) => {
+      message_box(window, "Failed", "The chosen file is not a DLL", None);
+      return;
+    }
+    Err(e) => {
+      message_box(window, "Failed", e.to_string(), None);
+      return;
+    }
+  };
+
+  // Block up front with the reason, and offer to restart elevated with the same target and DLL when that is the fix
+  let we_elevated = Kenjector::running_elevated();
+  match Kenjector::access_status(kenjection_info.process_id, method.info().process_access, we_elevated) {
+    AccessStatus::Accessible => {}
+    status @ (AccessStatus::Elevated(_) | AccessStatus::Denied(_)) if cfg!(target_os = "windows") && !we_elevated => {
+      let restart_args = GuiArgs { process_id: Some(kenjection_info.process_id), dll: Some(path) }.to_args();
+      let window_c = window.clone();
+      confirm_box(window, "Kenjection needs administrator rights", format!("{}: {}.", kenjection_info.name, status.reason()), "Restart as administrator", move || restart_as_admin(&window_c, &restart_args));
+      return;
+    }
+    status => {
+      message_box(window, "Can't Kenject", format!("{}: {}.", kenjection_info.name, status.reason()), None);
+      return;
+    }
+  }
+
+  {
+    let mut state = aps.write();
+    state.config.add_recent_dll(&path);
+    state.recent_index = 0;
+    if let Err(e) = state.config.save() {
+      eprintln!("Failed to save the config, error: {}", e);
+    }
+  }
+
+  let deadline = Deadline::new(aps.read().config.injection_timeout());
+  aps.write().running = Some(deadline.clone());
+  controls.set_running(true);
+
+  // A payload exporting the init function gets a channel to talk back through, a reflective one isn't
+  // in the module list to find the export in
+  let mut channel = if method.info().in_module_list && Kenjector::has_init_export(&path) { ChannelServer::open(kenjection_info.process_id).inspect_err(|e| eprintln!("Failed to open a channel for the payload, error: {}", e)).ok() } else { None };
+
+  // A DllMain that hangs would freeze the window until the timeout, so wait for it on another thread
+  let started = Instant::now();
+  let (sender, receiver) = std::sync::mpsc::channel();
+  {
+    let kenjection_info = kenjection_info.clone();
+    let path = path.clone();
+    let endpoint = channel.as_ref().map(|c| c.endpoint.clone());
+    std::thread::spawn(move || {
+      let mut result = Kenjector::kennject(&kenjection_info, path.clone(), method, &deadline);
+      // The init export gets a whole timeout of its own, not what LoadLibrary left of it
+      let init = match (&mut result, endpoint) {
+        (Ok(kenjected), Some(endpoint)) => Some(Kenjector::call_init(kenjection_info.process_id, &path, kenjected.base, &kenjector_channel::init_config(&endpoint), &deadline.restart()).map(|(code, warning)| {
+          kenjected.warn(warning);
+          code
+        })),
+        _ => None,
+      };
+      let _ = sender.send((result, init));
+    });
+  }
+
+  let window = window.clone();
+  let aps = aps.clone();
+  let history_view = history_view.clone();
+  let controls = controls.clone();
+  let kenjection_info = kenjection_info.clone();
+  gtk4::glib::timeout_add_local(Duration::from_millis(50), move || {
+    let (result, init) = match receiver.try_recv() {
+      Ok(v) => v,
+      Err(TryRecvError::Empty) => return gtk4::glib::ControlFlow::Continue,
+      Err(TryRecvError::Disconnected) => (Err(String::from("The Kenjection thread panicked")), None),
+    };
+
+    aps.write().running = None;
+    controls.set_running(false);
+
+    let record = InjectionRecord::new(&kenjection_info.name, kenjection_info.process_id, path.clone(), method.to_string(), started, &result);
+    if let Err(e) = aps.read().history_log.append(&record) {
+      eprintln!("Failed to write the history log, error: {}", e);
+    }
+
+    {
+      let mut state = aps.write();
+      state.history.push(record);
+      history_view.set_items(&state.history);
+    }
+
+    match result {
+      Ok(kenjected) => {
+        let warning = kenjected.warning.map(|w| format!("\n{}", w)).unwrap_or_default();
+        message_box(&window, "Kenjection complete", format!("Kenjected into {}\nDLL Kenjected successfully at {:#X}{}", kenjection_info.name, kenjected.base, warning), None)
+      }
+      Err(e) => message_box(&window, "Kenjection failed", &format!("Failed to Kennject into {}\n{}", kenjection_info.name, e), None),
+    }
+    if let (Some(channel), Some(init)) = (channel.take(), init) {
+      console_view(&window, &kenjection_info.name, kenjection_info.process_id, channel, Some(init));
+    }
+    gtk4::glib::ControlFlow::Break
+  });
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+  // Lets an elevated Kenjector open services and other users' processes too, root needs nothing enabled
+  #[cfg(target_os = "windows")]
+  let debug_privilege = Kenjector::running_elevated().then(privilege::enable_debug_privilege);
+  #[cfg(target_os = "linux")]
+  let debug_privilege = Kenjector::running_elevated().then_some(Ok(()));
+  if let Some(Err(e)) = &debug_privilege {
+    eprintln!("Failed to enable SeDebugPrivilege, error: {}", e);
+  }
+
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if !GuiArgs::is_gui(&args) {
+    return cli::run(&args);
+  }
+  let gui_args = GuiArgs::parse(&args)?;
+
   let application = gtk4::Application::builder().build();
   let aps = Arc::new(RwLock::new(AppState::default()));
+  let history = aps.read().history_log.load();
+  aps.write().history = history;
+  aps.write().config = Config::load();
   let consts = aps.read().consts.clone();
 
   application.connect_activate(move |app| {
+    let aps = aps.clone();
+
     // dark mode
     gtk4::Settings::default().expect("Failed to get settings").set_gtk_application_prefer_dark_theme(true);
 
@@ -79,23 +332,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     grid.set_row_spacing(10);
     grid.set_column_spacing(10);
     grid.set_margin_all(consts.margin);
-    window.set_child(Some(&grid));
+
+    // Overlay so toasts can float above the grid
+    let overlay = gtk4::Overlay::builder().child(&grid).build();
+    window.set_child(Some(&overlay));
 
     let mut listview = GenericListView::<ProcessInfo>::new();
     let alignment = gtk4::pango::Alignment::Left;
     listview
       .add_icon_column("Icon", 0, Some(40))
+      .add_text_column("★", 6, Some(20), alignment)
       .add_text_column("Admin", 1, Some(50), alignment)
       .add_text_column("Name", 2, Some(400), alignment)
       .add_text_column("Arch", 3, None, alignment)
       .add_text_column("ID", 4, None, alignment)
       .add_text_column("0xID", 5, None, alignment)
+      .add_text_column("Access", 7, None, alignment)
+      .set_pinned_column(6)
       .enable_sorting(4, gtk4::SortType::Ascending)
       .set_row_mapper(ProcessInfo::fill_row);
+    // Why a process can't be Kenjected into shows when hovering its row
+    listview.tree_view.set_tooltip_column(8);
 
-    let proc_info_vec = Kenjector::get_processes();
+    let proc_info_vec = load_processes(&aps.read().config);
 
     listview.set_items(&proc_info_vec);
+    if let Some(process_id) = gui_args.process_id {
+      listview.select_where(|p| p.process_id == process_id);
+    }
 
     grid.attach(&listview.container, 0, 0, 2, 2);
 
@@ -103,6 +367,63 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     input.set_placeholder_text(Some("Path"));
     input.set_hexpand(true);
     // input.set_sensitive(false);
+    if let Some(path) = &gui_args.dll {
+      input.set_text(&path.to_string_lossy());
+    } else if let Some(path) = aps.read().config.recent_dlls.iter().find(|p| p.exists()) {
+      input.set_text(&path.to_string_lossy());
+    }
+
+    // Recent DLLs dropdown next to the path
+    let recent_list = gtk4::ListBox::new();
+    recent_list.set_selection_mode(gtk4::SelectionMode::None);
+    recent_list.set_activate_on_single_click(true);
+    let recent_popover = gtk4::Popover::builder().child(&gtk4::ScrolledWindow::builder().child(&recent_list).propagate_natural_height(true).propagate_natural_width(true).max_content_height(300).build()).build();
+    let recent_btn = gtk4::MenuButton::builder().icon_name("document-open-recent-symbolic").tooltip_text("Recent DLLs (Alt+Up / Alt+Down)").popover(&recent_popover).build();
+    {
+      let aps = aps.clone();
+      let recent_list_c = recent_list.clone();
+      recent_popover.connect_show(move |_| fill_recent_dlls(&recent_list_c, &aps.read().config));
+    }
+    {
+      let aps = aps.clone();
+      let input_c = input.clone();
+      let recent_popover_c = recent_popover.clone();
+      recent_list.connect_row_activated(move |_, row| {
+        let mut state = aps.write();
+        if let Some(path) = state.config.recent_dlls.get(row.index() as usize).cloned() {
+          state.recent_index = row.index() as usize;
+          input_c.set_text(&path.to_string_lossy());
+        }
+        recent_popover_c.popdown();
+      });
+    }
+
+    let path_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 4);
+    path_box.append(&input);
+    path_box.append(&recent_btn);
+
+    // Alt+Up / Alt+Down cycle through the recent DLLs
+    let key_controller = gtk4::EventControllerKey::new();
+    {
+      let aps = aps.clone();
+      let input_c = input.clone();
+      key_controller.connect_key_pressed(move |_, keyval, _keycode, state| {
+        if !state.contains(gtk4::gdk::ModifierType::ALT_MASK) || !matches!(keyval, gtk4::gdk::Key::Up | gtk4::gdk::Key::Down) {
+          return gtk4::glib::Propagation::Proceed;
+        }
+
+        let mut aps = aps.write();
+        let count = aps.config.recent_dlls.len();
+        if count == 0 {
+          return gtk4::glib::Propagation::Stop;
+        }
+
+        aps.recent_index = if keyval == gtk4::gdk::Key::Down { (aps.recent_index + 1) % count } else { (aps.recent_index + count - 1) % count };
+        input_c.set_text(&aps.config.recent_dlls[aps.recent_index].to_string_lossy());
+        gtk4::glib::Propagation::Stop
+      });
+    }
+    window.add_controller(key_controller);
 
     let input_c = input.clone();
     let window_c = window.clone();
@@ -119,7 +440,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         if resp == gtk4::ResponseType::Accept {
           if let Some(file) = dialog.file() {
             if let Some(path) = file.path() {
-              match Kenjector::is_pe_dll(&path) {
+              match Kenjector::is_library(&path) {
                 Ok(v) => {
                   if v {
                     input_c_c.set_text(path.to_str().unwrap_or_default());
@@ -138,23 +459,419 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
       dialog.show();
     });
 
-    grid.attach(&input, 0, 2, 1, 1);
+    grid.attach(&path_box, 0, 2, 1, 1);
     grid.attach(&browse_btn, 1, 2, 1, 1);
 
+    // Drag and drop a DLL anywhere on the window to fill the path
+    {
+      let input_c = input.clone();
+      let overlay_c = overlay.clone();
+      window.add_controller(dll_drop_target(&window, move |path, _, _| input_c.set_text(&path.to_string_lossy()), move |e| toast(&overlay_c, e)));
+    }
+
+    // Dropping onto a process row also selects that process as the target
+    {
+      let input_c = input.clone();
+      let overlay_c = overlay.clone();
+      let tree_view = listview.tree_view.clone();
+      let row_drop = dll_drop_target(
+        &listview.tree_view,
+        move |path, x, y| {
+          input_c.set_text(&path.to_string_lossy());
+          let (bx, by) = tree_view.convert_widget_to_bin_window_coords(x as i32, y as i32);
+          if let Some((Some(row), _, _, _)) = tree_view.path_at_pos(bx, by) {
+            tree_view.selection().unselect_all();
+            tree_view.selection().select_path(&row);
+            tree_view.scroll_to_cell(Some(&row), None::<&gtk4::TreeViewColumn>, false, 0.0, 0.0);
+          }
+          tree_view.set_drag_dest_row(None, gtk4::TreeViewDropPosition::IntoOrAfter);
+        },
+        move |e| toast(&overlay_c, e),
+      );
+
+      // Highlight the row under the pointer while dragging
+      let tree_view = listview.tree_view.clone();
+      row_drop.connect_motion(move |_, x, y| {
+        let (bx, by) = tree_view.convert_widget_to_bin_window_coords(x as i32, y as i32);
+        let row = tree_view.path_at_pos(bx, by).and_then(|(row, _, _, _)| row);
+        tree_view.set_drag_dest_row(row.as_ref(), gtk4::TreeViewDropPosition::IntoOrAfter);
+        gtk4::gdk::DragAction::COPY
+      });
+
+      let tree_view = listview.tree_view.clone();
+      row_drop.connect_leave(move |_| tree_view.set_drag_dest_row(None, gtk4::TreeViewDropPosition::IntoOrAfter));
+
+      listview.tree_view.add_controller(row_drop);
+    }
+
+    // What we can reach, with a way to elevate when that is not everything
+    let access_label = gtk4::Label::builder().label(access_summary(&proc_info_vec, &debug_privilege)).xalign(0.0).wrap(true).hexpand(true).build();
+    access_label.add_css_class("dim-label");
+    let restart_btn = gtk4::Button::with_label("Restart as administrator");
+    restart_btn.set_visible(cfg!(target_os = "windows") && debug_privilege.is_none());
+    restart_btn.set_tooltip_text(Some("Start Kenjector elevated, keeping the selected process and DLL path"));
+    {
+      let listview_c = listview.clone();
+      let input_c = input.clone();
+      let window_c = window.clone();
+      restart_btn.connect_clicked(move |_| {
+        let process_id = listview_c.selected_items().first().map(|p| p.process_id);
+        let dll = Some(PathBuf::from(input_c.text())).filter(|p| !p.as_os_str().is_empty());
+        restart_as_admin(&window_c, &GuiArgs { process_id, dll }.to_args());
+      });
+    }
+    let access_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+    access_box.append(&access_label);
+    access_box.append(&restart_btn);
+
     let refresh_btn = gtk4::Button::with_label("Refresh");
     {
       let listview_c = listview.clone();
+      let aps = aps.clone();
+      let access_label = access_label.clone();
+      let debug_privilege = debug_privilege.clone();
       refresh_btn.connect_clicked(move |_| {
-        let proc_info_vec = Kenjector::get_processes();
+        let proc_info_vec = load_processes(&aps.read().config);
         listview_c.set_items(&proc_info_vec);
+        access_label.set_label(&access_summary(&proc_info_vec, &debug_privilege));
+      });
+    }
+
+    // Pin or unpin the selected processes so they float to the top
+    let pin_btn = gtk4::Button::with_label("Pin / Unpin");
+    {
+      let listview_c = listview.clone();
+      let aps = aps.clone();
+      pin_btn.connect_clicked(move |_| {
+        let selected = listview_c.selected_items();
+        if selected.is_empty() {
+          return;
+        }
+
+        let mut state = aps.write();
+        for p in &selected {
+          state.config.toggle_favourite(&p.name);
+        }
+        if let Err(e) = state.config.save() {
+          eprintln!("Failed to save the config, error: {}", e);
+        }
+
+        let mut processes = listview_c.items();
+        for p in &mut processes {
+          p.favourite = state.config.is_favourite(&p.name);
+        }
+        listview_c.set_items(&processes);
+      });
+    }
+
+    // Tools working on the selected process
+    let scan_btn = gtk4::Button::with_label("Pattern scan");
+    scan_btn.set_tooltip_text(Some("Scan the selected process's memory for a byte signature"));
+    {
+      let listview_c = listview.clone();
+      let overlay_c = overlay.clone();
+      let window_c = window.clone();
+      scan_btn.connect_clicked(move |_| match listview_c.selected_items().first() {
+        Some(p) => scan_view(&window_c, &p.name, p.process_id),
+        None => toast(&overlay_c, "Select a process first"),
+      });
+    }
+    let regions_btn = gtk4::Button::with_label("Regions");
+    regions_btn.set_tooltip_text(Some("List the selected process's memory regions"));
+    {
+      let listview_c = listview.clone();
+      let overlay_c = overlay.clone();
+      let window_c = window.clone();
+      regions_btn.connect_clicked(move |_| match listview_c.selected_items().first() {
+        Some(p) => {
+          if let Err(e) = region_view(&window_c, &p.name, p.process_id) {
+            message_box(&window_c, "Memory regions", e, None);
+          }
+        }
+        None => toast(&overlay_c, "Select a process first"),
+      });
+    }
+    let process_memory_btn = gtk4::Button::with_label("Memory");
+    process_memory_btn.set_tooltip_text(Some("Inspect and edit the selected process's memory, starting at its executable"));
+    {
+      let listview_c = listview.clone();
+      let overlay_c = overlay.clone();
+      let window_c = window.clone();
+      process_memory_btn.connect_clicked(move |_| match listview_c.selected_items().first() {
+        Some(p) => {
+          if let Err(e) = ProcessMemory::open(p.process_id).and_then(|memory| memory.first_address(&p.name)).and_then(|address| hex_view(&window_c, &p.name, p.process_id, address)) {
+            message_box(&window_c, "Memory", e, None);
+          }
+        }
+        None => toast(&overlay_c, "Select a process first"),
+      });
+    }
+    let pointers_btn = gtk4::Button::with_label("Pointers");
+    pointers_btn.set_tooltip_text(Some("Follow pointer chains in the selected process and watch the values they lead to"));
+    {
+      let listview_c = listview.clone();
+      let overlay_c = overlay.clone();
+      let window_c = window.clone();
+      let aps = aps.clone();
+      pointers_btn.connect_clicked(move |_| match listview_c.selected_items().first() {
+        Some(p) => {
+          if let Err(e) = pointer_view(&window_c, &aps, &p.name, p.process_id) {
+            message_box(&window_c, "Pointers", e, None);
+          }
+        }
+        None => toast(&overlay_c, "Select a process first"),
+      });
+    }
+    let threads_btn = gtk4::Button::with_label("Threads");
+    threads_btn.set_tooltip_text(Some("List the selected process's threads, to suspend and resume them"));
+    {
+      let listview_c = listview.clone();
+      let overlay_c = overlay.clone();
+      let window_c = window.clone();
+      threads_btn.connect_clicked(move |_| match listview_c.selected_items().first() {
+        Some(p) => {
+          if let Err(e) = thread_view(&window_c, &p.name, p.process_id) {
+            message_box(&window_c, "Threads", e, None);
+          }
+        }
+        None => toast(&overlay_c, "Select a process first"),
+      });
+    }
+    let output_btn = gtk4::Button::with_label("Output");
+    output_btn.set_tooltip_text(Some("Capture the selected process's debug output, launching a new copy of it on Linux"));
+    {
+      let listview_c = listview.clone();
+      let overlay_c = overlay.clone();
+      let window_c = window.clone();
+      output_btn.connect_clicked(move |_| match listview_c.selected_items().first() {
+        Some(p) => {
+          if let Err(e) = debug_view(&window_c, &p.name, p.process_id) {
+            message_box(&window_c, "Debug output", e, None);
+          }
+        }
+        None => toast(&overlay_c, "Select a process first"),
+      });
+    }
+    let process_tools = gtk4::Box::new(gtk4::Orientation::Horizontal, 4);
+    process_tools.append(&pin_btn);
+    process_tools.append(&scan_btn);
+    process_tools.append(&regions_btn);
+    process_tools.append(&process_memory_btn);
+    process_tools.append(&pointers_btn);
+    process_tools.append(&threads_btn);
+    process_tools.append(&output_btn);
+
+    let export_btn = gtk4::Button::with_label("Export");
+    {
+      let aps = aps.clone();
+      let listview_c = listview.clone();
+      let window_c = window.clone();
+      export_btn.connect_clicked(move |_| {
+        let dialog = gtk4::FileChooserNative::new(Some("Export process list"), Some(&window_c), gtk4::FileChooserAction::Save, Some("Export"), Some("Cancel"));
+        dialog.set_current_name("processes.json");
+
+        let csv_filter = gtk4::FileFilter::new();
+        csv_filter.set_name(Some("CSV"));
+        csv_filter.add_pattern("*.csv");
+        let json_filter = gtk4::FileFilter::new();
+        json_filter.set_name(Some("JSON"));
+        json_filter.add_pattern("*.json");
+        dialog.add_filter(&json_filter);
+        dialog.add_filter(&csv_filter);
+
+        let aps = aps.clone();
+        let listview_c_c = listview_c.clone();
+        let window_c_c = window_c.clone();
+        dialog.connect_response(move |dialog, resp| {
+          if resp == gtk4::ResponseType::Accept {
+            if let Some(path) = dialog.file().and_then(|f| f.path()) {
+              let history = aps.read().history.clone();
+              match export::write_snapshot(&path, &listview_c_c.visible_items(), &history) {
+                Ok(written) => message_box(&window_c_c, "Export complete", written.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join("\n"), None),
+                Err(e) => message_box(&window_c_c, "Export failed", e.to_string(), None),
+              }
+            }
+          }
+          dialog.destroy();
+        });
+
+        dialog.show();
+      });
+    }
+
+    // Started from the Kenject and Re-run buttons, cancel shows while one runs
+    let inject_btn = gtk4::Button::with_label("Kenject");
+    inject_btn.set_hexpand(true);
+    let rerun_btn = gtk4::Button::with_label("Re-run");
+    let cancel_btn = gtk4::Button::with_label("Cancel");
+    cancel_btn.set_visible(false);
+    cancel_btn.set_tooltip_text(Some("Stop waiting for the Kenjection. Whatever already runs in the target keeps running"));
+    {
+      let aps = aps.clone();
+      cancel_btn.connect_clicked(move |_| {
+        if let Some(deadline) = &aps.read().running {
+          deadline.cancel();
+        }
+      });
+    }
+    let controls = KenjectControls { start_buttons: vec![inject_btn.clone(), rerun_btn.clone()], cancel_btn: cancel_btn.clone() };
+
+    // Injection history, newest first
+    let mut history_view = GenericListView::<InjectionRecord>::new();
+    history_view
+      .add_text_column("Time", 0, None, alignment)
+      .add_text_column("Target", 1, None, alignment)
+      .add_text_column("PID", 2, None, alignment)
+      .add_text_column("DLL", 3, Some(200), alignment)
+      .add_text_column("Method", 4, None, alignment)
+      .add_text_column("Base", 5, None, alignment)
+      .add_text_column("ms", 6, None, alignment)
+      .add_text_column("Result", 7, None, alignment)
+      .enable_sorting(0, gtk4::SortType::Descending)
+      .set_row_mapper(InjectionRecord::fill_row);
+    history_view.set_items(&aps.read().history);
+    history_view.container.set_size_request(-1, 150);
+
+    {
+      let aps = aps.clone();
+      let history_view_c = history_view.clone();
+      let window_c = window.clone();
+      let controls = controls.clone();
+      rerun_btn.connect_clicked(move |_| {
+        let Some(record) = history_view_c.selected_items().into_iter().next() else {
+          message_box(&window_c, "Re-run", "Select an injection in the history first", None);
+          return;
+        };
+
+        let kenjection_info = KenjectionInfo { name: record.target_name.clone(), process_id: record.process_id };
+        let method = InjectionMethod::from_name(&record.method).unwrap_or_default();
+        kenject(&window_c, &aps, &history_view_c, &controls, &kenjection_info, record.dll_path.clone(), method);
+      });
+    }
+
+    // A queued APC can load the DLL long after the Kenjection gave up waiting, so let the user look again
+    let check_btn = gtk4::Button::with_label("Check loaded");
+    check_btn.set_tooltip_text(Some("Check whether the selected entry's DLL is loaded in its target now"));
+    {
+      let history_view_c = history_view.clone();
+      let overlay_c = overlay.clone();
+      check_btn.connect_clicked(move |_| {
+        let Some(record) = history_view_c.selected_items().into_iter().next() else {
+          toast(&overlay_c, "Select an injection in the history first");
+          return;
+        };
+
+        let found = winpath::normalize_for_injection(&record.dll_path).and_then(|path| Kenjector::find_module(record.process_id, &path));
+        match found {
+          Ok(Some(module)) => toast(&overlay_c, format!("{} is loaded in {} at {:#X}", module.name, record.target_name, module.base)),
+          Ok(None) => toast(&overlay_c, format!("{} is not loaded in {}", record.dll_path.display(), record.target_name)),
+          Err(e) => toast(&overlay_c, e),
+        }
+      });
+    }
+
+    // Look at what the DLL did to its target, from where it was loaded
+    let memory_btn = gtk4::Button::with_label("Memory");
+    memory_btn.set_tooltip_text(Some("Inspect and edit the selected entry's target memory at the module base"));
+    {
+      let history_view_c = history_view.clone();
+      let overlay_c = overlay.clone();
+      let window_c = window.clone();
+      memory_btn.connect_clicked(move |_| {
+        let Some(record) = history_view_c.selected_items().into_iter().next() else {
+          toast(&overlay_c, "Select an injection in the history first");
+          return;
+        };
+
+        if let Err(e) = hex_view(&window_c, &record.target_name, record.process_id, record.module_base.unwrap_or_default()) {
+          message_box(&window_c, "Memory", e, None);
+        }
+      });
+    }
+
+    // Unloading runs the payload's own shutdown first, which may take a while, so wait on another thread
+    let eject_btn = gtk4::Button::with_label("Eject");
+    eject_btn.set_tooltip_text(Some("Unload the selected entry's DLL from its target, letting a payload built with the SDK stop first"));
+    {
+      let aps = aps.clone();
+      let history_view_c = history_view.clone();
+      let overlay_c = overlay.clone();
+      let window_c = window.clone();
+      eject_btn.connect_clicked(move |_| {
+        let Some(record) = history_view_c.selected_items().into_iter().next() else {
+          toast(&overlay_c, "Select an injection in the history first");
+          return;
+        };
+
+        let deadline = Deadline::new(aps.read().config.injection_timeout());
+        let overlay_c_c = overlay_c.clone();
+        let window_c_c = window_c.clone();
+        confirm_box(&window_c, format!("Eject {} from {}?", record.dll_path.display(), record.target_name), "Code still running from the DLL, or pointers into it, crash the target once it is unloaded.", "Eject", move || {
+          let (sender, receiver) = std::sync::mpsc::channel();
+          {
+            let record = record.clone();
+            std::thread::spawn(move || {
+              let _ = sender.send(winpath::normalize_for_injection(&record.dll_path).and_then(|path| Kenjector::eject(record.process_id, &path, &deadline)));
+            });
+          }
+          gtk4::glib::timeout_add_local(Duration::from_millis(50), move || {
+            let result = match receiver.try_recv() {
+              Ok(result) => result,
+              Err(TryRecvError::Empty) => return gtk4::glib::ControlFlow::Continue,
+              Err(TryRecvError::Disconnected) => Err(String::from("The eject thread panicked")),
+            };
+            match result {
+              Ok(base) => toast(&overlay_c_c, format!("Ejected {} from {}, it was at {:#X}", record.dll_path.display(), record.target_name, base)),
+              Err(e) => message_box(&window_c_c, "Eject failed", e, None),
+            }
+            gtk4::glib::ControlFlow::Break
+          });
+        });
+      });
+    }
+
+    let history_buttons = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+    history_buttons.set_halign(gtk4::Align::End);
+    history_buttons.append(&memory_btn);
+    history_buttons.append(&eject_btn);
+    history_buttons.append(&check_btn);
+    history_buttons.append(&rerun_btn);
+
+    let history_box = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+    history_box.append(&history_view.container);
+    history_box.append(&history_buttons);
+    let history_expander = gtk4::Expander::builder().label("History").child(&history_box).build();
+
+    // Injection method, picked per Kenjection
+    let method_dropdown = gtk4::DropDown::from_strings(&method::REGISTRY.iter().map(|m| m.name).collect::<Vec<_>>());
+    method_dropdown.set_tooltip_text(Some(&method_tooltip(&method::REGISTRY[0])));
+    method_dropdown.connect_selected_notify(|dropdown| {
+      if let Some(info) = method::REGISTRY.get(dropdown.selected() as usize) {
+        dropdown.set_tooltip_text(Some(&method_tooltip(info)));
+      }
+    });
+
+    // How long to wait on the target, kept in the config
+    let timeout_spin = gtk4::SpinButton::with_range(100.0, 600_000.0, 500.0);
+    timeout_spin.set_value(aps.read().config.injection_timeout_ms as f64);
+    timeout_spin.set_tooltip_text(Some("Timeout in ms. How long to wait for DllMain to return before giving up"));
+    {
+      let aps = aps.clone();
+      timeout_spin.connect_value_changed(move |spin| {
+        let mut state = aps.write();
+        state.config.injection_timeout_ms = spin.value() as u64;
+        if let Err(e) = state.config.save() {
+          eprintln!("Failed to save the config, error: {}", e);
+        }
       });
     }
 
     let listview_c = listview.clone();
     let input_c = input.clone();
     let window_c = window.clone();
+    let history_view_c = history_view.clone();
+    let method_dropdown_c = method_dropdown.clone();
 
-    let inject_btn = gtk4::Button::with_label("Kenject");
     inject_btn.connect_clicked(move |_| {
       let selected_iters = listview_c.get_selected();
       let mut process_id = u64::MAX;
@@ -173,44 +890,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
       let process_id = process_id as u32;
       let kenjection_info = KenjectionInfo { name: process_name.clone(), process_id };
       let path = PathBuf::from(input_c.text());
+      let method = method::REGISTRY.get(method_dropdown_c.selected() as usize).map(|m| m.method).unwrap_or_default();
 
-      // Verify the file is a valid PE DLL
-      let path_valid = match Kenjector::is_pe_dll(&path) {
-        Ok(true) => true,
-        Ok(false) => {
-          message_box(&window_c, "Failed", "The chosen file is not a DLL", None);
-          false
-        }
-        Err(e) => {
-          message_box(&window_c, "Failed", e.to_string(), None);
-          false
-        }
-      };
-
-      if path_valid {
-        if !Kenjector::is_elevated(unsafe { GetCurrentProcess() }).unwrap() {
-          match Kenjector::open_process(Access::Limited, process_id) {
-            Ok(process_handle) => {
-              if let Ok(true) = Kenjector::is_elevated(process_handle) {
-                return;
-              }
-            }
-            Err(_) => {
-              message_box(&window_c, "Kenjection failed", "Can't Kenject into an elevated process without running as admin", None);
-              return;
-            }
-          };
-        }
-
-        match Kenjector::kennject(&kenjection_info, path.clone()) {
-          Ok(v) => message_box(&window_c, "Kenjection complete", &format!("Kenjected into {}\n{}", process_name, v), None),
-          Err(e) => message_box(&window_c, "Kenjection failed", &format!("Failed to Kennject into {}\n{}", process_name, e), None),
-        }
-      }
+      kenject(&window_c, &aps, &history_view_c, &controls, &kenjection_info, path, method);
     });
 
-    grid.attach(&inject_btn, 0, 3, 1, 1);
+    let inject_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 4);
+    inject_box.append(&method_dropdown);
+    inject_box.append(&timeout_spin);
+    inject_box.append(&inject_btn);
+    inject_box.append(&cancel_btn);
+
+    grid.attach(&inject_box, 0, 3, 1, 1);
     grid.attach(&refresh_btn, 1, 3, 1, 1);
+    grid.attach(&process_tools, 0, 4, 1, 1);
+    grid.attach(&export_btn, 1, 4, 1, 1);
+    grid.attach(&history_expander, 0, 5, 2, 1);
+    grid.attach(&access_box, 0, 6, 2, 1);
 
     window.present();
 
@@ -218,6 +914,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     GtkHelper::centre_to_screen(&window).unwrap();
   });
 
-  application.run();
+  // Our own options are parsed above, GTK would reject them
+  application.run_with_args(&std::env::args().take(1).collect::<Vec<_>>());
   Ok(())
 }
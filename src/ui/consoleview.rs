@@ -0,0 +1,103 @@
+use crate::{MarginAll, logic::channel::{ChannelEvent, ChannelServer}};
+use gtk4::prelude::*;
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+/// Add a line to the end of the console, with the time it arrived, and keep it in view.
+fn append(view: &gtk4::TextView, prefix: &str, line: &str) {
+  let buffer = view.buffer();
+  let mut end = buffer.end_iter();
+  buffer.insert(&mut end, &format!("{} {}{}\n", chrono::Local::now().format("%H:%M:%S%.3f"), prefix, line));
+  view.scroll_to_iter(&mut buffer.end_iter(), 0.0, false, 0.0, 1.0);
+}
+
+/// Open a console on the channel to the payload Kenjected into `process_id`: what it logs and replies
+/// shows up as it arrives and commands typed in are sent to it. Closing the window closes the channel.
+/// `init` is how the payload's init export went, when it was called.
+pub fn console_view(parent: &gtk4::ApplicationWindow, name: &str, process_id: u32, server: ChannelServer, init: Option<Result<u32, String>>) {
+  let window = gtk4::Window::builder().title(format!("Console - {} - {:#X}", name, process_id)).transient_for(parent).default_width(760).default_height(480).build();
+
+  let view = gtk4::TextView::builder().editable(false).cursor_visible(false).wrap_mode(gtk4::WrapMode::WordChar).build();
+  view.add_css_class("monospace");
+  let command_entry = gtk4::Entry::builder().placeholder_text("Command for the payload").hexpand(true).sensitive(false).build();
+  command_entry.add_css_class("monospace");
+  let send_btn = gtk4::Button::builder().label("Send").sensitive(false).build();
+  let command_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+  command_box.append(&command_entry);
+  command_box.append(&send_btn);
+
+  let status = gtk4::Label::builder().xalign(0.0).wrap(true).selectable(true).build();
+  status.set_label(&match init {
+    Some(Ok(0)) | None => format!("Waiting for the payload to connect to {}", server.endpoint),
+    Some(Ok(code)) => format!("The payload's init export returned {}, it may not connect to {}", code, server.endpoint),
+    Some(Err(e)) => format!("Calling the payload's init export failed, error: {}", e),
+  });
+
+  let server = Rc::new(server);
+  {
+    let server = server.clone();
+    let view = view.clone();
+    let status = status.clone();
+    let command_entry_c = command_entry.clone();
+    let send = move || {
+      let command = command_entry_c.text().trim().to_string();
+      if command.is_empty() {
+        return;
+      }
+      match server.send_command(&command) {
+        Ok(()) => {
+          append(&view, "> ", &command);
+          command_entry_c.set_text("");
+        }
+        Err(e) => status.set_label(&e),
+      }
+    };
+    let send_c = send.clone();
+    send_btn.connect_clicked(move |_| send_c());
+    command_entry.connect_activate(move |_| send());
+  }
+
+  // Polls the channel until it is closed, removed with the window
+  let source = Rc::new(RefCell::new(None::<gtk4::glib::SourceId>));
+  {
+    let source_c = source.clone();
+    let view = view.clone();
+    let status = status.clone();
+    let command_entry = command_entry.clone();
+    let send_btn = send_btn.clone();
+    *source.borrow_mut() = Some(gtk4::glib::timeout_add_local(Duration::from_millis(50), move || {
+      while let Some(event) = server.try_recv() {
+        match event {
+          ChannelEvent::Connected { process_id } => {
+            status.set_label(&format!("Connected to the payload in process {}", process_id));
+            command_entry.set_sensitive(true);
+            send_btn.set_sensitive(true);
+          }
+          ChannelEvent::Log(line) => append(&view, "", &line),
+          ChannelEvent::Reply(text) => append(&view, "< ", &text),
+          ChannelEvent::Closed(error) => {
+            status.set_label(&error.map_or_else(|| String::from("The payload closed the channel"), |e| format!("The channel closed, error: {}", e)));
+            command_entry.set_sensitive(false);
+            send_btn.set_sensitive(false);
+            source_c.borrow_mut().take();
+            return gtk4::glib::ControlFlow::Break;
+          }
+        }
+      }
+      gtk4::glib::ControlFlow::Continue
+    }));
+  }
+  window.connect_close_request(move |_| {
+    if let Some(source) = source.borrow_mut().take() {
+      source.remove();
+    }
+    gtk4::glib::Propagation::Proceed
+  });
+
+  let content = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+  content.set_margin_all(10);
+  content.append(&gtk4::ScrolledWindow::builder().child(&view).vexpand(true).build());
+  content.append(&command_box);
+  content.append(&status);
+  window.set_child(Some(&content));
+  window.present();
+}
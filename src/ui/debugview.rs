@@ -0,0 +1,160 @@
+use crate::{MarginAll, logic::debugoutput::{CaptureEvent, OutputCapture, OutputLine}, ui::{export, listview::{GenericListView, ListRow}}};
+use gtk4::prelude::*;
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+impl ListRow for OutputLine {
+  fn column_types() -> &'static [gtk4::glib::Type] { &[gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING] }
+  fn fill_row(store: &gtk4::ListStore, l: &Self) { store.insert_with_values(None, &[(0, &l.time.format("%H:%M:%S%.3f").to_string()), (1, &l.source.to_string()), (2, &l.text)]); }
+  fn filter_columns() -> &'static [(&'static str, i32)] { &[("time", 0), ("source", 1), ("text", 2)] }
+  fn export_fields() -> &'static [&'static str] { &["time", "source", "text"] }
+  fn export_values(&self) -> Vec<serde_json::Value> { vec![self.time.to_rfc3339().into(), self.source.to_string().into(), self.text.clone().into()] }
+}
+
+/// Open a log of the debug output of `process_id`. On Windows it is what the process passes to
+/// `OutputDebugString`, on Linux what a copy of it launched from the window writes to stdout and stderr.
+pub fn debug_view(parent: &gtk4::ApplicationWindow, name: &str, process_id: u32) -> Result<(), String> {
+  let capture = Rc::new(RefCell::new(None::<OutputCapture>));
+  if cfg!(target_os = "windows") {
+    *capture.borrow_mut() = Some(OutputCapture::attach(process_id)?);
+  }
+  let window = gtk4::Window::builder().title(format!("Debug output - {} - {:#X}", name, process_id)).transient_for(parent).default_width(860).default_height(520).build();
+
+  let mut listview = GenericListView::<OutputLine>::new();
+  let alignment = gtk4::pango::Alignment::Left;
+  listview.add_text_column("Time", 0, None, alignment).add_text_column("Source", 1, None, alignment).add_text_column("Text", 2, None, alignment).set_row_mapper(OutputLine::fill_row);
+
+  let status = gtk4::Label::builder().xalign(0.0).wrap(true).hexpand(true).build();
+  let content = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+  content.set_margin_all(10);
+
+  #[cfg(target_os = "linux")]
+  {
+    use crate::logic::debugoutput::{join_arguments, split_arguments};
+
+    let program_entry = gtk4::Entry::builder().placeholder_text("Program").hexpand(true).build();
+    let args_entry = gtk4::Entry::builder().placeholder_text("Arguments").tooltip_text("Double quotes keep an argument with spaces together, \\\" and \\\\ are a literal \" and \\").hexpand(true).build();
+    if let Ok((program, args)) = OutputCapture::command_of(process_id) {
+      program_entry.set_text(&program.display().to_string());
+      args_entry.set_text(&join_arguments(&args));
+    }
+    let launch_btn = gtk4::Button::with_label("Launch");
+    launch_btn.set_tooltip_text(Some("Start the program with its stdout and stderr captured here, the running process can't be captured"));
+    {
+      let capture = capture.clone();
+      let status = status.clone();
+      let window = window.clone();
+      let name = name.to_string();
+      let program_entry = program_entry.clone();
+      let args_entry = args_entry.clone();
+      launch_btn.connect_clicked(move |_| {
+        let program = program_entry.text().trim().to_string();
+        if program.is_empty() {
+          status.set_label("Enter the program to launch");
+          return;
+        }
+        match OutputCapture::launch(std::path::Path::new(&program), &split_arguments(&args_entry.text())) {
+          Ok(launched) => {
+            status.set_label(&format!("Capturing process {}", launched.process_id));
+            window.set_title(Some(&format!("Debug output - {} - {:#X}", name, launched.process_id)));
+            *capture.borrow_mut() = Some(launched);
+          }
+          Err(e) => status.set_label(&e),
+        }
+      });
+    }
+    {
+      let launch_btn = launch_btn.clone();
+      args_entry.connect_activate(move |_| launch_btn.emit_clicked());
+    }
+
+    let launch_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+    launch_box.append(&program_entry);
+    launch_box.append(&args_entry);
+    launch_box.append(&launch_btn);
+    content.append(&launch_box);
+    status.set_label("The output of a running process can't be captured, launch a new one to see its stdout and stderr");
+  }
+  #[cfg(target_os = "windows")]
+  status.set_label(&format!("Listening for OutputDebugString from process {}", process_id));
+
+  let follow_btn = gtk4::CheckButton::builder().label("Follow").active(true).tooltip_text("Keep the newest line in view").build();
+  let clear_btn = gtk4::Button::with_label("Clear");
+  {
+    let listview = listview.clone();
+    clear_btn.connect_clicked(move |_| listview.set_items(&[]));
+  }
+  let save_btn = gtk4::Button::with_label("Save");
+  save_btn.set_tooltip_text(Some("Save the lines shown to a .txt, .csv or .json file"));
+  {
+    let listview = listview.clone();
+    let window = window.clone();
+    let status = status.clone();
+    save_btn.connect_clicked(move |_| {
+      let dialog = gtk4::FileChooserNative::new(Some("Save debug output"), Some(&window), gtk4::FileChooserAction::Save, Some("Save"), Some("Cancel"));
+      dialog.set_current_name("output.txt");
+      let listview = listview.clone();
+      let status = status.clone();
+      dialog.connect_response(move |dialog, resp| {
+        if resp == gtk4::ResponseType::Accept
+          && let Some(path) = dialog.file().and_then(|f| f.path())
+        {
+          let lines = listview.visible_items();
+          match export::write_output(&path, &lines) {
+            Ok(()) => status.set_label(&format!("Saved {} lines to {}", lines.len(), path.display())),
+            Err(e) => status.set_label(&format!("Failed to save to {}, error: {}", path.display(), e)),
+          }
+        }
+        dialog.destroy();
+      });
+      dialog.show();
+    });
+  }
+
+  // Polls the capture, removed with the window, which also stops the capture
+  let source = {
+    let capture = capture.clone();
+    let listview = listview.clone();
+    let status = status.clone();
+    let follow_btn = follow_btn.clone();
+    gtk4::glib::timeout_add_local(Duration::from_millis(50), move || {
+      let mut lines = Vec::new();
+      if let Some(capture) = capture.borrow().as_ref() {
+        while let Some(event) = capture.try_recv() {
+          match event {
+            CaptureEvent::Line(line) => lines.push(line),
+            CaptureEvent::Ended(reason) => status.set_label(&reason),
+          }
+        }
+      }
+      if !lines.is_empty() {
+        listview.append_items(&lines);
+        if follow_btn.is_active() {
+          let adjustment = listview.scrolled.vadjustment();
+          // The new rows are only measured once the list is laid out again
+          gtk4::glib::idle_add_local_once(move || adjustment.set_value(adjustment.upper()));
+        }
+      }
+      gtk4::glib::ControlFlow::Continue
+    })
+  };
+  let source = RefCell::new(Some(source));
+  window.connect_close_request(move |_| {
+    if let Some(source) = source.borrow_mut().take() {
+      source.remove();
+    }
+    capture.borrow_mut().take();
+    gtk4::glib::Propagation::Proceed
+  });
+
+  let button_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+  button_box.append(&status);
+  button_box.append(&follow_btn);
+  button_box.append(&clear_btn);
+  button_box.append(&save_btn);
+
+  content.append(&listview.container);
+  content.append(&button_box);
+  window.set_child(Some(&content));
+  window.present();
+  Ok(())
+}
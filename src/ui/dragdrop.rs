@@ -0,0 +1,64 @@
+use crate::logic::kenjector::Kenjector;
+use gtk4::prelude::*;
+use std::path::PathBuf;
+
+/// Build a drop target on `widget` that accepts one DLL file dragged from the file manager.
+///
+/// The widget gets the `drop_hover` class while a drag is over it. A file that passes
+/// `Kenjector::is_library` goes to `on_dll` along with the drop coordinates, anything else to `on_error`.
+pub fn dll_drop_target<W, D, E>(widget: &W, on_dll: D, on_error: E) -> gtk4::DropTarget
+where
+  W: IsA<gtk4::Widget>,
+  D: Fn(PathBuf, f64, f64) + 'static,
+  E: Fn(String) + 'static,
+{
+  let drop_target = gtk4::DropTarget::new(gtk4::gdk::FileList::static_type(), gtk4::gdk::DragAction::COPY);
+
+  {
+    let widget = widget.clone().upcast::<gtk4::Widget>();
+    drop_target.connect_enter(move |_, _, _| {
+      widget.add_css_class("drop_hover");
+      gtk4::gdk::DragAction::COPY
+    });
+  }
+
+  {
+    let widget = widget.clone().upcast::<gtk4::Widget>();
+    drop_target.connect_leave(move |_| widget.remove_css_class("drop_hover"));
+  }
+
+  let widget = widget.clone().upcast::<gtk4::Widget>();
+  drop_target.connect_drop(move |_, value, x, y| {
+    widget.remove_css_class("drop_hover");
+
+    match dropped_dll(value) {
+      Ok(path) => {
+        on_dll(path, x, y);
+        true
+      }
+      Err(e) => {
+        on_error(e);
+        false
+      }
+    }
+  });
+
+  drop_target
+}
+
+fn dropped_dll(value: &gtk4::glib::Value) -> Result<PathBuf, String> {
+  let files = value.get::<gtk4::gdk::FileList>().map_err(|_| String::from("Only files can be dropped here"))?;
+  let files = files.files();
+
+  let path = match files.as_slice() {
+    [file] => file.path().ok_or("The dropped file is not on a local disk")?,
+    [] => return Err(String::from("Nothing was dropped")),
+    _ => return Err(String::from("Drop a single DLL at a time")),
+  };
+
+  match Kenjector::is_library(&path) {
+    Ok(true) => Ok(path),
+    Ok(false) => Err(format!("{} is not a DLL", path.display())),
+    Err(e) => Err(format!("{} is not a DLL, {}", path.display(), e)),
+  }
+}
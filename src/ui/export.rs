@@ -0,0 +1,68 @@
+use crate::{logic::{debugoutput::OutputLine, history::InjectionRecord, kenjector::ProcessInfo}, ui::listview::ListRow};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+  Csv,
+  Json,
+}
+
+impl ExportFormat {
+  /// Pick the format from the file extension, `.json` or `.csv`.
+  pub fn from_path(path: &Path) -> Result<Self, String> {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+      Some("csv") => Ok(Self::Csv),
+      Some("json") => Ok(Self::Json),
+      _ => Err(format!("Unsupported export format for {}, use a .csv or .json file", path.display())),
+    }
+  }
+}
+
+/// Write the process list and injection history to `path`.
+///
+/// JSON gets a single document with a `processes` and an `injections` array. CSV can only
+/// hold one table, so the history goes next to it as `<name>.injections.csv`.
+/// Returns every file written.
+pub fn write_snapshot(path: &Path, processes: &[ProcessInfo], history: &[InjectionRecord]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+  match ExportFormat::from_path(path)? {
+    ExportFormat::Json => {
+      let document = serde_json::json!({ "processes": ProcessInfo::to_json(processes), "injections": InjectionRecord::to_json(history) });
+      std::fs::write(path, serde_json::to_string_pretty(&document)?)?;
+      Ok(vec![path.to_path_buf()])
+    }
+    ExportFormat::Csv => {
+      std::fs::write(path, ProcessInfo::to_csv(processes))?;
+      let mut written = vec![path.to_path_buf()];
+
+      if !history.is_empty() {
+        let history_path = path.with_extension("injections.csv");
+        std::fs::write(&history_path, InjectionRecord::to_csv(history))?;
+        written.push(history_path);
+      }
+
+      Ok(written)
+    }
+  }
+}
+
+/// Write only the injection history to `path`, as a CSV table or a JSON array.
+pub fn write_history(path: &Path, history: &[InjectionRecord]) -> Result<(), Box<dyn std::error::Error>> {
+  let contents = match ExportFormat::from_path(path)? {
+    ExportFormat::Csv => InjectionRecord::to_csv(history),
+    ExportFormat::Json => serde_json::to_string_pretty(&InjectionRecord::to_json(history))?,
+  };
+  std::fs::write(path, contents)?;
+  Ok(())
+}
+
+/// Write captured debug output to `path`, as a CSV table or a JSON array, or as plain text lines for
+/// any other extension.
+pub fn write_output(path: &Path, lines: &[OutputLine]) -> Result<(), Box<dyn std::error::Error>> {
+  let contents = match ExportFormat::from_path(path) {
+    Ok(ExportFormat::Csv) => OutputLine::to_csv(lines),
+    Ok(ExportFormat::Json) => serde_json::to_string_pretty(&OutputLine::to_json(lines))?,
+    Err(_) => lines.iter().map(|l| format!("{} [{}] {}\n", l.time.format("%Y-%m-%d %H:%M:%S%.3f"), l.source, l.text)).collect(),
+  };
+  std::fs::write(path, contents)?;
+  Ok(())
+}
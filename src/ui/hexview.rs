@@ -0,0 +1,165 @@
+use crate::{MarginAll, logic::memory::ProcessMemory};
+use gtk4::prelude::*;
+use std::{cell::Cell, rc::Rc};
+
+/// Bytes per row and rows per page of the dump.
+const ROW_LEN: usize = 16;
+const ROWS: usize = 16;
+const PAGE_LEN: u64 = (ROW_LEN * ROWS) as u64;
+
+/// The widgets showing one page of memory, refilled whenever the address changes.
+#[derive(Clone)]
+struct HexPage {
+  memory: Rc<ProcessMemory>,
+  address: Rc<Cell<u64>>,
+  offsets: Vec<gtk4::Label>,
+  bytes: Vec<gtk4::Entry>,
+  text: Vec<gtk4::Label>,
+  status: gtk4::Label,
+}
+
+impl HexPage {
+  /// Read the page at the current address again, unreadable bytes show as `??`.
+  fn refresh(&self) {
+    let address = self.address.get();
+    let bytes = self.memory.read_lossy(address, PAGE_LEN as usize);
+
+    for (row, chunk) in bytes.chunks(ROW_LEN).enumerate() {
+      self.offsets[row].set_label(&format!("{:016X}", address.wrapping_add((row * ROW_LEN) as u64)));
+      self.text[row].set_label(&chunk.iter().map(|b| b.filter(|b| b.is_ascii_graphic() || *b == b' ').map_or('.', char::from)).collect::<String>());
+    }
+    for (entry, byte) in self.bytes.iter().zip(&bytes) {
+      entry.set_text(&byte.map_or(String::from("??"), |b| format!("{:02X}", b)));
+      entry.set_sensitive(byte.is_some());
+    }
+
+    let unreadable = bytes.iter().filter(|b| b.is_none()).count();
+    self.status.set_label(&match unreadable {
+      0 => String::new(),
+      n if n == bytes.len() => format!("Nothing at {:#X} can be read", address),
+      n => format!("{} bytes can't be read", n),
+    });
+  }
+
+  fn go_to(&self, address: u64) {
+    self.address.set(address);
+    self.refresh();
+  }
+
+  /// Write the byte typed into entry `index`, then show what the target holds now.
+  fn write(&self, index: usize, text: &str) {
+    let address = self.address.get().wrapping_add(index as u64);
+    match u8::from_str_radix(text.trim(), 16) {
+      Ok(byte) => match self.memory.write(address, &[byte]) {
+        Ok(()) => {
+          self.refresh();
+          self.status.set_label(&format!("Wrote {:02X} at {:#X}", byte, address));
+        }
+        Err(e) => {
+          self.refresh();
+          self.status.set_label(&e);
+        }
+      },
+      Err(_) => self.status.set_label(&format!("{} is not a hex byte", text)),
+    }
+  }
+}
+
+/// Parse an address typed as hex, with or without `0x`.
+fn parse_address(text: &str) -> Option<u64> {
+  let text = text.trim();
+  u64::from_str_radix(text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text), 16).ok()
+}
+
+/// Open a window showing the memory of `process_id` from `address`, with bytes that can be edited in
+/// place. Enter writes an edited byte.
+pub fn hex_view(parent: &gtk4::ApplicationWindow, name: &str, process_id: u32, address: u64) -> Result<(), String> {
+  let memory = Rc::new(ProcessMemory::open(process_id)?);
+  let window = gtk4::Window::builder().title(format!("Memory - {} - {:#X}", name, process_id)).transient_for(parent).default_width(760).default_height(520).build();
+
+  let address_entry = gtk4::Entry::builder().text(format!("{:X}", address)).placeholder_text("Address (hex)").hexpand(true).build();
+  address_entry.add_css_class("monospace");
+  let go_btn = gtk4::Button::with_label("Go");
+  let prev_btn = gtk4::Button::from_icon_name("go-previous-symbolic");
+  prev_btn.set_tooltip_text(Some("Previous 256 bytes"));
+  let next_btn = gtk4::Button::from_icon_name("go-next-symbolic");
+  next_btn.set_tooltip_text(Some("Next 256 bytes"));
+  let refresh_btn = gtk4::Button::with_label("Refresh");
+
+  let bar = gtk4::Box::new(gtk4::Orientation::Horizontal, 4);
+  bar.append(&address_entry);
+  bar.append(&go_btn);
+  bar.append(&prev_btn);
+  bar.append(&next_btn);
+  bar.append(&refresh_btn);
+
+  // Offset, 16 bytes and their text on every row
+  let dump = gtk4::Grid::builder().column_spacing(2).row_spacing(2).build();
+  let mut page = HexPage {
+    memory,
+    address: Rc::new(Cell::new(address)),
+    offsets: Vec::new(),
+    bytes: Vec::new(),
+    text: Vec::new(),
+    status: gtk4::Label::builder().xalign(0.0).wrap(true).build(),
+  };
+  for row in 0..ROWS {
+    let offset = gtk4::Label::new(None);
+    offset.add_css_class("monospace");
+    offset.add_css_class("dim-label");
+    dump.attach(&offset, 0, row as i32, 1, 1);
+    page.offsets.push(offset);
+
+    for column in 0..ROW_LEN {
+      let entry = gtk4::Entry::builder().width_chars(2).max_width_chars(2).max_length(2).xalign(0.5).has_frame(false).build();
+      entry.add_css_class("monospace");
+      dump.attach(&entry, column as i32 + 1, row as i32, 1, 1);
+      page.bytes.push(entry);
+    }
+
+    let text = gtk4::Label::builder().xalign(0.0).margin_start(8).build();
+    text.add_css_class("monospace");
+    dump.attach(&text, ROW_LEN as i32 + 1, row as i32, 1, 1);
+    page.text.push(text);
+  }
+
+  for (index, entry) in page.bytes.iter().enumerate() {
+    let page_c = page.clone();
+    entry.connect_activate(move |entry| page_c.write(index, &entry.text()));
+  }
+  {
+    let page_c = page.clone();
+    let go = move |entry: &gtk4::Entry| match parse_address(&entry.text()) {
+      Some(address) => page_c.go_to(address),
+      None => page_c.status.set_label(&format!("{} is not a hex address", entry.text())),
+    };
+    let address_entry_c = address_entry.clone();
+    let go_c = go.clone();
+    go_btn.connect_clicked(move |_| go_c(&address_entry_c));
+    address_entry.connect_activate(go);
+  }
+  for (button, step) in [(&prev_btn, PAGE_LEN.wrapping_neg()), (&next_btn, PAGE_LEN)] {
+    let page_c = page.clone();
+    let address_entry_c = address_entry.clone();
+    button.connect_clicked(move |_| {
+      let address = page_c.address.get().wrapping_add(step);
+      address_entry_c.set_text(&format!("{:X}", address));
+      page_c.go_to(address);
+    });
+  }
+  {
+    let page_c = page.clone();
+    refresh_btn.connect_clicked(move |_| page_c.refresh());
+  }
+
+  let content = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+  content.set_margin_all(10);
+  content.append(&bar);
+  content.append(&gtk4::ScrolledWindow::builder().child(&dump).vexpand(true).build());
+  content.append(&page.status);
+  window.set_child(Some(&content));
+
+  page.refresh();
+  window.present();
+  Ok(())
+}
@@ -1,4 +1,7 @@
+use crate::logic::filter::{FieldValue, Filter, Filterable};
 use gtk4::prelude::*;
+use parking_lot::RwLock;
+use serde_json::{Map, Value};
 use std::{marker::PhantomData, sync::Arc};
 
 /// Trait each row-type must implement to provide column schema and fill logic.
@@ -7,6 +10,79 @@ pub trait ListRow {
   fn column_types() -> &'static [gtk4::glib::Type];
   /// Called for each item to insert its values into the store.
   fn fill_row(store: &gtk4::ListStore, item: &Self);
+  /// Field names understood by the search filter and the column each one reads.
+  /// When empty, only bare terms are accepted and they search every column.
+  fn filter_columns() -> &'static [(&'static str, i32)] { &[] }
+  /// Stable field names used as the CSV header and JSON keys on export.
+  fn export_fields() -> &'static [&'static str];
+  /// This item's values, in the order of `export_fields`.
+  fn export_values(&self) -> Vec<Value>;
+
+  /// Serialize `items` as CSV with a header row.
+  fn to_csv(items: &[Self]) -> String
+  where
+    Self: Sized,
+  {
+    let mut csv = Self::export_fields().iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(",");
+    csv.push_str("\r\n");
+
+    for item in items {
+      let record: Vec<String> = item
+        .export_values()
+        .iter()
+        .map(|v| match v {
+          Value::Null => String::new(),
+          Value::String(s) => csv_field(s),
+          other => csv_field(&other.to_string()),
+        })
+        .collect();
+      csv.push_str(&record.join(","));
+      csv.push_str("\r\n");
+    }
+
+    csv
+  }
+
+  /// Serialize `items` as a JSON array of objects keyed by `export_fields`.
+  fn to_json(items: &[Self]) -> Value
+  where
+    Self: Sized,
+  {
+    Value::Array(items.iter().map(|item| Value::Object(Self::export_fields().iter().map(|f| f.to_string()).zip(item.export_values()).collect::<Map<_, _>>())).collect())
+  }
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break.
+fn csv_field(text: &str) -> String { if text.contains([',', '"', '\r', '\n']) { format!("\"{}\"", text.replace('"', "\"\"")) } else { text.to_string() } }
+
+/// Exposes one row of a tree model to the search filter.
+struct ModelRow<'a> {
+  model: &'a gtk4::TreeModel,
+  iter: &'a gtk4::TreeIter,
+  columns: &'static [(&'static str, i32)],
+  column_count: i32,
+}
+
+impl ModelRow<'_> {
+  fn value(&self, column: i32) -> Option<FieldValue> {
+    let value = self.model.get_value(self.iter, column);
+    if let Ok(v) = value.get::<String>() {
+      return Some(FieldValue::Text(v.trim().to_string()));
+    }
+    if let Ok(v) = value.get::<u64>() {
+      return Some(FieldValue::Number(v));
+    }
+    if let Ok(v) = value.get::<u32>() {
+      return Some(FieldValue::Number(v as u64));
+    }
+    None
+  }
+}
+
+impl Filterable for ModelRow<'_> {
+  fn field_values(&self) -> Vec<FieldValue> { if self.columns.is_empty() { (0..self.column_count).filter_map(|i| self.value(i)).collect() } else { self.columns.iter().filter_map(|(_, i)| self.value(*i)).collect() } }
+
+  fn field_value(&self, field: &str) -> Option<FieldValue> { self.columns.iter().find(|(name, _)| *name == field).and_then(|(_, i)| self.value(*i)) }
 }
 
 /// A reusable GTK4 ListView component, parameterized on `T: ListRow`.
@@ -17,10 +93,12 @@ pub struct GenericListView<T: ListRow> {
   pub scrolled: gtk4::ScrolledWindow,
   pub search_entry: gtk4::SearchEntry,
   pub search_bar: gtk4::SearchBar,
+  pub search_error: gtk4::Label,
   pub list_store: gtk4::ListStore,
   filter_model: gtk4::TreeModelFilter,
   sort_model: gtk4::TreeModelSort,
   row_mapper: Arc<dyn Fn(&gtk4::ListStore, &T)>,
+  items: Arc<RwLock<Vec<T>>>,
   _marker: PhantomData<T>,
 }
 
@@ -33,7 +111,13 @@ impl<T: ListRow + 'static> GenericListView<T> {
 
     // 2) Create filter/search
     let search_entry = gtk4::SearchEntry::new();
-    let search_bar = gtk4::SearchBar::builder().halign(gtk4::Align::End).valign(gtk4::Align::End).show_close_button(true).child(&search_entry).build();
+    search_entry.set_width_chars(30);
+    let search_error = gtk4::Label::builder().xalign(0.0).wrap(true).max_width_chars(40).visible(false).build();
+    search_error.add_css_class("error");
+    let search_box = gtk4::Box::new(gtk4::Orientation::Vertical, 4);
+    search_box.append(&search_entry);
+    search_box.append(&search_error);
+    let search_bar = gtk4::SearchBar::builder().halign(gtk4::Align::End).valign(gtk4::Align::End).show_close_button(true).child(&search_box).build();
 
     // 3) Pack them into a vertical container (so search_bar overlays)
     let container = gtk4::Box::new(gtk4::Orientation::Vertical, 0);
@@ -49,35 +133,51 @@ impl<T: ListRow + 'static> GenericListView<T> {
     let sort_model = gtk4::TreeModelSort::with_model(&filter_model);
     tree_view.set_model(Some(&sort_model));
 
-    // 6) Filtering function
+    // 6) Filtering function, the query is parsed once per edit and shared with the visible func
+    let filter = Arc::new(RwLock::new(Filter::default()));
     {
-      let search_entry = search_entry.downgrade();
+      let filter = filter.clone();
+      let column_count = T::column_types().len() as i32;
       filter_model.set_visible_func(move |model, iter| {
-        let Some(search_entry) = search_entry.upgrade() else { return false };
-
-        let text = search_entry.text();
-        if text.is_empty() {
+        let filter = filter.read();
+        if filter.is_empty() {
           return true;
         }
 
-        for i in 0..T::column_types().len() as i32 {
-          if let Ok(val) = model.get_value(iter, i).get::<String>() {
-            if val.to_lowercase().contains(&text.to_lowercase()) {
-              return true;
-            }
-          }
-        }
-        false
+        filter.matches(&ModelRow { model, iter, columns: T::filter_columns(), column_count })
       });
     }
 
     tree_view.set_search_entry(Some(&search_entry));
 
     {
+      let filter = filter.clone();
       let filter_model = filter_model.downgrade();
-      search_entry.connect_search_changed(move |_| {
-        if let Some(filter_model) = filter_model.upgrade() {
-          filter_model.refilter();
+      let search_error = search_error.downgrade();
+      search_entry.connect_search_changed(move |search_entry| {
+        let fields: Vec<&str> = T::filter_columns().iter().map(|(name, _)| *name).collect();
+        let parsed = Filter::parse(&search_entry.text(), &fields);
+
+        if let Some(search_error) = search_error.upgrade() {
+          match &parsed {
+            Ok(_) => {
+              search_entry.remove_css_class("error");
+              search_error.set_visible(false);
+            }
+            Err(e) => {
+              search_entry.add_css_class("error");
+              search_error.set_text(&e.to_string());
+              search_error.set_visible(true);
+            }
+          }
+        }
+
+        // Keep the last good filter while the query is being typed
+        if let Ok(parsed) = parsed {
+          *filter.write() = parsed;
+          if let Some(filter_model) = filter_model.upgrade() {
+            filter_model.refilter();
+          }
         }
       });
     }
@@ -108,10 +208,12 @@ impl<T: ListRow + 'static> GenericListView<T> {
       scrolled,
       search_entry,
       search_bar,
+      search_error,
       list_store,
       filter_model,
       sort_model,
       row_mapper,
+      items: Arc::new(RwLock::new(Vec::new())),
       _marker: PhantomData,
     }
   }
@@ -161,6 +263,42 @@ impl<T: ListRow + 'static> GenericListView<T> {
     self
   }
 
+  /// Keep rows whose `pinned_col` is set (a `true` bool or a non-empty string) above the rest,
+  /// whichever column the list is sorted by and in either direction.
+  pub fn set_pinned_column(&mut self, pinned_col: i32) -> &mut Self {
+    fn is_pinned(model: &impl IsA<gtk4::TreeModel>, iter: &gtk4::TreeIter, col: i32) -> bool {
+      let value = model.get_value(iter, col);
+      value.get::<bool>().unwrap_or(false) || value.get::<String>().is_ok_and(|s| !s.trim().is_empty())
+    }
+
+    fn compare(a: &gtk4::glib::Value, b: &gtk4::glib::Value) -> std::cmp::Ordering {
+      if let (Ok(a), Ok(b)) = (a.get::<String>(), b.get::<String>()) {
+        return a.trim().to_lowercase().cmp(&b.trim().to_lowercase());
+      }
+      if let (Ok(a), Ok(b)) = (a.get::<u64>(), b.get::<u64>()) {
+        return a.cmp(&b);
+      }
+      if let (Ok(a), Ok(b)) = (a.get::<bool>(), b.get::<bool>()) {
+        return a.cmp(&b);
+      }
+      std::cmp::Ordering::Equal
+    }
+
+    for col in 0..T::column_types().len() as i32 {
+      let sort_model = self.sort_model.downgrade();
+      self.sort_model.set_sort_func(gtk4::SortColumn::Index(col as u32), move |model, a, b| {
+        // TreeModelSort reverses the whole result when descending, so flip the pin order to keep it on top
+        let descending = sort_model.upgrade().and_then(|m| m.sort_column_id()).is_some_and(|(_, order)| order == gtk4::SortType::Descending);
+        let pin_order = is_pinned(model, b, pinned_col).cmp(&is_pinned(model, a, pinned_col));
+        let pin_order = if descending { pin_order.reverse() } else { pin_order };
+
+        pin_order.then_with(|| compare(&model.get_value(a, col), &model.get_value(b, col))).into()
+      });
+    }
+
+    self
+  }
+
   /// Provide the function that maps `&T` → store-rows.
   /// Must be called before `set_items`.
   pub fn set_row_mapper<F>(&mut self, f: F) -> &mut Self
@@ -172,13 +310,99 @@ impl<T: ListRow + 'static> GenericListView<T> {
   }
 
   /// Given a slice of `T`, clear+populate the store.
-  pub fn set_items(&self, items: &[T]) {
+  pub fn set_items(&self, items: &[T])
+  where
+    T: Clone,
+  {
+    *self.items.write() = items.to_vec();
     self.list_store.clear();
     for item in items {
       (self.row_mapper)(&self.list_store, item);
     }
   }
 
+  /// Add `items` after the ones already there, for lists that only grow.
+  pub fn append_items(&self, items: &[T])
+  where
+    T: Clone,
+  {
+    self.items.write().extend_from_slice(items);
+    for item in items {
+      (self.row_mapper)(&self.list_store, item);
+    }
+  }
+
+  /// Every item last passed to `set_items`, filtered or not.
+  pub fn items(&self) -> Vec<T>
+  where
+    T: Clone,
+  {
+    self.items.read().clone()
+  }
+
+  /// The items currently shown, after filtering, in display order.
+  pub fn visible_items(&self) -> Vec<T>
+  where
+    T: Clone,
+  {
+    let items = self.items.read();
+    let mut visible = Vec::new();
+
+    if let Some(sort_iter) = self.sort_model.iter_first() {
+      loop {
+        let filter_iter = self.sort_model.convert_iter_to_child_iter(&sort_iter);
+        let list_store_iter = self.filter_model.convert_iter_to_child_iter(&filter_iter);
+        // Rows are appended in `set_items` order, so the store index is the item index
+        if let Some(item) = self.list_store.path(&list_store_iter).indices().first().and_then(|i| items.get(*i as usize)) {
+          visible.push(item.clone());
+        }
+
+        if !self.sort_model.iter_next(&sort_iter) {
+          break;
+        }
+      }
+    }
+
+    visible
+  }
+
+  /// The items behind the selected rows.
+  pub fn selected_items(&self) -> Vec<T>
+  where
+    T: Clone,
+  {
+    let items = self.items.read();
+    self.get_selected().iter().filter_map(|iter| self.list_store.path(iter).indices().first().and_then(|i| items.get(*i as usize)).cloned()).collect()
+  }
+
+  /// Select and scroll to the first visible row whose item matches, returns whether there was one.
+  pub fn select_where(&self, matches: impl Fn(&T) -> bool) -> bool {
+    let Some(index) = self.items.read().iter().position(matches) else { return false };
+    let store_path = gtk4::TreePath::from_indices(&[index as i32]);
+    let Some(path) = self.filter_model.convert_child_path_to_path(&store_path).and_then(|p| self.sort_model.convert_child_path_to_path(&p)) else { return false };
+
+    self.tree_view.selection().unselect_all();
+    self.tree_view.selection().select_path(&path);
+    self.tree_view.scroll_to_cell(Some(&path), None::<&gtk4::TreeViewColumn>, false, 0.0, 0.0);
+    true
+  }
+
+  /// Replace the items like `set_items`, keeping the rows at the selected positions selected, for
+  /// lists that are refreshed on a timer.
+  pub fn refresh_items(&self, items: &[T])
+  where
+    T: Clone,
+  {
+    let selected = self.get_selected().iter().filter_map(|iter| self.list_store.path(iter).indices().first().copied()).collect::<Vec<_>>();
+    self.set_items(items);
+    for index in selected.into_iter().filter(|i| (*i as usize) < items.len()) {
+      let store_path = gtk4::TreePath::from_indices(&[index]);
+      if let Some(path) = self.filter_model.convert_child_path_to_path(&store_path).and_then(|p| self.sort_model.convert_child_path_to_path(&p)) {
+        self.tree_view.selection().select_path(&path);
+      }
+    }
+  }
+
   pub fn get_selected(&self) -> Vec<gtk4::TreeIter> {
     let selection = self.tree_view.selection();
     let (paths, _) = selection.selected_rows();
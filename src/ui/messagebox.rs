@@ -3,3 +3,13 @@ pub fn message_box(window: &gtk4::ApplicationWindow, message: impl AsRef<str>, d
   let alert = gtk4::AlertDialog::builder().modal(true).message(message.as_ref()).detail(detail.as_ref()).buttons(buttons).default_button(1).cancel_button(0).build();
   alert.choose(Some(window), None::<&gtk4::gio::Cancellable>, move |res| if res == Ok(1) {});
 }
+
+/// Ask before doing something, `on_confirm` runs when the user picks `confirm_label`.
+pub fn confirm_box(window: &gtk4::ApplicationWindow, message: impl AsRef<str>, detail: impl AsRef<str>, confirm_label: &str, on_confirm: impl FnOnce() + 'static) {
+  let alert = gtk4::AlertDialog::builder().modal(true).message(message.as_ref()).detail(detail.as_ref()).buttons(["Cancel", confirm_label]).default_button(1).cancel_button(0).build();
+  alert.choose(Some(window), None::<&gtk4::gio::Cancellable>, move |res| {
+    if res == Ok(1) {
+      on_confirm();
+    }
+  });
+}
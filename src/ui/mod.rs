@@ -1,2 +1,12 @@
+pub(crate) mod consoleview;
+pub(crate) mod debugview;
+pub(crate) mod dragdrop;
+pub(crate) mod export;
+pub(crate) mod hexview;
 pub(crate) mod listview;
 pub(crate) mod messagebox;
+pub(crate) mod pointerview;
+pub(crate) mod regionview;
+pub(crate) mod scanview;
+pub(crate) mod threadview;
+pub(crate) mod toast;
@@ -0,0 +1,220 @@
+use crate::{AppState, MarginAll, logic::{memory::ProcessMemory, pointer::{PointerChain, ValueType}}, ui::{hexview::hex_view, listview::{GenericListView, ListRow}}};
+use gtk4::prelude::*;
+use parking_lot::RwLock;
+use std::{cell::RefCell, rc::Rc, sync::Arc, time::Duration};
+
+/// How often the watch list is read again until changed, in ms.
+const DEFAULT_INTERVAL_MS: f64 = 500.0;
+
+/// A chain with what it resolved to on the last refresh.
+#[derive(Debug, Clone)]
+pub struct PointerWatch {
+  pub chain: PointerChain,
+  /// `None` when the chain couldn't be followed.
+  pub address: Option<u64>,
+  /// The value, or why it couldn't be read.
+  pub value: String,
+}
+
+impl PointerWatch {
+  fn resolve(memory: &ProcessMemory, chain: &PointerChain) -> Self {
+    match chain.resolve(memory) {
+      Ok(address) => Self {
+        chain: chain.clone(),
+        address: Some(address),
+        value: chain.value_type.read(memory, address).unwrap_or_else(|e| e),
+      },
+      Err(e) => Self { chain: chain.clone(), address: None, value: e },
+    }
+  }
+}
+
+impl ListRow for PointerWatch {
+  fn column_types() -> &'static [gtk4::glib::Type] { &[gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING] }
+  fn fill_row(store: &gtk4::ListStore, w: &Self) {
+    let address = w.address.map(|a| format!("{:016X}", a)).unwrap_or_default();
+    store.insert_with_values(None, &[(0, &w.chain.name), (1, &w.chain.path()), (2, &w.chain.value_type.to_string()), (3, &address), (4, &w.value)]);
+  }
+  fn filter_columns() -> &'static [(&'static str, i32)] { &[("name", 0), ("chain", 1), ("type", 2), ("address", 3), ("value", 4)] }
+  fn export_fields() -> &'static [&'static str] { &["name", "chain", "type", "address", "value"] }
+  fn export_values(&self) -> Vec<serde_json::Value> { vec![self.chain.name.clone().into(), self.chain.path().into(), self.chain.value_type.to_string().into(), self.address.into(), self.value.clone().into()] }
+}
+
+/// Open a window to watch values at the end of pointer chains in `process_id`, read again on an
+/// interval. Chains are saved in the config under a profile, which starts out named after the process.
+pub fn pointer_view(parent: &gtk4::ApplicationWindow, aps: &Arc<RwLock<AppState>>, name: &str, process_id: u32) -> Result<(), String> {
+  let memory = Rc::new(ProcessMemory::open(process_id)?);
+  let window = gtk4::Window::builder().title(format!("Pointers - {} - {:#X}", name, process_id)).transient_for(parent).default_width(860).default_height(480).build();
+
+  let profile_entry = gtk4::Entry::builder().text(name).placeholder_text("Profile").hexpand(true).build();
+  let load_btn = gtk4::Button::with_label("Load");
+  load_btn.set_tooltip_text(Some("Replace the list with the chains saved under this profile"));
+  let save_btn = gtk4::Button::with_label("Save");
+  save_btn.set_tooltip_text(Some("Save the list under this profile"));
+  let profile_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+  profile_box.append(&gtk4::Label::new(Some("Profile")));
+  profile_box.append(&profile_entry);
+  profile_box.append(&load_btn);
+  profile_box.append(&save_btn);
+
+  let name_entry = gtk4::Entry::builder().placeholder_text("Name").width_chars(16).build();
+  let chain_entry = gtk4::Entry::builder().placeholder_text("Chain, e.g. game.exe+0x1A2B3C -> +0x10 -> +0x48").hexpand(true).build();
+  chain_entry.add_css_class("monospace");
+  let type_dropdown = gtk4::DropDown::from_strings(&ValueType::ALL.map(|t| t.to_string()).iter().map(String::as_str).collect::<Vec<_>>());
+  let add_btn = gtk4::Button::with_label("Add");
+  let add_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+  add_box.append(&name_entry);
+  add_box.append(&chain_entry);
+  add_box.append(&type_dropdown);
+  add_box.append(&add_btn);
+
+  let mut listview = GenericListView::<PointerWatch>::new();
+  let alignment = gtk4::pango::Alignment::Left;
+  listview.add_text_column("Name", 0, None, alignment).add_text_column("Chain", 1, Some(320), alignment).add_text_column("Type", 2, None, alignment).add_text_column("Address", 3, None, alignment).add_text_column("Value", 4, Some(240), alignment).set_row_mapper(PointerWatch::fill_row);
+
+  let status = gtk4::Label::builder().xalign(0.0).wrap(true).hexpand(true).build();
+  let interval_spin = gtk4::SpinButton::with_range(100.0, 60_000.0, 100.0);
+  interval_spin.set_value(DEFAULT_INTERVAL_MS);
+  interval_spin.set_tooltip_text(Some("Refresh interval in ms"));
+  let remove_btn = gtk4::Button::with_label("Remove");
+  let memory_btn = gtk4::Button::with_label("Memory");
+  memory_btn.set_tooltip_text(Some("Open the selected chain's address in the memory view"));
+  let button_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+  button_box.append(&status);
+  button_box.append(&gtk4::Label::new(Some("Refresh every")));
+  button_box.append(&interval_spin);
+  button_box.append(&remove_btn);
+  button_box.append(&memory_btn);
+
+  // The chains being watched, in the order they were added
+  let chains = Rc::new(RefCell::new(aps.read().config.pointer_chains(name).to_vec()));
+  let refresh = {
+    let listview = listview.clone();
+    let chains = chains.clone();
+    move || listview.refresh_items(&chains.borrow().iter().map(|chain| PointerWatch::resolve(&memory, chain)).collect::<Vec<_>>())
+  };
+  refresh();
+
+  // Restarted whenever the interval changes, removed with the window
+  let source = Rc::new(RefCell::new(None::<gtk4::glib::SourceId>));
+  let start_timer = {
+    let source = source.clone();
+    let refresh = refresh.clone();
+    move |interval_ms: u64| {
+      if let Some(old) = source.borrow_mut().take() {
+        old.remove();
+      }
+      let refresh = refresh.clone();
+      *source.borrow_mut() = Some(gtk4::glib::timeout_add_local(Duration::from_millis(interval_ms), move || {
+        refresh();
+        gtk4::glib::ControlFlow::Continue
+      }));
+    }
+  };
+  start_timer(DEFAULT_INTERVAL_MS as u64);
+  interval_spin.connect_value_changed(move |spin| start_timer(spin.value() as u64));
+  window.connect_close_request(move |_| {
+    if let Some(source) = source.borrow_mut().take() {
+      source.remove();
+    }
+    gtk4::glib::Propagation::Proceed
+  });
+
+  {
+    let chains = chains.clone();
+    let refresh = refresh.clone();
+    let status = status.clone();
+    let chain_entry_c = chain_entry.clone();
+    let add = move || {
+      let value_type = ValueType::ALL.get(type_dropdown.selected() as usize).copied().unwrap_or_default();
+      let text = chain_entry_c.text();
+      let label = Some(name_entry.text().trim().to_string()).filter(|n| !n.is_empty()).unwrap_or_else(|| text.trim().to_string());
+      match PointerChain::parse(&label, &text, value_type) {
+        Ok(chain) => {
+          chains.borrow_mut().push(chain);
+          name_entry.set_text("");
+          chain_entry_c.set_text("");
+          status.set_label("");
+          refresh();
+        }
+        Err(e) => status.set_label(&e),
+      }
+    };
+    let add_c = add.clone();
+    add_btn.connect_clicked(move |_| add_c());
+    chain_entry.connect_activate(move |_| add());
+  }
+  {
+    let listview = listview.clone();
+    let chains = chains.clone();
+    let refresh = refresh.clone();
+    let status = status.clone();
+    remove_btn.connect_clicked(move |_| {
+      let selected = listview.selected_items();
+      if selected.is_empty() {
+        status.set_label("Select a chain first");
+        return;
+      }
+      for watch in selected {
+        let mut chains = chains.borrow_mut();
+        if let Some(index) = chains.iter().position(|c| *c == watch.chain) {
+          chains.remove(index);
+        }
+      }
+      refresh();
+    });
+  }
+  {
+    let listview = listview.clone();
+    let parent = parent.clone();
+    let status = status.clone();
+    let name = name.to_string();
+    memory_btn.connect_clicked(move |_| match listview.selected_items().into_iter().next() {
+      Some(PointerWatch { address: Some(address), .. }) => {
+        if let Err(e) = hex_view(&parent, &name, process_id, address) {
+          status.set_label(&e);
+        }
+      }
+      Some(_) => status.set_label("The selected chain doesn't resolve"),
+      None => status.set_label("Select a chain first"),
+    });
+  }
+  {
+    let aps = aps.clone();
+    let chains = chains.clone();
+    let profile_entry = profile_entry.clone();
+    let status = status.clone();
+    load_btn.connect_clicked(move |_| {
+      let profile = profile_entry.text().trim().to_string();
+      *chains.borrow_mut() = aps.read().config.pointer_chains(&profile).to_vec();
+      status.set_label(&format!("Loaded {} chains from {}", chains.borrow().len(), profile));
+      refresh();
+    });
+  }
+  {
+    let aps = aps.clone();
+    save_btn.connect_clicked(move |_| {
+      let profile = profile_entry.text().trim().to_string();
+      if profile.is_empty() {
+        status.set_label("Name the profile first");
+        return;
+      }
+      let mut state = aps.write();
+      state.config.set_pointer_chains(&profile, chains.borrow().clone());
+      match state.config.save() {
+        Ok(()) => status.set_label(&format!("Saved {} chains as {}", chains.borrow().len(), profile)),
+        Err(e) => status.set_label(&format!("Failed to save the config, error: {}", e)),
+      }
+    });
+  }
+
+  let content = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+  content.set_margin_all(10);
+  content.append(&profile_box);
+  content.append(&add_box);
+  content.append(&listview.container);
+  content.append(&button_box);
+  window.set_child(Some(&content));
+  window.present();
+  Ok(())
+}
@@ -0,0 +1,101 @@
+use crate::{MarginAll, logic::regions::{self, MemoryRegion, RegionState}, ui::{hexview::hex_view, listview::{GenericListView, ListRow}}};
+use gtk4::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+impl ListRow for MemoryRegion {
+  fn column_types() -> &'static [gtk4::glib::Type] { &[gtk4::glib::Type::STRING, gtk4::glib::Type::U64, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING] }
+  fn fill_row(store: &gtk4::ListStore, r: &Self) {
+    let kind = r.kind.map(|k| k.to_string()).unwrap_or_default();
+    store.insert_with_values(None, &[(0, &format!("{:016X}", r.base)), (1, &r.size), (2, &r.protection.to_string()), (3, &r.state.to_string()), (4, &kind), (5, &r.owner)]);
+  }
+  fn filter_columns() -> &'static [(&'static str, i32)] { &[("base", 0), ("size", 1), ("protection", 2), ("state", 3), ("type", 4), ("owner", 5)] }
+  fn export_fields() -> &'static [&'static str] { &["base", "size", "protection", "state", "type", "owner"] }
+  fn export_values(&self) -> Vec<serde_json::Value> { vec![self.base.into(), self.size.into(), self.protection.to_string().into(), self.state.to_string().into(), self.kind.map(|k| k.to_string()).into(), self.owner.clone().into()] }
+}
+
+/// Open a window listing the memory regions of `process_id` with their protection, state, type and
+/// owner, to see where to write and what runs. Search it like the process list, e.g. `protection:x`.
+pub fn region_view(parent: &gtk4::ApplicationWindow, name: &str, process_id: u32) -> Result<(), String> {
+  let regions = regions::query(process_id)?;
+  let window = gtk4::Window::builder().title(format!("Memory regions - {} - {:#X}", name, process_id)).transient_for(parent).default_width(860).default_height(560).build();
+
+  let mut listview = GenericListView::<MemoryRegion>::new();
+  let alignment = gtk4::pango::Alignment::Left;
+  listview
+    .add_text_column("Base", 0, None, alignment)
+    .add_text_column("Size", 1, None, alignment)
+    .add_text_column("Protection", 2, None, alignment)
+    .add_text_column("State", 3, None, alignment)
+    .add_text_column("Type", 4, None, alignment)
+    .add_text_column("Owner", 5, Some(400), alignment)
+    .enable_sorting(0, gtk4::SortType::Ascending)
+    .set_row_mapper(MemoryRegion::fill_row);
+
+  // Free regions are most of a 64-bit address space and rarely what anyone is after
+  let hide_free = gtk4::CheckButton::builder().label("Hide free").active(true).build();
+  let status = gtk4::Label::builder().xalign(0.0).wrap(true).hexpand(true).build();
+  // Everything last queried, what is shown depends on the checkbox
+  let all = Rc::new(RefCell::new(regions));
+  let show = {
+    let listview = listview.clone();
+    let hide_free = hide_free.clone();
+    let status = status.clone();
+    let all = all.clone();
+    move || {
+      let regions = all.borrow();
+      let shown = regions.iter().filter(|r| !hide_free.is_active() || r.state != RegionState::Free).cloned().collect::<Vec<_>>();
+      listview.set_items(&shown);
+      status.set_label(&format!("{} regions, {} MiB committed", shown.len(), regions.iter().filter(|r| r.state == RegionState::Committed).map(|r| r.size).sum::<u64>() >> 20));
+    }
+  };
+  show();
+
+  {
+    let show = show.clone();
+    hide_free.connect_toggled(move |_| show());
+  }
+
+  let refresh_btn = gtk4::Button::with_label("Refresh");
+  {
+    let status = status.clone();
+    refresh_btn.connect_clicked(move |_| match regions::query(process_id) {
+      Ok(regions) => {
+        *all.borrow_mut() = regions;
+        show();
+      }
+      Err(e) => status.set_label(&e),
+    });
+  }
+
+  let memory_btn = gtk4::Button::with_label("Memory");
+  memory_btn.set_tooltip_text(Some("Open the selected region in the memory view"));
+  {
+    let listview = listview.clone();
+    let parent = parent.clone();
+    let status = status.clone();
+    let name = name.to_string();
+    memory_btn.connect_clicked(move |_| {
+      let Some(region) = listview.selected_items().into_iter().next() else {
+        status.set_label("Select a region first");
+        return;
+      };
+      if let Err(e) = hex_view(&parent, &name, process_id, region.base) {
+        status.set_label(&e);
+      }
+    });
+  }
+
+  let button_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+  button_box.append(&status);
+  button_box.append(&hide_free);
+  button_box.append(&memory_btn);
+  button_box.append(&refresh_btn);
+
+  let content = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+  content.set_margin_all(10);
+  content.append(&listview.container);
+  content.append(&button_box);
+  window.set_child(Some(&content));
+  window.present();
+  Ok(())
+}
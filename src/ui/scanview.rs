@@ -0,0 +1,219 @@
+use crate::{MarginAll, logic::{memory::ProcessMemory, pattern::{self, Pattern}}, ui::{hexview::hex_view, listview::{GenericListView, ListRow}}};
+use gtk4::prelude::*;
+use std::{sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc::TryRecvError}, time::{Duration, Instant}};
+
+/// Matches past this many are dropped, a signature that common isn't worth listing.
+const MAX_MATCHES: usize = 10_000;
+
+/// One match of a pattern scan.
+#[derive(Debug, Clone)]
+pub struct ScanMatch {
+  pub address: u64,
+  /// `module+offset` when the scan was limited to a module.
+  pub location: String,
+  /// The bytes that matched, as hex.
+  pub bytes: String,
+  /// Where the RIP-relative operand of the match points, when asked for.
+  pub target: Option<u64>,
+}
+
+impl ListRow for ScanMatch {
+  fn column_types() -> &'static [gtk4::glib::Type] { &[gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING] }
+  fn fill_row(store: &gtk4::ListStore, m: &Self) {
+    let target = m.target.map(|t| format!("{:016X}", t)).unwrap_or_default();
+    store.insert_with_values(None, &[(0, &format!("{:016X}", m.address)), (1, &m.location), (2, &m.bytes), (3, &target)]);
+  }
+  fn filter_columns() -> &'static [(&'static str, i32)] { &[("address", 0), ("location", 1), ("bytes", 2), ("target", 3)] }
+  fn export_fields() -> &'static [&'static str] { &["address", "location", "bytes", "target"] }
+  fn export_values(&self) -> Vec<serde_json::Value> { vec![self.address.into(), self.location.clone().into(), self.bytes.clone().into(), self.target.into()] }
+}
+
+/// What to scan and how to read the matches.
+#[derive(Debug, Clone)]
+struct ScanRequest {
+  process_id: u32,
+  pattern: Pattern,
+  /// Only this module's image, all readable memory when `None`.
+  module: Option<String>,
+  /// Offset of the displacement in the matched instruction and the instruction's length.
+  rip_relative: Option<(u64, u64)>,
+}
+
+/// Scan on the calling thread, the process is opened here as its handle can't move between threads.
+fn run_scan(request: &ScanRequest, cancelled: &AtomicBool) -> Result<Vec<ScanMatch>, String> {
+  let memory = ProcessMemory::open(request.process_id)?;
+  let (regions, module_start) = match &request.module {
+    Some(module) => {
+      let range = memory.module_range(module)?;
+      (vec![range.clone()], Some(range.start))
+    }
+    None => (memory.readable_regions()?, None),
+  };
+
+  let addresses = pattern::scan(&memory, &regions, &request.pattern, MAX_MATCHES, cancelled);
+  Ok(
+    addresses
+      .into_iter()
+      .map(|address| {
+        let location = match (&request.module, module_start) {
+          (Some(module), Some(start)) => format!("{}+{:#X}", module, address - start),
+          _ => String::new(),
+        };
+        let bytes = memory.read_lossy(address, request.pattern.len()).iter().map(|b| b.map_or(String::from("??"), |b| format!("{:02X}", b))).collect::<Vec<_>>().join(" ");
+        let target = request.rip_relative.and_then(|(offset, length)| pattern::resolve_rip_relative(&memory, address, offset, length).ok());
+        ScanMatch { address, location, bytes, target }
+      })
+      .collect(),
+  )
+}
+
+/// The pattern typed in, either a signature with wildcards or, when `mask` is given, plain bytes such as
+/// `48 8B 05 00` or `\x48\x8B\x05\x00` with a code-style mask.
+fn parse_pattern(text: &str, mask: &str) -> Result<Pattern, String> {
+  if mask.is_empty() {
+    return Pattern::parse(text);
+  }
+  let hex = text.replace("\\x", "").split_whitespace().collect::<String>();
+  if hex.len() % 2 != 0 {
+    return Err(format!("{} is not a whole number of bytes", text));
+  }
+  let bytes = (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("{} is not a hex byte", &hex[i..i + 2]))).collect::<Result<Vec<u8>, String>>()?;
+  Pattern::from_mask(&bytes, mask)
+}
+
+/// Open a window to scan the memory of `process_id` for a byte signature, with wildcards, and list
+/// every match. The scan runs in the background and can be cancelled.
+pub fn scan_view(parent: &gtk4::ApplicationWindow, name: &str, process_id: u32) {
+  let window = gtk4::Window::builder().title(format!("Pattern scan - {} - {:#X}", name, process_id)).transient_for(parent).default_width(760).default_height(520).build();
+
+  let pattern_entry = gtk4::Entry::builder().placeholder_text("Pattern, e.g. 48 8B 05 ?? ?? ?? ?? 48 85 C0").hexpand(true).build();
+  pattern_entry.add_css_class("monospace");
+  let mask_entry = gtk4::Entry::builder().placeholder_text("Mask, e.g. xxx????xxx. Leave empty for a pattern with ?? wildcards").hexpand(true).build();
+  mask_entry.add_css_class("monospace");
+  let module_entry = gtk4::Entry::builder().placeholder_text("Module, e.g. game.exe. All readable memory when empty").hexpand(true).build();
+
+  // `mov rax, [rip+disp32]` has its displacement 3 bytes in and is 7 long
+  let rip_check = gtk4::CheckButton::with_label("Resolve RIP-relative operand, displacement at");
+  let offset_spin = gtk4::SpinButton::with_range(0.0, 15.0, 1.0);
+  offset_spin.set_value(3.0);
+  let length_label = gtk4::Label::new(Some("instruction length"));
+  let length_spin = gtk4::SpinButton::with_range(1.0, 15.0, 1.0);
+  length_spin.set_value(7.0);
+  let rip_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+  rip_box.append(&rip_check);
+  rip_box.append(&offset_spin);
+  rip_box.append(&length_label);
+  rip_box.append(&length_spin);
+
+  let scan_btn = gtk4::Button::with_label("Scan");
+  let cancel_btn = gtk4::Button::with_label("Cancel");
+  cancel_btn.set_visible(false);
+  let memory_btn = gtk4::Button::with_label("Memory");
+  memory_btn.set_tooltip_text(Some("Open the selected match, or where its operand points, in the memory view"));
+  let status = gtk4::Label::builder().xalign(0.0).wrap(true).hexpand(true).build();
+  let button_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+  button_box.append(&status);
+  button_box.append(&memory_btn);
+  button_box.append(&cancel_btn);
+  button_box.append(&scan_btn);
+
+  let mut results = GenericListView::<ScanMatch>::new();
+  let alignment = gtk4::pango::Alignment::Left;
+  results.add_text_column("Address", 0, None, alignment).add_text_column("Location", 1, None, alignment).add_text_column("Bytes", 2, Some(260), alignment).add_text_column("Target", 3, None, alignment).enable_sorting(0, gtk4::SortType::Ascending).set_row_mapper(ScanMatch::fill_row);
+
+  let cancelled = Arc::new(AtomicBool::new(false));
+  {
+    let cancelled = cancelled.clone();
+    cancel_btn.connect_clicked(move |_| cancelled.store(true, Ordering::Relaxed));
+  }
+  {
+    let results = results.clone();
+    let status = status.clone();
+    let scan_btn_c = scan_btn.clone();
+    let cancel_btn = cancel_btn.clone();
+    let pattern_entry = pattern_entry.clone();
+    let mask_entry = mask_entry.clone();
+    let module_entry = module_entry.clone();
+    scan_btn.connect_clicked(move |_| {
+      let pattern = match parse_pattern(&pattern_entry.text(), mask_entry.text().trim()) {
+        Ok(pattern) => pattern,
+        Err(e) => {
+          status.set_label(&e);
+          return;
+        }
+      };
+      let module = Some(module_entry.text().trim().to_string()).filter(|m| !m.is_empty());
+      let rip_relative = rip_check.is_active().then(|| (offset_spin.value() as u64, length_spin.value() as u64));
+      let request = ScanRequest { process_id, pattern, module, rip_relative };
+
+      cancelled.store(false, Ordering::Relaxed);
+      scan_btn_c.set_sensitive(false);
+      cancel_btn.set_visible(true);
+      status.set_label("Scanning...");
+
+      let started = Instant::now();
+      let (sender, receiver) = std::sync::mpsc::channel();
+      {
+        let cancelled = cancelled.clone();
+        std::thread::spawn(move || {
+          let _ = sender.send(run_scan(&request, &cancelled));
+        });
+      }
+
+      let results = results.clone();
+      let status = status.clone();
+      let scan_btn = scan_btn_c.clone();
+      let cancel_btn = cancel_btn.clone();
+      let cancelled = cancelled.clone();
+      gtk4::glib::timeout_add_local(Duration::from_millis(50), move || {
+        let result = match receiver.try_recv() {
+          Ok(v) => v,
+          Err(TryRecvError::Empty) => return gtk4::glib::ControlFlow::Continue,
+          Err(TryRecvError::Disconnected) => Err(String::from("The scan thread panicked")),
+        };
+
+        scan_btn.set_sensitive(true);
+        cancel_btn.set_visible(false);
+        match result {
+          Ok(matches) => {
+            results.set_items(&matches);
+            let note = match matches.len() {
+              _ if cancelled.load(Ordering::Relaxed) => ", cancelled",
+              MAX_MATCHES => ", stopped at the limit",
+              _ => "",
+            };
+            status.set_label(&format!("{} matches in {} ms{}", matches.len(), started.elapsed().as_millis(), note));
+          }
+          Err(e) => status.set_label(&e),
+        }
+        gtk4::glib::ControlFlow::Break
+      });
+    });
+  }
+  {
+    let results = results.clone();
+    let parent = parent.clone();
+    let status = status.clone();
+    let name = name.to_string();
+    memory_btn.connect_clicked(move |_| {
+      let Some(selected) = results.selected_items().into_iter().next() else {
+        status.set_label("Select a match first");
+        return;
+      };
+      if let Err(e) = hex_view(&parent, &name, process_id, selected.target.unwrap_or(selected.address)) {
+        status.set_label(&e);
+      }
+    });
+  }
+
+  let content = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+  content.set_margin_all(10);
+  content.append(&pattern_entry);
+  content.append(&mask_entry);
+  content.append(&module_entry);
+  content.append(&rip_box);
+  content.append(&results.container);
+  content.append(&button_box);
+  window.set_child(Some(&content));
+  window.present();
+}
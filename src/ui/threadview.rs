@@ -0,0 +1,107 @@
+use crate::{MarginAll, logic::threads::{self, ThreadInfo}, ui::listview::{GenericListView, ListRow}};
+use gtk4::prelude::*;
+
+impl ListRow for ThreadInfo {
+  fn column_types() -> &'static [gtk4::glib::Type] { &[gtk4::glib::Type::U32, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING, gtk4::glib::Type::STRING] }
+  fn fill_row(store: &gtk4::ListStore, t: &Self) {
+    let start = match t.start_address {
+      Some(_) if !t.start_location.is_empty() => t.start_location.clone(),
+      Some(address) => format!("{:016X}", address),
+      None => String::new(),
+    };
+    store.insert_with_values(None, &[(0, &t.thread_id), (1, &t.name), (2, &start), (3, &t.state.to_string()), (4, &t.priority.to_string())]);
+  }
+  fn filter_columns() -> &'static [(&'static str, i32)] { &[("tid", 0), ("name", 1), ("start", 2), ("state", 3), ("priority", 4)] }
+  fn export_fields() -> &'static [&'static str] { &["tid", "name", "start_address", "start", "state", "priority"] }
+  fn export_values(&self) -> Vec<serde_json::Value> { vec![self.thread_id.into(), self.name.clone().into(), self.start_address.into(), self.start_location.clone().into(), self.state.to_string().into(), self.priority.into()] }
+}
+
+/// Open a window listing the threads of `process_id` with their start address, state and priority,
+/// where threads, or the whole process, can be suspended and resumed.
+pub fn thread_view(parent: &gtk4::ApplicationWindow, name: &str, process_id: u32) -> Result<(), String> {
+  let threads = threads::query(process_id)?;
+  let window = gtk4::Window::builder().title(format!("Threads - {} - {:#X}", name, process_id)).transient_for(parent).default_width(760).default_height(480).build();
+
+  let mut listview = GenericListView::<ThreadInfo>::new();
+  let alignment = gtk4::pango::Alignment::Left;
+  listview
+    .add_text_column("TID", 0, None, alignment)
+    .add_text_column("Name", 1, None, alignment)
+    .add_text_column("Start", 2, Some(300), alignment)
+    .add_text_column("State", 3, None, alignment)
+    .add_text_column("Priority", 4, None, alignment)
+    .enable_sorting(0, gtk4::SortType::Ascending)
+    .set_row_mapper(ThreadInfo::fill_row);
+  listview.set_items(&threads);
+
+  let status = gtk4::Label::builder().xalign(0.0).wrap(true).hexpand(true).label(format!("{} threads", threads.len())).build();
+  let refresh = {
+    let listview = listview.clone();
+    let status = status.clone();
+    move || match threads::query(process_id) {
+      Ok(threads) => {
+        listview.refresh_items(&threads);
+        status.set_label(&format!("{} threads", threads.len()));
+      }
+      Err(e) => status.set_label(&e),
+    }
+  };
+
+  let suspend_btn = gtk4::Button::with_label("Suspend");
+  suspend_btn.set_tooltip_text(Some("Suspend the selected threads"));
+  let resume_btn = gtk4::Button::with_label("Resume");
+  resume_btn.set_tooltip_text(Some("Resume the selected threads"));
+  for (button, action, verb) in [(&suspend_btn, threads::suspend_thread as fn(u32, u32) -> Result<(), String>, "Suspended"), (&resume_btn, threads::resume_thread, "Resumed")] {
+    let listview = listview.clone();
+    let status = status.clone();
+    let refresh = refresh.clone();
+    button.connect_clicked(move |_| {
+      let selected = listview.selected_items();
+      if selected.is_empty() {
+        status.set_label("Select a thread first");
+        return;
+      }
+      let errors = selected.iter().filter_map(|t| action(process_id, t.thread_id).err()).collect::<Vec<_>>();
+      refresh();
+      if errors.is_empty() {
+        status.set_label(&format!("{} {} threads", verb, selected.len()));
+      } else {
+        status.set_label(&errors.join("\n"));
+      }
+    });
+  }
+
+  let suspend_all_btn = gtk4::Button::with_label("Suspend process");
+  let resume_all_btn = gtk4::Button::with_label("Resume process");
+  for (button, action, verb) in [(&suspend_all_btn, threads::suspend_process as fn(u32) -> Result<(), String>, "Suspended"), (&resume_all_btn, threads::resume_process, "Resumed")] {
+    let status = status.clone();
+    let refresh = refresh.clone();
+    button.connect_clicked(move |_| {
+      let result = action(process_id);
+      refresh();
+      match result {
+        Ok(()) => status.set_label(&format!("{} the process", verb)),
+        Err(e) => status.set_label(&e),
+      }
+    });
+  }
+
+  let refresh_btn = gtk4::Button::with_label("Refresh");
+  refresh_btn.connect_clicked(move |_| refresh());
+
+  let button_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+  button_box.append(&status);
+  button_box.append(&suspend_btn);
+  button_box.append(&resume_btn);
+  button_box.append(&suspend_all_btn);
+  button_box.append(&resume_all_btn);
+  button_box.append(&refresh_btn);
+
+  let content = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+  content.set_margin_all(10);
+  content.append(&listview.container);
+  content.append(&button_box);
+  window.set_child(Some(&content));
+  window.present();
+  Ok(())
+}
@@ -0,0 +1,17 @@
+use gtk4::prelude::*;
+
+/// Show `message` at the bottom of `overlay` for a few seconds, styled by `.app_toast_box`.
+pub fn toast(overlay: &gtk4::Overlay, message: impl AsRef<str>) {
+  let label = gtk4::Label::builder().label(message.as_ref()).wrap(true).build();
+  let toast_box = gtk4::Box::builder().halign(gtk4::Align::Center).valign(gtk4::Align::End).can_target(false).build();
+  toast_box.add_css_class("app_toast_box");
+  toast_box.append(&label);
+  overlay.add_overlay(&toast_box);
+
+  let overlay = overlay.downgrade();
+  gtk4::glib::timeout_add_local_once(std::time::Duration::from_secs(3), move || {
+    if let Some(overlay) = overlay.upgrade() {
+      overlay.remove_overlay(&toast_box);
+    }
+  });
+}
@@ -0,0 +1,41 @@
+# Source of plain_x64.dll, reflective_x64.s without the ReflectiveLoader export:
+#   llvm-mc -filetype=obj -triple=x86_64-pc-windows-msvc -x86-asm-syntax=intel plain_x64.s -o plain_x64.obj
+#   rust-lld -flavor link /timestamp:0 /dll /noentry /nodefaultlib /machine:x64 /out:plain_x64.dll plain_x64.obj
+	.text
+	.globl	DllMain
+DllMain:
+	mov	eax, 1
+	ret
+	.globl	Helper
+Helper:
+	xor	eax, eax
+	ret
+	.globl	ReflectiveLoader
+ReflectiveLoader:
+	mov	rax, rcx
+	ret
+
+# The export table, written out here rather than with /export: lld packs its arrays unaligned after
+# the DLL name, and pelite refuses to read them. link.exe aligns them to 4 bytes like this.
+	.section .edata,"dr"
+	.p2align 2
+	.long	0
+	.long	0
+	.short	0, 0
+	.rva	dll_name
+	.long	1
+	.long	1
+	.long	1
+	.rva	functions
+	.rva	names
+	.rva	ordinals
+functions:
+	.rva	Helper
+names:
+	.rva	name0
+ordinals:
+	.short	0
+dll_name:
+	.asciz	"plain_x64.dll"
+name0:
+	.asciz	"Helper"
@@ -0,0 +1,40 @@
+# Source of reflective_arm64.dll, a DLL exporting a stand-in ReflectiveLoader for the reflective loader tests:
+#   llvm-mc -filetype=obj -triple=aarch64-pc-windows-msvc reflective_arm64.s -o reflective_arm64.obj
+#   rust-lld -flavor link /timestamp:0 /dll /noentry /nodefaultlib /machine:arm64 /out:reflective_arm64.dll reflective_arm64.obj
+	.text
+	.globl	Helper
+Helper:
+	mov	w0, #0
+	ret
+	.globl	ReflectiveLoader
+ReflectiveLoader:
+	ret
+
+# The export table, written out here rather than with /export: lld packs its arrays unaligned after
+# the DLL name, and pelite refuses to read them. link.exe aligns them to 4 bytes like this.
+	.section .edata,"dr"
+	.p2align 2
+	.long	0
+	.long	0
+	.short	0, 0
+	.rva	dll_name
+	.long	1
+	.long	2
+	.long	2
+	.rva	functions
+	.rva	names
+	.rva	ordinals
+functions:
+	.rva	Helper
+	.rva	ReflectiveLoader
+names:
+	.rva	name0
+	.rva	name1
+ordinals:
+	.short	0, 1
+dll_name:
+	.asciz	"reflective_arm64.dll"
+name0:
+	.asciz	"Helper"
+name1:
+	.asciz	"ReflectiveLoader"
@@ -0,0 +1,45 @@
+# Source of reflective_x86.dll, a DLL exporting a stand-in ReflectiveLoader for the reflective loader tests:
+#   llvm-mc -filetype=obj -triple=i686-pc-windows-msvc -x86-asm-syntax=intel reflective_x86.s -o reflective_x86.obj
+#   rust-lld -flavor link /timestamp:0 /dll /noentry /nodefaultlib /machine:x86 /safeseh:no /out:reflective_x86.dll reflective_x86.obj
+	.text
+	.globl	_DllMain@12
+_DllMain@12:
+	mov	eax, 1
+	ret	12
+	.globl	_Helper
+_Helper:
+	xor	eax, eax
+	ret
+	.globl	_ReflectiveLoader@4
+_ReflectiveLoader@4:
+	mov	eax, dword ptr [esp + 4]
+	ret	4
+
+# The export table, written out here rather than with /export: lld packs its arrays unaligned after
+# the DLL name, and pelite refuses to read them. link.exe aligns them to 4 bytes like this.
+	.section .edata,"dr"
+	.p2align 2
+	.long	0
+	.long	0
+	.short	0, 0
+	.rva	dll_name
+	.long	1
+	.long	2
+	.long	2
+	.rva	functions
+	.rva	names
+	.rva	ordinals
+functions:
+	.rva	_Helper
+	.rva	_ReflectiveLoader@4
+names:
+	.rva	name0
+	.rva	name1
+ordinals:
+	.short	0, 1
+dll_name:
+	.asciz	"reflective_x86.dll"
+name0:
+	.asciz	"Helper"
+name1:
+	.asciz	"_ReflectiveLoader@4"